@@ -0,0 +1,258 @@
+use crate::backend::{AddrContext, Backend, BackendSelection};
+use crate::{OpKind, OpType, ProcessTask, Service, ServiceRef, ZeroconfError};
+
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures_core::Stream;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// `Protocol` selects the kind of addresses looked up by an [`AddrResolver`].
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Protocol {
+    /// Look up both IPv4 and IPv6 addresses.
+    #[default]
+    Any,
+    /// Look up IPv4 addresses only.
+    IPv4,
+    /// Look up IPv6 addresses only.
+    IPv6,
+}
+
+impl Protocol {
+    /// Check if `addr` is of a kind selected by this protocol.
+    pub fn matches(&self, addr: &IpAddr) -> bool {
+        match self {
+            Protocol::Any => true,
+            Protocol::IPv4 => addr.is_ipv4(),
+            Protocol::IPv6 => addr.is_ipv6(),
+        }
+    }
+}
+
+/// `AddrEvent` is produced by [`AddrEvents`] when an address of a host is
+/// added or removed.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum AddrEvent {
+    /// An address has been found for the host.
+    Added(IpAddr),
+    /// An address is no longer valid for the host.
+    Removed(IpAddr),
+}
+
+/// `AddrResolver` is used to look up the addresses of the host providing a
+/// service that has been resolved with a
+/// [`ServiceResolver`][`crate::ServiceResolver`].
+///
+/// The lookup uses multicast DNS where required, so works for hosts in the
+/// `local` domain, and is limited to the interface of the service.
+///
+/// # Examples
+/// ```
+/// # tokio_test::block_on(async {
+/// let mut browser = async_zeroconf::ServiceBrowserBuilder::new("_http._tcp");
+/// let mut services = browser
+///     .timeout(tokio::time::Duration::from_secs(2))
+///     .browse()?;
+///
+/// while let Some(Ok(v)) = services.recv_resolve().await {
+///     let addresses = async_zeroconf::AddrResolver::new_with_timeout(
+///             tokio::time::Duration::from_secs(2)
+///         )
+///         .addresses(&v)
+///         .await?;
+///     println!("Service = {} @ {:?}", v, addresses);
+/// }
+/// # Ok::<(), async_zeroconf::ZeroconfError>(())
+/// # });
+/// ```
+#[derive(Debug, Default)]
+pub struct AddrResolver {
+    protocol: Protocol,
+    timeout: Option<Duration>,
+    backend: Option<BackendSelection>,
+}
+
+impl AddrResolver {
+    /// Create a new `AddrResolver` looking up all kinds of address with no
+    /// timeout.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Create a new `AddrResolver` with a timeout.
+    pub fn new_with_timeout(timeout: Duration) -> Self {
+        AddrResolver {
+            timeout: Some(timeout),
+            ..Default::default()
+        }
+    }
+
+    /// Set the kind of addresses to look up.
+    pub fn set_protocol(&mut self, protocol: Protocol) -> &mut Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Set the backend used to look up addresses rather than the backend
+    /// associated with each service
+    pub fn set_backend(&mut self, backend: Arc<dyn Backend>) -> &mut Self {
+        self.backend = Some(BackendSelection::new(backend));
+        self
+    }
+
+    /// Set the backend selection used to look up addresses
+    pub(crate) fn select_backend(&mut self, backend: BackendSelection) -> &mut Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// Look up the addresses of the host of the specified [`Service`], which
+    /// must have been resolved so that the host is known.
+    ///
+    /// This returns once the first set of addresses has been found. If no
+    /// address is found before the timeout a [`ZeroconfError::Timeout`] is
+    /// returned.
+    pub async fn addresses(&self, service: &Service) -> Result<Vec<IpAddr>, ZeroconfError> {
+        let mut events = self.events(service)?;
+
+        let mut addresses = Vec::new();
+        while let Some((event, last)) = events.rx.recv().await {
+            match event? {
+                AddrEvent::Added(a) if !addresses.contains(&a) => addresses.push(a),
+                AddrEvent::Added(_) => (),
+                AddrEvent::Removed(a) => addresses.retain(|x| *x != a),
+            }
+            if last && !addresses.is_empty() {
+                return Ok(addresses);
+            }
+        }
+
+        if addresses.is_empty() {
+            Err(ZeroconfError::Timeout(service.clone()))
+        } else {
+            Ok(addresses)
+        }
+    }
+
+    /// Start looking up the addresses of the host of the specified
+    /// [`Service`], producing an [`AddrEvent`] each time an address is added
+    /// or removed. The lookup continues until the timeout or until the
+    /// [`AddrEvents`] is dropped.
+    ///
+    /// # Examples
+    /// ```
+    /// # tokio_test::block_on(async {
+    /// let mut browser = async_zeroconf::ServiceBrowserBuilder::new("_http._tcp");
+    /// let mut services = browser
+    ///     .timeout(tokio::time::Duration::from_secs(2))
+    ///     .browse()?;
+    ///
+    /// if let Some(Ok(v)) = services.recv_resolve().await {
+    ///     let mut events = async_zeroconf::AddrResolver::new_with_timeout(
+    ///             tokio::time::Duration::from_secs(2)
+    ///         )
+    ///         .events(&v)?;
+    ///     while let Some(Ok(event)) = events.recv().await {
+    ///         println!("Event = {:?}", event);
+    ///     }
+    /// }
+    /// # Ok::<(), async_zeroconf::ZeroconfError>(())
+    /// # });
+    /// ```
+    pub fn events(&self, service: &Service) -> Result<AddrEvents, ZeroconfError> {
+        let (events, task) = self.events_task(service)?;
+
+        tokio::spawn(task);
+
+        Ok(events)
+    }
+
+    /// Start looking up the addresses of the host of the specified
+    /// [`Service`]. The returned [`ProcessTask`] future must be awaited to
+    /// process events associated with the lookup.
+    ///
+    /// # Note
+    /// This method is intended if more control is needed over how the task
+    /// is spawned. [`AddrResolver::events`] will automatically spawn the
+    /// task.
+    pub fn events_task(
+        &self,
+        service: &Service,
+    ) -> Result<(AddrEvents, impl ProcessTask), ZeroconfError> {
+        let hostname = hostname(service)?;
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        let backend = self.backend.as_ref().unwrap_or_else(|| service.backend());
+        let operation = backend.get()?.get_addr_info(
+            service.interface(),
+            self.protocol,
+            &hostname,
+            AddrContext::new(tx),
+        )?;
+
+        let (delegate, task) = ServiceRef::from_operation(
+            operation,
+            OpType::new(service.service_type(), OpKind::GetAddrInfo),
+            self.timeout,
+        );
+
+        log::debug!("Created AddrEvents for '{}'", hostname);
+        Ok((AddrEvents { rx, delegate }, task))
+    }
+}
+
+// Get the full domain name of the host of a resolved service
+pub(crate) fn hostname(service: &Service) -> Result<String, ZeroconfError> {
+    let host = service
+        .host()
+        .as_deref()
+        .ok_or_else(|| ZeroconfError::NotResolved(service.clone()))?;
+
+    // Hosts within the domain of the service are relative to it, and any
+    // others are absolute names ending with a dot
+    if host.ends_with('.') {
+        Ok(host.to_string())
+    } else {
+        let domain = service.domain().as_deref().unwrap_or("local.");
+        Ok(format!("{}.{}", host, domain))
+    }
+}
+
+/// Struct used to get the address events of an [`AddrResolver`] lookup, which
+/// can be used as a stream or with [`AddrEvents::recv`].
+#[derive(Debug)]
+pub struct AddrEvents {
+    // Channel to receive events
+    rx: mpsc::UnboundedReceiver<(Result<AddrEvent, ZeroconfError>, bool)>,
+    // Reference to the operation performing the lookup
+    delegate: ServiceRef,
+}
+
+impl AddrEvents {
+    /// Receive an event from the lookup.
+    ///
+    /// A response of `None` indicates that the lookup has finished, for
+    /// example due to a timeout or error.
+    pub async fn recv(&mut self) -> Option<Result<AddrEvent, ZeroconfError>> {
+        self.rx.recv().await.map(|e| e.0)
+    }
+
+    /// Get the [`OpType`] of the operation performing the lookup.
+    pub fn op_type(&self) -> &OpType {
+        self.delegate.op_type()
+    }
+}
+
+impl Stream for AddrEvents {
+    type Item = Result<AddrEvent, ZeroconfError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<<Self as futures_core::Stream>::Item>> {
+        self.rx.poll_recv(cx).map(|e| e.map(|e| e.0))
+    }
+}
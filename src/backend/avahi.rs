@@ -0,0 +1,1095 @@
+// Backend using the D-Bus API of avahi-daemon
+
+use crate::backend::task::{RegistrationRequest, TaskOperation};
+use crate::backend::{
+    AddrContext, Backend, BackendOperation, BrowseContext, DomainContext, PublishContext,
+    RecordContext, ResolveContext, ResolverInformation,
+};
+use crate::service_type_browser::SERVICE_TYPE_QUERY;
+use crate::{
+    AddrEvent, BonjourError, Domain, DomainEvent, DomainKind, Interface, OpKind, OpType, Protocol,
+    RecordData, RecordEvent, RecordQuery, RecordType, ResourceRecord, Service, ServiceInstanceName,
+    TxtRecord, ZeroconfError,
+};
+
+use futures::stream::StreamExt;
+use std::future::Future;
+use std::net::IpAddr;
+use tokio::sync::mpsc;
+use zbus::zvariant::OwnedObjectPath;
+use zbus::{Connection, Proxy};
+
+const AVAHI_SERVICE: &str = "org.freedesktop.Avahi";
+const DBUS_SERVICE: &str = "org.freedesktop.DBus";
+const DBUS_PATH: &str = "/org/freedesktop/DBus";
+// Error from the bus when a method is called on a name that has no owner
+const SERVICE_UNKNOWN: &str = "org.freedesktop.DBus.Error.ServiceUnknown";
+const SERVER_INTERFACE: &str = "org.freedesktop.Avahi.Server";
+const ENTRY_GROUP_INTERFACE: &str = "org.freedesktop.Avahi.EntryGroup";
+const SERVICE_BROWSER_INTERFACE: &str = "org.freedesktop.Avahi.ServiceBrowser";
+const SERVICE_TYPE_BROWSER_INTERFACE: &str = "org.freedesktop.Avahi.ServiceTypeBrowser";
+const SERVICE_RESOLVER_INTERFACE: &str = "org.freedesktop.Avahi.ServiceResolver";
+const HOST_NAME_RESOLVER_INTERFACE: &str = "org.freedesktop.Avahi.HostNameResolver";
+const DOMAIN_BROWSER_INTERFACE: &str = "org.freedesktop.Avahi.DomainBrowser";
+const RECORD_BROWSER_INTERFACE: &str = "org.freedesktop.Avahi.RecordBrowser";
+
+// AVAHI_IF_UNSPEC and AVAHI_PROTO_UNSPEC
+const UNSPEC: i32 = -1;
+// AVAHI_PROTO_INET and AVAHI_PROTO_INET6
+const PROTO_INET: i32 = 0;
+const PROTO_INET6: i32 = 1;
+
+// States of an entry group
+const ENTRY_GROUP_ESTABLISHED: i32 = 2;
+const ENTRY_GROUP_COLLISION: i32 = 3;
+const ENTRY_GROUP_FAILURE: i32 = 4;
+
+// AVAHI_DEFAULT_TTL, used for records added with a TTL of zero
+const DEFAULT_TTL: u32 = 4500;
+// AVAHI_DEFAULT_TTL_HOST_NAME, used for unique records registered without a
+// TTL
+const DEFAULT_HOST_TTL: u32 = 120;
+// AVAHI_PUBLISH_UNIQUE
+const PUBLISH_UNIQUE: u32 = 1;
+
+// AVAHI_DOMAIN_BROWSER_BROWSE and AVAHI_DOMAIN_BROWSER_REGISTER
+const DOMAIN_BROWSER_BROWSE: i32 = 0;
+const DOMAIN_BROWSER_REGISTER: i32 = 2;
+
+// Body of the ItemNew and ItemRemove signals of a service browser
+type BrowseItem = (i32, i32, String, String, String, u32);
+// Body of the ItemNew and ItemRemove signals of a service type browser
+type ServiceTypeItem = (i32, i32, String, String, u32);
+// Body of the ItemNew and ItemRemove signals of a domain browser
+type DomainItem = (i32, i32, String, u32);
+// Body of the ItemNew and ItemRemove signals of a record browser
+type RecordItem = (i32, i32, String, u16, u16, Vec<u8>, u32);
+// Body of the Found signal of a host name resolver
+type HostNameItem = (i32, i32, String, i32, String, u32);
+// Arguments of the AddRecord method of an entry group
+type RecordArgs<'a> = (i32, i32, u32, &'a str, u16, u16, u32, &'a [u8]);
+// Body of the Found signal of a service resolver
+type ResolvedItem = (
+    i32,
+    i32,
+    String,
+    String,
+    String,
+    String,
+    i32,
+    String,
+    u16,
+    Vec<Vec<u8>>,
+    u32,
+);
+
+/// `AvahiBackend` implements the `ZeroConf` operations using the D-Bus API
+/// of `avahi-daemon`, rather than through the Bonjour compatibility layer of
+/// Avahi.
+///
+/// Each operation uses its own connection to the bus, so any objects created
+/// in `avahi-daemon` are freed when the operation is dropped.
+///
+/// This is the default backend if the `avahi` feature is enabled and the
+/// `bonjour` feature is not.
+///
+/// # Examples
+/// ```
+/// # tokio_test::block_on(async {
+/// use std::sync::Arc;
+///
+/// let service_ref = async_zeroconf::Service::new("Server", "_http._tcp", 80)
+///                       .set_backend(Arc::new(async_zeroconf::backend::AvahiBackend::new()))
+///                       .publish().await?;
+/// # Ok::<(), async_zeroconf::ZeroconfError>(())
+/// # });
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct AvahiBackend {
+    address: Option<String>,
+}
+
+impl AvahiBackend {
+    /// Create a new `AvahiBackend` using the system bus.
+    pub fn new() -> Self {
+        AvahiBackend { address: None }
+    }
+
+    /// Create a new `AvahiBackend` using the bus at `address` (e.g.
+    /// `"unix:path=/tmp/bus"`) rather than the system bus.
+    pub fn with_address(address: &str) -> Self {
+        AvahiBackend {
+            address: Some(address.to_string()),
+        }
+    }
+
+    async fn connect(address: Option<String>) -> Result<Connection, ZeroconfError> {
+        let conn = match address {
+            Some(a) => {
+                zbus::connection::Builder::address(a.as_str())?
+                    .build()
+                    .await?
+            }
+            None => Connection::system().await?,
+        };
+        Ok(conn)
+    }
+}
+
+#[cfg(test)]
+impl AvahiBackend {
+    // Check that avahi-daemon can be reached, so tests can be skipped if not
+    pub(crate) async fn is_available(&self) -> bool {
+        let version = async {
+            let conn = AvahiBackend::connect(self.address.clone()).await?;
+            let version: String = server(&conn).await?.call("GetVersionString", &()).await?;
+            Ok::<_, ZeroconfError>(version)
+        };
+        match version.await {
+            Ok(v) => {
+                log::debug!("Testing against {}", v);
+                true
+            }
+            Err(e) => {
+                log::warn!("avahi-daemon not available - {}", e);
+                false
+            }
+        }
+    }
+}
+
+impl Backend for AvahiBackend {
+    fn register(
+        &self,
+        service: &Service,
+        context: PublishContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        let registration = Registration {
+            name: service.name().to_string(),
+            service_type: service.service_type().to_string(),
+            subtypes: service.subtypes().to_vec(),
+            port: service.port(),
+            interface: interface_index(service.interface()),
+            domain: service.domain().clone().unwrap_or_default(),
+            host: service.host().clone().unwrap_or_default(),
+            txt: service.txt().entries().collect(),
+            allow_rename: service.allow_rename(),
+            records: Vec::new(),
+            next_record: 0,
+        };
+        let address = self.address.clone();
+        let (requests_tx, requests) = mpsc::unbounded_channel();
+
+        Ok(TaskOperation::start_registration(
+            async move {
+                let conn = AvahiBackend::connect(address).await?;
+                until_stopped(&conn, registration.run(&conn, context, requests)).await
+            },
+            OpType::new(service.service_type(), OpKind::Publish),
+            requests_tx,
+        ))
+    }
+
+    fn browse(
+        &self,
+        interface: &Interface,
+        service_type: &str,
+        domain: Option<&str>,
+        context: BrowseContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        let interface = interface_index(interface);
+        let args = (
+            interface,
+            UNSPEC,
+            service_type.to_string(),
+            domain.unwrap_or_default().to_string(),
+            0u32,
+        );
+        let address = self.address.clone();
+
+        Ok(TaskOperation::start(
+            async move {
+                let conn = AvahiBackend::connect(address).await?;
+                until_stopped(&conn, browse(&conn, args, context)).await
+            },
+            OpType::new(service_type, OpKind::Browse),
+        ))
+    }
+
+    fn resolve(
+        &self,
+        service: &Service,
+        context: ResolveContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        let domain = service
+            .domain()
+            .as_deref()
+            .ok_or_else(|| ZeroconfError::NotFromBrowser(service.clone()))?;
+        let args = (
+            interface_index(service.interface()),
+            UNSPEC,
+            service.name().to_string(),
+            service.service_type().trim_end_matches('.').to_string(),
+            domain.trim_end_matches('.').to_string(),
+            UNSPEC,
+            0u32,
+        );
+        let interface = *service.interface();
+        let address = self.address.clone();
+
+        Ok(TaskOperation::start(
+            async move {
+                let conn = AvahiBackend::connect(address).await?;
+                until_stopped(&conn, resolve(&conn, args, interface, context)).await
+            },
+            OpType::new(service.service_type(), OpKind::Resolve),
+        ))
+    }
+
+    fn get_addr_info(
+        &self,
+        interface: &Interface,
+        protocol: Protocol,
+        hostname: &str,
+        context: AddrContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        let aprotocol = match protocol {
+            Protocol::Any => UNSPEC,
+            Protocol::IPv4 => PROTO_INET,
+            Protocol::IPv6 => PROTO_INET6,
+        };
+        let args = (
+            interface_index(interface),
+            UNSPEC,
+            hostname.trim_end_matches('.').to_string(),
+            aprotocol,
+            0u32,
+        );
+        let address = self.address.clone();
+
+        Ok(TaskOperation::start(
+            async move {
+                let conn = AvahiBackend::connect(address).await?;
+                until_stopped(&conn, get_addr_info(&conn, args, context)).await
+            },
+            OpType::new(hostname, OpKind::GetAddrInfo),
+        ))
+    }
+
+    fn enumerate_domains(
+        &self,
+        interface: &Interface,
+        kind: DomainKind,
+        context: DomainContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        let btype = match kind {
+            DomainKind::Browse => DOMAIN_BROWSER_BROWSE,
+            DomainKind::Registration => DOMAIN_BROWSER_REGISTER,
+        };
+        let args = (
+            interface_index(interface),
+            UNSPEC,
+            String::new(),
+            btype,
+            0u32,
+        );
+        let address = self.address.clone();
+
+        Ok(TaskOperation::start(
+            async move {
+                let conn = AvahiBackend::connect(address).await?;
+                until_stopped(&conn, enumerate_domains(&conn, args, context)).await
+            },
+            OpType::new(&kind.to_string(), OpKind::EnumerateDomains),
+        ))
+    }
+
+    fn query_record(
+        &self,
+        interface: &Interface,
+        fullname: &str,
+        rrtype: RecordType,
+        rrclass: u16,
+        context: RecordContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        let args = (
+            interface_index(interface),
+            UNSPEC,
+            fullname.to_string(),
+            rrclass,
+            u16::from(rrtype),
+            0u32,
+        );
+        let address = self.address.clone();
+
+        Ok(TaskOperation::start(
+            async move {
+                let conn = AvahiBackend::connect(address).await?;
+                until_stopped(&conn, query_record(&conn, args, context)).await
+            },
+            OpType::new(fullname, OpKind::QueryRecord),
+        ))
+    }
+
+    fn register_records(
+        &self,
+        interface: &Interface,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        let index = interface_index(interface);
+        let address = self.address.clone();
+        let (requests_tx, requests) = mpsc::unbounded_channel();
+
+        Ok(TaskOperation::start_registration(
+            async move {
+                let conn = AvahiBackend::connect(address).await?;
+                until_stopped(&conn, register_records(&conn, index, requests)).await
+            },
+            OpType::new(&interface.to_string(), OpKind::RegisterRecord),
+            requests_tx,
+        ))
+    }
+}
+
+fn interface_index(interface: &Interface) -> i32 {
+    match interface {
+        Interface::Unspecified => UNSPEC,
+        Interface::Interface(id) => *id as i32,
+    }
+}
+
+// Convert an error string from avahi-daemon to the closest error
+fn avahi_error(error: &str) -> BonjourError {
+    log::debug!("Avahi error '{}'", error);
+    match error {
+        "Local name collision" | "Name collision" => BonjourError::NameConflict,
+        "Not found" => BonjourError::NoSuchName,
+        "Not supported" => BonjourError::Unsupported,
+        "Not permitted" | "Access denied" => BonjourError::Refused,
+        "No memory" => BonjourError::NoMemory,
+        "Bad state" => BonjourError::BadState,
+        "Invalid flags" => BonjourError::BadFlags,
+        "Invalid interface index" => BonjourError::BadInterfaceIndex,
+        e if e.starts_with("Invalid") => BonjourError::BadParam,
+        _ => BonjourError::Unknown,
+    }
+}
+
+// Run the operation `future` on `conn` until it completes or avahi-daemon
+// leaves the bus, as it does when it stops
+async fn until_stopped<F>(conn: &Connection, future: F) -> Result<(), ZeroconfError>
+where
+    F: Future<Output = Result<(), ZeroconfError>>,
+{
+    let dbus = Proxy::new(conn, DBUS_SERVICE, DBUS_PATH, DBUS_SERVICE).await?;
+    let mut owners = dbus
+        .receive_signal_with_args("NameOwnerChanged", &[(0, AVAHI_SERVICE)])
+        .await?;
+    let stopped = async move {
+        while let Some(msg) = owners.next().await {
+            let (_name, _old_owner, new_owner): (String, String, String) =
+                msg.body().deserialize()?;
+            if new_owner.is_empty() {
+                break;
+            }
+        }
+        Ok::<_, ZeroconfError>(())
+    };
+
+    let result = tokio::select! {
+        r = future => r,
+        r = stopped => r.and(Err(BonjourError::ServiceNotRunning.into())),
+    };
+    match result {
+        Err(ZeroconfError::DBus(e)) if is_service_unknown(e.as_ref()) => {
+            Err(BonjourError::ServiceNotRunning.into())
+        }
+        r => r,
+    }
+}
+
+// Check for the error returned when calling avahi-daemon while it's stopped
+fn is_service_unknown(error: &(dyn std::error::Error + Send + Sync + 'static)) -> bool {
+    matches!(
+        error.downcast_ref::<zbus::Error>(),
+        Some(zbus::Error::MethodError(name, _, _)) if name.as_str() == SERVICE_UNKNOWN
+    )
+}
+
+async fn server(conn: &Connection) -> Result<Proxy<'static>, ZeroconfError> {
+    Ok(Proxy::new(conn, AVAHI_SERVICE, "/", SERVER_INTERFACE).await?)
+}
+
+// Information required to add a service to an entry group
+#[derive(Debug)]
+struct Registration {
+    name: String,
+    service_type: String,
+    subtypes: Vec<String>,
+    port: u16,
+    interface: i32,
+    domain: String,
+    host: String,
+    txt: Vec<Vec<u8>>,
+    allow_rename: bool,
+    records: Vec<AddedRecord>,
+    next_record: u64,
+}
+
+// A record added to a service, in its own entry group so that adding or
+// removing it doesn't withdraw the service. The group is `None` while the
+// service is being renamed.
+#[derive(Debug)]
+struct AddedRecord {
+    id: u64,
+    rrtype: u16,
+    rdata: Vec<u8>,
+    ttl: u32,
+    group: Option<Proxy<'static>>,
+}
+
+impl Registration {
+    async fn run(
+        mut self,
+        conn: &Connection,
+        context: PublishContext,
+        mut requests: mpsc::UnboundedReceiver<RegistrationRequest>,
+    ) -> Result<(), ZeroconfError> {
+        let server = server(conn).await?;
+        if self.name.is_empty() {
+            self.name = server.call("GetHostName", &()).await?;
+        }
+        let domain: String = if self.domain.is_empty() {
+            server.call("GetDomainName", &()).await?
+        } else {
+            self.domain.clone()
+        };
+        let service_type = format!("{}.", self.service_type.trim_end_matches('.'));
+        let domain = format!("{}.", domain.trim_end_matches('.'));
+
+        let path: OwnedObjectPath = server.call("EntryGroupNew", &()).await?;
+        let group = Proxy::new(conn, AVAHI_SERVICE, path, ENTRY_GROUP_INTERFACE).await?;
+        let mut states = group.receive_signal("StateChanged").await?;
+
+        let mut name = self.name.clone();
+        let mut rename_count = 1;
+        loop {
+            log::trace!("Call EntryGroup.AddService ({})", name);
+            let args = (
+                self.interface,
+                UNSPEC,
+                0u32,
+                name.as_str(),
+                self.service_type.as_str(),
+                self.domain.as_str(),
+                self.host.as_str(),
+                self.port,
+                &self.txt,
+            );
+            group.call::<_, _, ()>("AddService", &args).await?;
+            for subtype in &self.subtypes {
+                log::trace!("Call EntryGroup.AddServiceSubtype ({})", subtype);
+                let subtype = format!("{}._sub.{}", subtype, self.service_type);
+                let args = (
+                    self.interface,
+                    UNSPEC,
+                    0u32,
+                    name.as_str(),
+                    self.service_type.as_str(),
+                    self.domain.as_str(),
+                    subtype.as_str(),
+                );
+                group.call::<_, _, ()>("AddServiceSubtype", &args).await?;
+            }
+            group.call::<_, _, ()>("Commit", &()).await?;
+            let fullname = ServiceInstanceName::new(&name, &service_type, &domain).to_string();
+
+            // Wait for the entry group to be established or to fail, and for
+            // any requests to change the registration
+            loop {
+                let msg = tokio::select! {
+                    msg = states.next() => msg.ok_or(ZeroconfError::Dropped)?,
+                    Some(request) = requests.recv() => {
+                        self.handle(conn, &server, &group, &name, &fullname, request).await;
+                        continue;
+                    }
+                };
+                let (state, error): (i32, String) = msg.body().deserialize()?;
+                match state {
+                    ENTRY_GROUP_ESTABLISHED => {
+                        log::debug!("EntryGroup established ({})", name);
+                        self.readd_records(conn, &server, &fullname).await;
+                        context.send_registered(&name, &service_type, &domain);
+                    }
+                    ENTRY_GROUP_COLLISION if self.allow_rename => {
+                        rename_count += 1;
+                        name = format!("{} ({})", self.name, rename_count);
+                        log::debug!("Name conflict, renaming to '{}'", name);
+                        group.call::<_, _, ()>("Reset", &()).await?;
+                        // Records are added again under the new name once the
+                        // service is established
+                        for record in &mut self.records {
+                            if let Some(group) = record.group.take() {
+                                free_group(&group).await;
+                            }
+                        }
+                        break;
+                    }
+                    ENTRY_GROUP_COLLISION => {
+                        context.send(Err(BonjourError::NameConflict));
+                        return Ok(());
+                    }
+                    ENTRY_GROUP_FAILURE => {
+                        context.send(Err(avahi_error(&error)));
+                        return Ok(());
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    // Handle a request to change the registration
+    async fn handle(
+        &mut self,
+        conn: &Connection,
+        server: &Proxy<'_>,
+        group: &Proxy<'_>,
+        name: &str,
+        fullname: &str,
+        request: RegistrationRequest,
+    ) {
+        match request {
+            RegistrationRequest::UpdateTxt(txt, tx) => {
+                let result = self.update_txt(group, name, txt).await;
+                if tx.send(result).is_err() {
+                    log::warn!("Failed to send TXT update result, receiver dropped");
+                }
+            }
+            RegistrationRequest::AddRecord(rrtype, rdata, ttl, tx) => {
+                let id = self.next_record;
+                let mut record = AddedRecord {
+                    id,
+                    rrtype: rrtype.into(),
+                    rdata,
+                    ttl: if ttl == 0 { DEFAULT_TTL } else { ttl },
+                    group: None,
+                };
+                // Only report the record as added once Avahi has accepted it
+                let result = match self.add_record(conn, server, fullname, &record).await {
+                    Ok(group) => {
+                        record.group = Some(group);
+                        self.records.push(record);
+                        self.next_record += 1;
+                        Ok(id)
+                    }
+                    Err(e) => Err(e),
+                };
+                if tx.send(result).is_err() {
+                    log::warn!("Failed to send added record, receiver dropped");
+                }
+            }
+            RegistrationRequest::RemoveRecord(id) => {
+                if let Some(i) = self.records.iter().position(|r| r.id == id) {
+                    if let Some(group) = self.records.remove(i).group {
+                        free_group(&group).await;
+                    }
+                }
+            }
+            RegistrationRequest::RegisterRecord(_, _, tx) => {
+                if tx.send(Err(BonjourError::Unsupported.into())).is_err() {
+                    log::warn!("Failed to send registered record, receiver dropped");
+                }
+            }
+        }
+    }
+
+    // Add a record of the service to a new entry group
+    async fn add_record(
+        &self,
+        conn: &Connection,
+        server: &Proxy<'_>,
+        fullname: &str,
+        record: &AddedRecord,
+    ) -> Result<Proxy<'static>, ZeroconfError> {
+        let args = (
+            self.interface,
+            UNSPEC,
+            0u32,
+            fullname,
+            RecordQuery::CLASS_IN,
+            record.rrtype,
+            record.ttl,
+            record.rdata.as_slice(),
+        );
+        add_record_group(conn, server, args).await
+    }
+
+    // Add any records removed when the service was renamed under the new name
+    async fn readd_records(&mut self, conn: &Connection, server: &Proxy<'_>, fullname: &str) {
+        for i in 0..self.records.len() {
+            if self.records[i].group.is_some() {
+                continue;
+            }
+            match self
+                .add_record(conn, server, fullname, &self.records[i])
+                .await
+            {
+                Ok(group) => self.records[i].group = Some(group),
+                Err(e) => log::warn!("Failed to add record to '{}' - {}", fullname, e),
+            }
+        }
+    }
+
+    // Replace the TXT record of the service in the entry group
+    async fn update_txt(
+        &mut self,
+        group: &Proxy<'_>,
+        name: &str,
+        txt: TxtRecord,
+    ) -> Result<(), ZeroconfError> {
+        let entries: Vec<Vec<u8>> = txt.entries().collect();
+        log::trace!("Call EntryGroup.UpdateServiceTxt ({})", name);
+        let args = (
+            self.interface,
+            UNSPEC,
+            0u32,
+            name,
+            self.service_type.as_str(),
+            self.domain.as_str(),
+            &entries,
+        );
+        group.call::<_, _, ()>("UpdateServiceTxt", &args).await?;
+        // Use the new record if the service is added again after a rename
+        self.txt = entries;
+        Ok(())
+    }
+}
+
+async fn browse(
+    conn: &Connection,
+    args: (i32, i32, String, String, u32),
+    context: BrowseContext,
+) -> Result<(), ZeroconfError> {
+    let server = server(conn).await?;
+    // Avahi doesn't answer the meta-query with a service browser, so a
+    // service type browser is used instead
+    let types = args.2 == SERVICE_TYPE_QUERY;
+    let browser = if types {
+        let (interface, protocol, _, domain, flags) = args;
+        let args = (interface, protocol, domain, flags);
+        let path: OwnedObjectPath = server.call("ServiceTypeBrowserPrepare", &args).await?;
+        Proxy::new(conn, AVAHI_SERVICE, path, SERVICE_TYPE_BROWSER_INTERFACE).await?
+    } else {
+        let path: OwnedObjectPath = server.call("ServiceBrowserPrepare", &args).await?;
+        Proxy::new(conn, AVAHI_SERVICE, path, SERVICE_BROWSER_INTERFACE).await?
+    };
+    let mut signals = browser.receive_all_signals().await?;
+    browser.call::<_, _, ()>("Start", &()).await?;
+
+    // Avahi doesn't indicate if more services are expected with each service
+    // so hold the latest service until the next one or 'AllForNow'
+    let mut pending: Option<Service> = None;
+    // Avahi reports services for each protocol, only report once per interface
+    let mut found: Vec<(i32, String, String, String, usize)> = Vec::new();
+
+    while let Some(msg) = signals.next().await {
+        let header = msg.header();
+        let member = match header.member() {
+            Some(m) => m.as_str(),
+            None => continue,
+        };
+        match member {
+            "ItemNew" | "ItemRemove" => {
+                let (interface, name, regtype, domain) = if types {
+                    // Report service types in the same form as
+                    // `DNSServiceBrowse`, with the first label as the name
+                    let (interface, _protocol, service_type, domain, _flags): ServiceTypeItem =
+                        msg.body().deserialize()?;
+                    let (name, regtype) = service_type.split_once('.').unwrap_or_default();
+                    (interface, name.to_string(), regtype.to_string(), domain)
+                } else {
+                    let (interface, _protocol, name, regtype, domain, _flags): BrowseItem =
+                        msg.body().deserialize()?;
+                    (interface, name, regtype, domain)
+                };
+                let position = found.iter().position(|f| {
+                    f.0 == interface && f.1 == name && f.2 == regtype && f.3 == domain
+                });
+                let mut service = Service::new(&name, &format!("{}.", regtype), 0);
+                service
+                    .set_interface(Interface::Interface(interface as u32))
+                    .set_domain(format!("{}.", domain));
+
+                if member == "ItemRemove" {
+                    if let Some(i) = position {
+                        found[i].4 -= 1;
+                        if found[i].4 == 0 {
+                            log::debug!("ServiceBrowse Remove {}", name);
+                            found.remove(i);
+                            // Keep events in order by sending any held service first
+                            if let Some(s) = pending.take() {
+                                context.send(Ok(s), false);
+                            }
+                            context.send_removed(service, true);
+                        }
+                    }
+                } else if let Some(i) = position {
+                    found[i].4 += 1;
+                } else {
+                    log::debug!("ServiceBrowse ItemNew ({}:{}:{})", name, regtype, domain);
+                    found.push((interface, name, regtype, domain, 1));
+
+                    if let Some(s) = pending.replace(service) {
+                        context.send(Ok(s), false);
+                    }
+                }
+            }
+            "AllForNow" => {
+                log::trace!("End of services (for now)");
+                if let Some(s) = pending.take() {
+                    context.send(Ok(s), true);
+                }
+            }
+            "Failure" => {
+                let error: String = msg.body().deserialize()?;
+                context.send(Err(avahi_error(&error).into()), false);
+                return Ok(());
+            }
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+async fn resolve(
+    conn: &Connection,
+    args: (i32, i32, String, String, String, i32, u32),
+    interface: Interface,
+    context: ResolveContext,
+) -> Result<(), ZeroconfError> {
+    let server = server(conn).await?;
+    let path: OwnedObjectPath = server.call("ServiceResolverPrepare", &args).await?;
+    let resolver = Proxy::new(conn, AVAHI_SERVICE, path, SERVICE_RESOLVER_INTERFACE).await?;
+    let mut signals = resolver.receive_all_signals().await?;
+    resolver.call::<_, _, ()>("Start", &()).await?;
+
+    while let Some(msg) = signals.next().await {
+        let header = msg.header();
+        match header.member().map(|m| m.as_str()) {
+            Some("Found") => {
+                let (_, _, name, regtype, domain, host, _, _, port, txt, _): ResolvedItem =
+                    msg.body().deserialize()?;
+
+                log::debug!("ServiceResolve Found ({}:{}:{})", name, host, port);
+                let fullname = ServiceInstanceName::new(&name, &regtype, &domain);
+                context.send(Ok(ResolverInformation::new(
+                    interface,
+                    &fullname.to_string(),
+                    &format!("{}.", host),
+                    port,
+                    TxtRecord::from_entries(txt.iter().map(|e| e.as_slice())),
+                )));
+                return Ok(());
+            }
+            Some("Failure") => {
+                let error: String = msg.body().deserialize()?;
+                context.send(Err(avahi_error(&error).into()));
+                return Ok(());
+            }
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+async fn get_addr_info(
+    conn: &Connection,
+    args: (i32, i32, String, i32, u32),
+    context: AddrContext,
+) -> Result<(), ZeroconfError> {
+    let server = server(conn).await?;
+    let path: OwnedObjectPath = server.call("HostNameResolverPrepare", &args).await?;
+    let resolver = Proxy::new(conn, AVAHI_SERVICE, path, HOST_NAME_RESOLVER_INTERFACE).await?;
+    let mut signals = resolver.receive_all_signals().await?;
+    resolver.call::<_, _, ()>("Start", &()).await?;
+
+    while let Some(msg) = signals.next().await {
+        let header = msg.header();
+        match header.member().map(|m| m.as_str()) {
+            Some("Found") => {
+                let (_, _, name, _, address, _): HostNameItem = msg.body().deserialize()?;
+                // Link-local addresses include the scope, which isn't part of
+                // the address itself
+                let address = address.split('%').next().unwrap_or_default();
+                match address.parse::<IpAddr>() {
+                    Ok(a) => {
+                        log::debug!("HostNameResolver Found ({}:{})", name, a);
+                        context.send(Ok(AddrEvent::Added(a)), true);
+                    }
+                    Err(e) => log::warn!("Invalid address '{}' for {}: {}", address, name, e),
+                }
+            }
+            Some("Failure") => {
+                let error: String = msg.body().deserialize()?;
+                context.send(Err(avahi_error(&error).into()), false);
+                return Ok(());
+            }
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+async fn enumerate_domains(
+    conn: &Connection,
+    args: (i32, i32, String, i32, u32),
+    context: DomainContext,
+) -> Result<(), ZeroconfError> {
+    // The domain of the server is the default, and isn't reported by the
+    // domain browser itself
+    let server = server(conn).await?;
+    let default: String = server.call("GetDomainName", &()).await?;
+    let default = format!("{}.", default.trim_end_matches('.'));
+    context.send(Ok(DomainEvent::Added(Domain::new(&default, true))));
+
+    let path: OwnedObjectPath = server.call("DomainBrowserPrepare", &args).await?;
+    let browser = Proxy::new(conn, AVAHI_SERVICE, path, DOMAIN_BROWSER_INTERFACE).await?;
+    let mut signals = browser.receive_all_signals().await?;
+    browser.call::<_, _, ()>("Start", &()).await?;
+
+    // Avahi reports domains for each interface and protocol, only report
+    // each domain once
+    let mut found: Vec<(String, usize)> = vec![(default.clone(), 1)];
+
+    while let Some(msg) = signals.next().await {
+        let header = msg.header();
+        let member = match header.member() {
+            Some(m) => m.as_str(),
+            None => continue,
+        };
+        match member {
+            "ItemNew" | "ItemRemove" => {
+                let (_interface, _protocol, domain, _flags): DomainItem =
+                    msg.body().deserialize()?;
+                let domain = format!("{}.", domain.trim_end_matches('.'));
+                let position = found.iter().position(|f| f.0 == domain);
+
+                if member == "ItemRemove" {
+                    if let Some(i) = position {
+                        found[i].1 -= 1;
+                        if found[i].1 == 0 {
+                            log::debug!("DomainBrowser ItemRemove ({})", domain);
+                            found.remove(i);
+                            let removed = Domain::new(&domain, domain == default);
+                            context.send(Ok(DomainEvent::Removed(removed)));
+                        }
+                    }
+                } else if let Some(i) = position {
+                    found[i].1 += 1;
+                } else {
+                    log::debug!("DomainBrowser ItemNew ({})", domain);
+                    found.push((domain.clone(), 1));
+                    context.send(Ok(DomainEvent::Added(Domain::new(&domain, false))));
+                }
+            }
+            "Failure" => {
+                let error: String = msg.body().deserialize()?;
+                context.send(Err(avahi_error(&error).into()));
+                return Ok(());
+            }
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+async fn query_record(
+    conn: &Connection,
+    args: (i32, i32, String, u16, u16, u32),
+    context: RecordContext,
+) -> Result<(), ZeroconfError> {
+    let server = server(conn).await?;
+    let path: OwnedObjectPath = server.call("RecordBrowserPrepare", &args).await?;
+    let browser = Proxy::new(conn, AVAHI_SERVICE, path, RECORD_BROWSER_INTERFACE).await?;
+    let mut signals = browser.receive_all_signals().await?;
+    browser.call::<_, _, ()>("Start", &()).await?;
+
+    // Avahi reports records for each interface and protocol, only report
+    // each record once
+    let mut found: Vec<(ResourceRecord, usize)> = Vec::new();
+
+    while let Some(msg) = signals.next().await {
+        let header = msg.header();
+        let member = match header.member() {
+            Some(m) => m.as_str(),
+            None => continue,
+        };
+        match member {
+            "ItemNew" | "ItemRemove" => {
+                let (_interface, _protocol, name, rrclass, rrtype, rdata, _flags): RecordItem =
+                    msg.body().deserialize()?;
+                // Avahi doesn't report the TTL of records
+                let data = RecordData::from_rdata(rrtype, &rdata);
+                let record = ResourceRecord::new(
+                    &format!("{}.", name.trim_end_matches('.')),
+                    rrclass,
+                    None,
+                    data,
+                );
+                let position = found.iter().position(|f| f.0 == record);
+
+                if member == "ItemRemove" {
+                    if let Some(i) = position {
+                        found[i].1 -= 1;
+                        if found[i].1 == 0 {
+                            log::debug!("RecordBrowser ItemRemove ({})", record);
+                            found.remove(i);
+                            context.send(Ok(RecordEvent::Removed(record)));
+                        }
+                    }
+                } else if let Some(i) = position {
+                    found[i].1 += 1;
+                } else {
+                    log::debug!("RecordBrowser ItemNew ({})", record);
+                    found.push((record.clone(), 1));
+                    context.send(Ok(RecordEvent::Added(record)));
+                }
+            }
+            "Failure" => {
+                let error: String = msg.body().deserialize()?;
+                context.send(Err(avahi_error(&error).into()));
+                return Ok(());
+            }
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+// Register each record in its own entry group, so that a conflict only affects
+// that record and the record can be removed by freeing its group
+async fn register_records(
+    conn: &Connection,
+    interface: i32,
+    mut requests: mpsc::UnboundedReceiver<RegistrationRequest>,
+) -> Result<(), ZeroconfError> {
+    let server = server(conn).await?;
+    let mut groups: Vec<(u64, Proxy<'static>)> = Vec::new();
+    let mut next_record = 0;
+
+    while let Some(request) = requests.recv().await {
+        match request {
+            RegistrationRequest::RegisterRecord(record, unique, tx) => {
+                let result = register_record(conn, &server, interface, &record, unique)
+                    .await
+                    .map(|group| {
+                        let id = next_record;
+                        next_record += 1;
+                        groups.push((id, group));
+                        id
+                    });
+                if tx.send(result).is_err() {
+                    log::warn!("Failed to send registered record, receiver dropped");
+                }
+            }
+            RegistrationRequest::RemoveRecord(id) => {
+                if let Some(i) = groups.iter().position(|g| g.0 == id) {
+                    log::trace!("Call EntryGroup.Free");
+                    groups.remove(i).1.call::<_, _, ()>("Free", &()).await?;
+                }
+            }
+            RegistrationRequest::UpdateTxt(_, tx) => {
+                if tx.send(Err(BonjourError::Unsupported.into())).is_err() {
+                    log::warn!("Failed to send TXT update result, receiver dropped");
+                }
+            }
+            RegistrationRequest::AddRecord(_, _, _, tx) => {
+                if tx.send(Err(BonjourError::Unsupported.into())).is_err() {
+                    log::warn!("Failed to send added record, receiver dropped");
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Register an individual record in a new entry group
+async fn register_record(
+    conn: &Connection,
+    server: &Proxy<'_>,
+    interface: i32,
+    record: &ResourceRecord,
+    unique: bool,
+) -> Result<Proxy<'static>, ZeroconfError> {
+    let rdata = record.data().to_rdata()?;
+    let (flags, ttl) = if unique {
+        (PUBLISH_UNIQUE, record.ttl().unwrap_or(DEFAULT_HOST_TTL))
+    } else {
+        (0, record.ttl().unwrap_or(DEFAULT_TTL))
+    };
+    let args = (
+        interface,
+        UNSPEC,
+        flags,
+        record.name().trim_end_matches('.'),
+        record.rrclass(),
+        u16::from(record.rrtype()),
+        ttl,
+        rdata.as_slice(),
+    );
+    add_record_group(conn, server, args).await
+}
+
+// Add a record to a new entry group, waiting for it to be established. The
+// group is freed if the record can't be added.
+async fn add_record_group(
+    conn: &Connection,
+    server: &Proxy<'_>,
+    args: RecordArgs<'_>,
+) -> Result<Proxy<'static>, ZeroconfError> {
+    let path: OwnedObjectPath = server.call("EntryGroupNew", &()).await?;
+    let group = Proxy::new(conn, AVAHI_SERVICE, path, ENTRY_GROUP_INTERFACE).await?;
+
+    let result = async {
+        let mut states = group.receive_signal("StateChanged").await?;
+        log::trace!("Call EntryGroup.AddRecord ({})", args.3);
+        group.call::<_, _, ()>("AddRecord", &args).await?;
+        group.call::<_, _, ()>("Commit", &()).await?;
+
+        loop {
+            let msg = states.next().await.ok_or(ZeroconfError::Dropped)?;
+            let (state, error): (i32, String) = msg.body().deserialize()?;
+            match state {
+                ENTRY_GROUP_ESTABLISHED => {
+                    log::debug!("EntryGroup established ({})", args.3);
+                    return Ok(());
+                }
+                ENTRY_GROUP_COLLISION => return Err(BonjourError::NameConflict.into()),
+                ENTRY_GROUP_FAILURE => return Err(avahi_error(&error).into()),
+                _ => (),
+            }
+        }
+    }
+    .await;
+
+    match result {
+        Ok(()) => Ok(group),
+        Err(e) => {
+            free_group(&group).await;
+            Err(e)
+        }
+    }
+}
+
+// Free an entry group, withdrawing its records
+async fn free_group(group: &Proxy<'_>) {
+    log::trace!("Call EntryGroup.Free");
+    if let Err(e) = group.call::<_, _, ()>("Free", &()).await {
+        log::warn!("Failed to free entry group - {}", e);
+    }
+}
@@ -0,0 +1,1246 @@
+// Backend using the Bonjour (`dns_sd`) API
+
+use crate::backend::{
+    AddRecordFuture, AddrContext, Backend, BackendOperation, BrowseContext, DomainContext,
+    ProcessFuture, PublishContext, RecordContext, ResolveContext, ResolverInformation,
+    UpdateFuture,
+};
+use crate::service_type::split_subtype;
+use crate::sync::lock;
+use crate::{
+    AddrEvent, BonjourError, Domain, DomainEvent, DomainKind, Interface, OpKind, OpType,
+    ProcessTask, Protocol, RecordData, RecordEvent, RecordType, ResourceRecord, Service, TxtRecord,
+    ZeroconfError,
+};
+
+use bonjour_sys::{
+    DNSRecordRef, DNSServiceErrorType, DNSServiceFlags, DNSServiceProcessResult, DNSServiceRef,
+    DNSServiceRefDeallocate, DNSServiceRefSockFD,
+};
+use std::any::Any;
+use std::ffi;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::unix::AsyncFd;
+use tokio::sync::{mpsc, oneshot, Notify};
+
+/// `BonjourBackend` implements the `ZeroConf` operations using the Bonjour
+/// (`dns_sd`) API, as provided by `mDNSResponder` or the compatibility layer
+/// of Avahi.
+///
+/// This is the default backend if the `bonjour` feature is enabled.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BonjourBackend;
+
+impl BonjourBackend {
+    /// Create a new `BonjourBackend`.
+    pub fn new() -> Self {
+        BonjourBackend
+    }
+}
+
+impl Backend for BonjourBackend {
+    fn register(
+        &self,
+        service: &Service,
+        context: PublishContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        register(None, service, context)
+    }
+
+    fn browse(
+        &self,
+        interface: &Interface,
+        service_type: &str,
+        domain: Option<&str>,
+        context: BrowseContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        browse(None, interface, service_type, domain, context)
+    }
+
+    fn resolve(
+        &self,
+        service: &Service,
+        context: ResolveContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        resolve(None, service, context)
+    }
+
+    fn get_addr_info(
+        &self,
+        interface: &Interface,
+        protocol: Protocol,
+        hostname: &str,
+        context: AddrContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        get_addr_info(None, interface, protocol, hostname, context)
+    }
+
+    fn enumerate_domains(
+        &self,
+        interface: &Interface,
+        kind: DomainKind,
+        context: DomainContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        enumerate_domains(None, interface, kind, context)
+    }
+
+    fn query_record(
+        &self,
+        interface: &Interface,
+        fullname: &str,
+        rrtype: RecordType,
+        rrclass: u16,
+        context: RecordContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        query_record(None, interface, fullname, rrtype, rrclass, context)
+    }
+
+    fn register_records(
+        &self,
+        interface: &Interface,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        let context = Arc::new(RegisterRecordContext {
+            interface: *interface,
+            pending: Mutex::new(Vec::new()),
+        });
+
+        let service_ref = crate::c_intf::create_connection()?;
+
+        let wrapper = ServiceRefWrapper::from_service(
+            service_ref,
+            Events::owned(service_ref)?,
+            OpType::new(&interface.to_string(), OpKind::RegisterRecord),
+            Some(Box::new(context)),
+        );
+
+        Ok(Box::new(wrapper))
+    }
+
+    fn reconfirm_record(
+        &self,
+        interface: &Interface,
+        record: &ResourceRecord,
+    ) -> Result<(), ZeroconfError> {
+        let rdata = record.data().to_rdata()?;
+        if rdata.len() > u16::MAX.into() {
+            return Err(BonjourError::BadParam.into());
+        }
+        crate::c_intf::reconfirm_record(interface, record, &rdata)
+    }
+}
+
+/// `Connection` is a connection to the Bonjour daemon that is shared by any
+/// number of operations, so that each operation doesn't open a socket of its
+/// own. The events of every operation sharing the connection are processed
+/// by a single task.
+///
+/// Operations share the connection when it is selected as their backend, or
+/// when it is set as the default backend with
+/// [`set_default`][`crate::backend::set_default`]. The connection remains
+/// open while the `Connection`, or any operation started with it, is held.
+///
+/// If the daemon stops, the operations sharing the connection end with an
+/// error and the next operation started opens a new connection.
+///
+/// Records registered with a
+/// [`RecordRegistrar`][`crate::RecordRegistrar`] use a connection of their
+/// own.
+///
+/// # Examples
+/// ```
+/// # tokio_test::block_on(async {
+/// use async_zeroconf::backend::Connection;
+/// use std::sync::Arc;
+///
+/// let connection = Arc::new(Connection::connect()?);
+///
+/// let mut service = async_zeroconf::Service::new("Server", "_http._tcp", 80);
+/// let service_ref = service.set_backend(connection.clone()).publish().await?;
+///
+/// let mut browser = async_zeroconf::ServiceBrowserBuilder::new("_http._tcp");
+/// let services = browser.backend(connection).browse()?;
+/// # Ok::<(), async_zeroconf::ZeroconfError>(())
+/// # });
+/// ```
+#[derive(Debug, Clone)]
+pub struct Connection {
+    state: Arc<ConnectionState>,
+    // Held by each user of the connection, so that processing ends once
+    // they have all been dropped
+    _user: mpsc::UnboundedSender<()>,
+}
+
+impl Connection {
+    /// Open a connection to the daemon, spawning the task that processes
+    /// the events of every operation sharing it.
+    pub fn connect() -> Result<Self, ZeroconfError> {
+        let (connection, task) = Connection::connect_task()?;
+
+        tokio::spawn(task);
+
+        Ok(connection)
+    }
+
+    /// Open a connection to the daemon. The returned [`ProcessTask`] future
+    /// must be awaited to process the events of every operation sharing the
+    /// connection.
+    ///
+    /// # Note
+    /// This method is intended if more control is needed over how the task
+    /// is spawned. [`Connection::connect`] will automatically spawn the task.
+    pub fn connect_task() -> Result<(Self, impl ProcessTask), ZeroconfError> {
+        let state = Arc::new(ConnectionState {
+            current: Mutex::new(Arc::new(SharedConnection::connect()?)),
+            replaced: Notify::new(),
+        });
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        let task = process_connection(state.clone(), rx);
+
+        log::debug!("Created Connection");
+        Ok((Connection { state, _user: tx }, task))
+    }
+}
+
+impl Backend for Connection {
+    fn register(
+        &self,
+        service: &Service,
+        context: PublishContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        register(Some(self), service, context)
+    }
+
+    fn browse(
+        &self,
+        interface: &Interface,
+        service_type: &str,
+        domain: Option<&str>,
+        context: BrowseContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        browse(Some(self), interface, service_type, domain, context)
+    }
+
+    fn resolve(
+        &self,
+        service: &Service,
+        context: ResolveContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        resolve(Some(self), service, context)
+    }
+
+    fn get_addr_info(
+        &self,
+        interface: &Interface,
+        protocol: Protocol,
+        hostname: &str,
+        context: AddrContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        get_addr_info(Some(self), interface, protocol, hostname, context)
+    }
+
+    fn enumerate_domains(
+        &self,
+        interface: &Interface,
+        kind: DomainKind,
+        context: DomainContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        enumerate_domains(Some(self), interface, kind, context)
+    }
+
+    fn query_record(
+        &self,
+        interface: &Interface,
+        fullname: &str,
+        rrtype: RecordType,
+        rrclass: u16,
+        context: RecordContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        query_record(Some(self), interface, fullname, rrtype, rrclass, context)
+    }
+
+    fn register_records(
+        &self,
+        interface: &Interface,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        BonjourBackend.register_records(interface)
+    }
+
+    fn reconfirm_record(
+        &self,
+        interface: &Interface,
+        record: &ResourceRecord,
+    ) -> Result<(), ZeroconfError> {
+        BonjourBackend.reconfirm_record(interface, record)
+    }
+}
+
+// Connection used to start new operations, which is replaced once it has
+// been closed
+#[derive(Debug)]
+struct ConnectionState {
+    current: Mutex<Arc<SharedConnection>>,
+    // Notified when the current connection is replaced
+    replaced: Notify,
+}
+
+impl ConnectionState {
+    fn current(&self) -> Arc<SharedConnection> {
+        lock(&self.current).clone()
+    }
+
+    // Start an operation that shares the current connection with `call`,
+    // opening a new connection if the current one has been closed
+    fn start<F>(&self, call: F) -> Result<(DNSServiceRef, Arc<SharedConnection>), ZeroconfError>
+    where
+        F: FnOnce(Option<DNSServiceRef>) -> Result<DNSServiceRef, ZeroconfError>,
+    {
+        let shared = {
+            let mut current = lock(&self.current);
+            if current.error().is_some() {
+                log::debug!("Connection closed, connecting again");
+                *current = Arc::new(SharedConnection::connect()?);
+                self.replaced.notify_waiters();
+            }
+            current.clone()
+        };
+        Ok((shared.start(call)?, shared))
+    }
+}
+
+// Connection to the daemon shared by operations, which is deallocated once
+// every operation sharing it has been dropped
+#[derive(Debug)]
+struct SharedConnection {
+    // Pointer to reference returned by C API
+    inner: DNSServiceRef,
+    // Mutex to protect the connection and every reference sharing it
+    lock: Mutex<()>,
+    // Async file descriptor to detect new events asynchronously
+    fd: AsyncFd<ServiceFileDescriptor>,
+    // Error that ended processing of the connection
+    error: Mutex<Option<BonjourError>>,
+    // Notified when processing of the connection ends
+    closed: Notify,
+}
+
+impl SharedConnection {
+    fn connect() -> Result<Self, ZeroconfError> {
+        let service_ref = crate::c_intf::create_connection()?;
+        Ok(SharedConnection::new(service_ref)?)
+    }
+
+    fn new(service_ref: DNSServiceRef) -> Result<Self, std::io::Error> {
+        log::trace!("Call DNSServiceRefSockFD");
+        let fd = unsafe { DNSServiceRefSockFD(service_ref) };
+        log::trace!("  FD:{}", fd);
+
+        let async_fd = AsyncFd::new(ServiceFileDescriptor { fd })?;
+
+        Ok(SharedConnection {
+            inner: service_ref,
+            lock: Mutex::new(()),
+            fd: async_fd,
+            error: Mutex::new(None),
+            closed: Notify::new(),
+        })
+    }
+
+    /// A future to wait for any pending events on the connection, handling
+    /// them by calling the callbacks of the operations sharing it.
+    async fn process_events(&self) -> Result<(), ZeroconfError> {
+        let mut fd = self.fd.readable().await?;
+
+        log::trace!("Call DNSServiceProcessResult");
+
+        let process_err = {
+            let _guard = lock(&self.lock);
+            unsafe { DNSServiceProcessResult(self.inner) }
+        };
+        fd.clear_ready();
+        if process_err != 0 {
+            return Err(Into::<BonjourError>::into(process_err).into());
+        }
+
+        Ok(())
+    }
+
+    // Record the error that ended processing, waking any operations waiting
+    // on the connection
+    fn close(&self, e: &ZeroconfError) {
+        let error = match e {
+            ZeroconfError::Bonjour(e) => *e,
+            _ => BonjourError::Unknown,
+        };
+        *lock(&self.error) = Some(error);
+        self.closed.notify_waiters();
+    }
+
+    // Get the error that ended processing, if processing has ended
+    fn error(&self) -> Option<BonjourError> {
+        *lock(&self.error)
+    }
+
+    // Wait until processing of the connection ends, returning the error that
+    // ended it
+    async fn wait_closed(&self) -> Result<bool, ZeroconfError> {
+        loop {
+            let closed = self.closed.notified();
+            if let Some(e) = self.error() {
+                return Err(e.into());
+            }
+            closed.await;
+        }
+    }
+
+    // Start an operation that shares the connection with `call`
+    fn start<F>(&self, call: F) -> Result<DNSServiceRef, ZeroconfError>
+    where
+        F: FnOnce(Option<DNSServiceRef>) -> Result<DNSServiceRef, ZeroconfError>,
+    {
+        let _guard = lock(&self.lock);
+        if let Some(e) = self.error() {
+            return Err(e.into());
+        }
+        call(Some(self.inner))
+    }
+}
+
+// Implement Send as reference is thread-safe
+unsafe impl Send for SharedConnection {}
+// Implement Sync as reference is protected by mutex
+unsafe impl Sync for SharedConnection {}
+
+impl Drop for SharedConnection {
+    fn drop(&mut self) {
+        log::debug!("Dropping and deallocating connection");
+        let _guard = lock(&self.lock);
+        unsafe { DNSServiceRefDeallocate(self.inner) };
+    }
+}
+
+// Process the events of the current connection until every user of it is
+// dropped, waiting for the connection to be replaced if it is closed
+async fn process_connection(state: Arc<ConnectionState>, mut users: mpsc::UnboundedReceiver<()>) {
+    loop {
+        let replaced = state.replaced.notified();
+        let shared = state.current();
+        let closed = shared.error().is_some();
+        tokio::select! {
+            // Nothing is sent, so this completes once all users are dropped
+            _ = users.recv() => {
+                log::debug!("Connection no longer used");
+                return;
+            }
+            result = shared.process_events(), if !closed => {
+                if let Err(e) = result {
+                    log::error!("Error on processing connection: {}", e);
+                    shared.close(&e);
+                }
+            }
+            _ = replaced, if closed => log::debug!("Processing new connection"),
+        }
+    }
+}
+
+// Start an operation with `call`, on a connection of its own or sharing
+// `connection`, returning the source of the operation's events
+fn start<F>(
+    connection: Option<&Connection>,
+    call: F,
+) -> Result<(DNSServiceRef, Events), ZeroconfError>
+where
+    F: FnOnce(Option<DNSServiceRef>) -> Result<DNSServiceRef, ZeroconfError>,
+{
+    match connection {
+        Some(connection) => {
+            let (service_ref, shared) = connection.state.start(call)?;
+            Ok((
+                service_ref,
+                Events::Shared {
+                    shared,
+                    _connection: connection.clone(),
+                },
+            ))
+        }
+        None => {
+            let service_ref = call(None)?;
+            Ok((service_ref, Events::owned(service_ref)?))
+        }
+    }
+}
+
+fn register(
+    connection: Option<&Connection>,
+    service: &Service,
+    context: PublishContext,
+) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+    let context = Arc::new(context);
+    let context_ptr = Arc::as_ptr(&context) as *mut Arc<PublishContext> as *mut libc::c_void;
+
+    let (service_ref, events) = start(connection, |c| {
+        crate::c_intf::service_register(
+            c,
+            (service.name(), &service.registration_type(), service.port()),
+            service.interface(),
+            (service.domain().as_deref(), service.host().as_deref()),
+            service.txt(),
+            service.allow_rename(),
+            (Some(register_callback), context_ptr),
+        )
+    })?;
+
+    let wrapper = ServiceRefWrapper::from_service(
+        service_ref,
+        events,
+        OpType::new(service.service_type(), OpKind::Publish),
+        Some(Box::new(context)),
+    );
+
+    Ok(Box::new(wrapper))
+}
+
+fn browse(
+    connection: Option<&Connection>,
+    interface: &Interface,
+    service_type: &str,
+    domain: Option<&str>,
+    context: BrowseContext,
+) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+    let context = Arc::new(context);
+    let context_ptr = Arc::as_ptr(&context) as *mut Arc<BrowseContext> as *mut libc::c_void;
+
+    // Bonjour expects subtypes in the same form as for registration
+    let regtype = match split_subtype(service_type) {
+        (Some(subtype), service_type) => format!("{},{}", service_type, subtype),
+        (None, service_type) => service_type.to_string(),
+    };
+
+    let (service_ref, events) = start(connection, |c| {
+        crate::c_intf::service_browse(
+            c,
+            interface,
+            &regtype,
+            domain,
+            Some(browse_callback),
+            context_ptr,
+        )
+    })?;
+
+    let wrapper = ServiceRefWrapper::from_service(
+        service_ref,
+        events,
+        OpType::new(service_type, OpKind::Browse),
+        Some(Box::new(context)),
+    );
+
+    Ok(Box::new(wrapper))
+}
+
+fn resolve(
+    connection: Option<&Connection>,
+    service: &Service,
+    context: ResolveContext,
+) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+    let context = Arc::new(context);
+    let context_ptr = Arc::as_ptr(&context) as *mut Arc<ResolveContext> as *mut libc::c_void;
+
+    let domain = service
+        .domain()
+        .as_ref()
+        .ok_or_else(|| ZeroconfError::NotFromBrowser(service.clone()))?;
+
+    let (service_ref, events) = start(connection, |c| {
+        crate::c_intf::service_resolve(
+            c,
+            service.name(),
+            service.interface(),
+            service.service_type(),
+            domain,
+            Some(resolve_callback),
+            context_ptr,
+        )
+    })?;
+
+    let wrapper = ServiceRefWrapper::from_service(
+        service_ref,
+        events,
+        OpType::new(service.service_type(), OpKind::Resolve),
+        Some(Box::new(context)),
+    );
+
+    Ok(Box::new(wrapper))
+}
+
+fn get_addr_info(
+    connection: Option<&Connection>,
+    interface: &Interface,
+    protocol: Protocol,
+    hostname: &str,
+    context: AddrContext,
+) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+    let context = Arc::new(context);
+    let context_ptr = Arc::as_ptr(&context) as *mut Arc<AddrContext> as *mut libc::c_void;
+
+    let (service_ref, events) = start(connection, |c| {
+        crate::c_intf::get_addr_info(
+            c,
+            interface,
+            protocol,
+            hostname,
+            Some(addr_info_callback),
+            context_ptr,
+        )
+    })?;
+
+    let wrapper = ServiceRefWrapper::from_service(
+        service_ref,
+        events,
+        OpType::new(hostname, OpKind::GetAddrInfo),
+        Some(Box::new(context)),
+    );
+
+    Ok(Box::new(wrapper))
+}
+
+fn enumerate_domains(
+    connection: Option<&Connection>,
+    interface: &Interface,
+    kind: DomainKind,
+    context: DomainContext,
+) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+    let context = Arc::new(context);
+    let context_ptr = Arc::as_ptr(&context) as *mut Arc<DomainContext> as *mut libc::c_void;
+
+    let (service_ref, events) = start(connection, |c| {
+        crate::c_intf::enumerate_domains(c, interface, kind, Some(domain_callback), context_ptr)
+    })?;
+
+    let wrapper = ServiceRefWrapper::from_service(
+        service_ref,
+        events,
+        OpType::new(&kind.to_string(), OpKind::EnumerateDomains),
+        Some(Box::new(context)),
+    );
+
+    Ok(Box::new(wrapper))
+}
+
+fn query_record(
+    connection: Option<&Connection>,
+    interface: &Interface,
+    fullname: &str,
+    rrtype: RecordType,
+    rrclass: u16,
+    context: RecordContext,
+) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+    let context = Arc::new(context);
+    let context_ptr = Arc::as_ptr(&context) as *mut Arc<RecordContext> as *mut libc::c_void;
+
+    let (service_ref, events) = start(connection, |c| {
+        crate::c_intf::query_record(
+            c,
+            interface,
+            fullname,
+            rrtype,
+            rrclass,
+            Some(query_record_callback),
+            context_ptr,
+        )
+    })?;
+
+    let wrapper = ServiceRefWrapper::from_service(
+        service_ref,
+        events,
+        OpType::new(fullname, OpKind::QueryRecord),
+        Some(Box::new(context)),
+    );
+
+    Ok(Box::new(wrapper))
+}
+
+// Context for the callbacks of records registered on a connection, holding
+// the channels waiting for the result of each registration
+#[derive(Debug)]
+struct RegisterRecordContext {
+    interface: Interface,
+    // Keyed by the address of the record reference
+    pending: Mutex<Vec<(usize, RecordResult)>>,
+}
+
+// Channel for the result of registering a record
+type RecordResult = oneshot::Sender<Result<(), BonjourError>>;
+
+impl RegisterRecordContext {
+    // Report the result of registering a record to the waiting channel, if
+    // this is the first result for the record
+    fn complete(&self, record_ref: usize, result: Result<(), BonjourError>) {
+        let tx = {
+            let mut pending = lock(&self.pending);
+            pending
+                .iter()
+                .position(|p| p.0 == record_ref)
+                .map(|i| pending.remove(i).1)
+        };
+        match (tx, result) {
+            (Some(tx), result) => {
+                if tx.send(result).is_err() {
+                    log::debug!("Record result not sent, receiver dropped");
+                }
+            }
+            (None, Err(e)) => log::warn!("Registered record failed: {}", e),
+            (None, Ok(())) => (),
+        }
+    }
+}
+
+// Callback passed to DNSServiceRegister
+unsafe extern "C" fn register_callback(
+    _sd_ref: DNSServiceRef,
+    _flags: DNSServiceFlags,
+    error: DNSServiceErrorType,
+    name: *const libc::c_char,
+    regtype: *const libc::c_char,
+    domain: *const libc::c_char,
+    context: *mut libc::c_void,
+) {
+    let proxy = &*(context as *const PublishContext);
+    if error == 0 {
+        let c_name = ffi::CStr::from_ptr(name);
+        let c_type = ffi::CStr::from_ptr(regtype);
+        let c_domain = ffi::CStr::from_ptr(domain);
+        let name = c_name
+            .to_str()
+            .expect("string originally from rust should be safe");
+        let regtype = c_type
+            .to_str()
+            .expect("string originally from rust should be safe");
+        let domain = c_domain
+            .to_str()
+            .expect("string originally from rust should be safe");
+        log::debug!("Service Callback OK ({}:{}:{})", name, regtype, domain);
+        proxy.send_registered(name, regtype, domain);
+    } else {
+        log::debug!(
+            "Service Callback Error ({}:{})",
+            error,
+            Into::<BonjourError>::into(error)
+        );
+        proxy.send(Err(error.into()));
+    }
+}
+
+unsafe fn browse_callback_inner(
+    intf_index: u32,
+    name: *const libc::c_char,
+    regtype: *const libc::c_char,
+    domain: *const libc::c_char,
+) -> Result<Service, ZeroconfError> {
+    let c_name = ffi::CStr::from_ptr(name);
+    let c_type = ffi::CStr::from_ptr(regtype);
+    let c_domain = ffi::CStr::from_ptr(domain);
+    let name = c_name.to_str()?;
+    let regtype = c_type.to_str()?;
+    let domain = c_domain.to_str()?;
+
+    log::debug!(
+        "ServiceBrowse Callback OK ({}:{}:{})",
+        name,
+        regtype,
+        domain
+    );
+    let mut service = Service::new(name, regtype, 0);
+    service
+        .set_interface(Interface::Interface(intf_index))
+        .set_domain(domain.to_string());
+    Ok(service)
+}
+
+// Callback passed to DNSServiceBrowse
+unsafe extern "C" fn browse_callback(
+    _sd_ref: DNSServiceRef,
+    flags: DNSServiceFlags,
+    intf_index: u32,
+    error: DNSServiceErrorType,
+    name: *const libc::c_char,
+    regtype: *const libc::c_char,
+    domain: *const libc::c_char,
+    context: *mut libc::c_void,
+) {
+    let proxy = &*(context as *const BrowseContext);
+    if error == 0 {
+        let more = (flags & 0x1) == 0x1;
+        let add = (flags & 0x2) == 0x2;
+
+        let service = browse_callback_inner(intf_index, name, regtype, domain);
+        if !more {
+            log::trace!("End of services (for now)");
+        }
+
+        match service {
+            Ok(s) if !add => {
+                log::debug!("ServiceBrowse Remove {}", s.name());
+                proxy.send_removed(s, !more);
+            }
+            s => proxy.send(s, !more),
+        }
+    } else {
+        proxy.send(Err(error.into()), false);
+
+        log::error!(
+            "ServiceBrowse Callback Error ({}:{})",
+            error,
+            Into::<BonjourError>::into(error)
+        )
+    }
+}
+
+unsafe fn resolve_callback_inner(
+    intf_index: u32,
+    fullname: *const libc::c_char,
+    hosttarget: *const libc::c_char,
+    port: u16,
+    txt_len: u16,
+    txt_record: *const libc::c_uchar,
+) -> Result<ResolverInformation, ZeroconfError> {
+    let c_fullname = ffi::CStr::from_ptr(fullname);
+    let c_hosttarget = ffi::CStr::from_ptr(hosttarget);
+    let fullname = c_fullname.to_str()?;
+    let hosttarget = c_hosttarget.to_str()?;
+    let port = port.to_be();
+
+    log::debug!(
+        "ServiceResolve Callback OK ({}:{}:{})",
+        fullname,
+        hosttarget,
+        port
+    );
+
+    let txt = if txt_record.is_null() {
+        TxtRecord::new()
+    } else {
+        TxtRecord::from_bytes_lossy(std::slice::from_raw_parts(txt_record, txt_len.into()))
+    };
+
+    Ok(ResolverInformation::new(
+        Interface::Interface(intf_index),
+        fullname,
+        hosttarget,
+        port,
+        txt,
+    ))
+}
+
+// Callback passed to DNSServiceResolve
+unsafe extern "C" fn resolve_callback(
+    _sd_ref: DNSServiceRef,
+    flags: DNSServiceFlags,
+    intf_index: u32,
+    error: DNSServiceErrorType,
+    fullname: *const libc::c_char,
+    hosttarget: *const libc::c_char,
+    port: u16,
+    txt_len: u16,
+    txt_record: *const libc::c_uchar,
+    context: *mut libc::c_void,
+) {
+    let proxy = &*(context as *const ResolveContext);
+    if error == 0 {
+        let more = (flags & 0x1) == 0x1;
+        if more {
+            log::warn!("Unexpected DNSServiceFlagsMoreComing set on resolve")
+        }
+
+        proxy.send(resolve_callback_inner(
+            intf_index, fullname, hosttarget, port, txt_len, txt_record,
+        ));
+    } else {
+        proxy.send(Err(error.into()));
+        log::error!(
+            "ServiceResolve Callback Error ({}:{})",
+            error,
+            Into::<BonjourError>::into(error)
+        )
+    }
+}
+
+unsafe fn addr_info_callback_inner(
+    hostname: *const libc::c_char,
+    address: *const bonjour_sys::sockaddr,
+) -> Result<IpAddr, ZeroconfError> {
+    let c_hostname = ffi::CStr::from_ptr(hostname);
+    let hostname = c_hostname.to_str()?;
+
+    let address = address as *const libc::sockaddr;
+    let address = match (*address).sa_family as i32 {
+        libc::AF_INET => {
+            let sin = &*(address as *const libc::sockaddr_in);
+            IpAddr::V4(Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr)))
+        }
+        libc::AF_INET6 => {
+            let sin6 = &*(address as *const libc::sockaddr_in6);
+            IpAddr::V6(Ipv6Addr::from(sin6.sin6_addr.s6_addr))
+        }
+        _ => return Err(BonjourError::Invalid.into()),
+    };
+
+    log::debug!("GetAddrInfo Callback OK ({}:{})", hostname, address);
+    Ok(address)
+}
+
+// Callback passed to DNSServiceGetAddrInfo
+unsafe extern "C" fn addr_info_callback(
+    _sd_ref: DNSServiceRef,
+    flags: DNSServiceFlags,
+    _intf_index: u32,
+    error: DNSServiceErrorType,
+    hostname: *const libc::c_char,
+    address: *const bonjour_sys::sockaddr,
+    _ttl: u32,
+    context: *mut libc::c_void,
+) {
+    let proxy = &*(context as *const AddrContext);
+    if error == 0 {
+        let more = (flags & 0x1) == 0x1;
+        let add = (flags & 0x2) == 0x2;
+
+        let event = addr_info_callback_inner(hostname, address).map(|a| {
+            if add {
+                AddrEvent::Added(a)
+            } else {
+                AddrEvent::Removed(a)
+            }
+        });
+        proxy.send(event, !more);
+    } else {
+        proxy.send(Err(error.into()), false);
+        log::error!(
+            "GetAddrInfo Callback Error ({}:{})",
+            error,
+            Into::<BonjourError>::into(error)
+        )
+    }
+}
+
+// Callback passed to DNSServiceEnumerateDomains
+unsafe extern "C" fn domain_callback(
+    _sd_ref: DNSServiceRef,
+    flags: DNSServiceFlags,
+    _intf_index: u32,
+    error: DNSServiceErrorType,
+    reply_domain: *const libc::c_char,
+    context: *mut libc::c_void,
+) {
+    let proxy = &*(context as *const DomainContext);
+    if error == 0 {
+        let add = (flags & 0x2) == 0x2;
+        let default = (flags & 0x4) == 0x4;
+
+        let c_domain = ffi::CStr::from_ptr(reply_domain);
+        let event = c_domain.to_str().map_err(|e| e.into()).map(|d| {
+            log::debug!("EnumerateDomains Callback OK ({}:{})", d, add);
+            let domain = Domain::new(d, default);
+            if add {
+                DomainEvent::Added(domain)
+            } else {
+                DomainEvent::Removed(domain)
+            }
+        });
+        proxy.send(event);
+    } else {
+        proxy.send(Err(error.into()));
+        log::error!(
+            "EnumerateDomains Callback Error ({}:{})",
+            error,
+            Into::<BonjourError>::into(error)
+        )
+    }
+}
+
+// Callback passed to DNSServiceRegisterRecord
+unsafe extern "C" fn register_record_callback(
+    _sd_ref: DNSServiceRef,
+    record_ref: DNSRecordRef,
+    _flags: DNSServiceFlags,
+    error: DNSServiceErrorType,
+    context: *mut libc::c_void,
+) {
+    let proxy = &*(context as *const RegisterRecordContext);
+    let result = if error == 0 {
+        log::debug!("Register Record Callback OK");
+        Ok(())
+    } else {
+        Err(error.into())
+    };
+    proxy.complete(record_ref as usize, result);
+}
+
+// Callback passed to DNSServiceQueryRecord
+unsafe extern "C" fn query_record_callback(
+    _sd_ref: DNSServiceRef,
+    flags: DNSServiceFlags,
+    _intf_index: u32,
+    error: DNSServiceErrorType,
+    fullname: *const libc::c_char,
+    rrtype: u16,
+    rrclass: u16,
+    rdlen: u16,
+    rdata: *const libc::c_void,
+    ttl: u32,
+    context: *mut libc::c_void,
+) {
+    let proxy = &*(context as *const RecordContext);
+    if error == 0 {
+        let add = (flags & 0x2) == 0x2;
+
+        let c_fullname = ffi::CStr::from_ptr(fullname);
+        let rdata = std::slice::from_raw_parts(rdata as *const u8, rdlen as usize);
+        let event = c_fullname.to_str().map_err(|e| e.into()).map(|name| {
+            log::debug!("QueryRecord Callback OK ({}:{}:{})", name, rrtype, add);
+            let data = RecordData::from_rdata(rrtype, rdata);
+            let record = ResourceRecord::new(name, rrclass, Some(ttl), data);
+            if add {
+                RecordEvent::Added(record)
+            } else {
+                RecordEvent::Removed(record)
+            }
+        });
+        proxy.send(event);
+    } else {
+        proxy.send(Err(error.into()));
+        log::error!(
+            "QueryRecord Callback Error ({}:{})",
+            error,
+            Into::<BonjourError>::into(error)
+        )
+    }
+}
+
+// Internal type to hold the file descriptor for the socket associated with the
+// service.
+#[derive(Debug)]
+pub(crate) struct ServiceFileDescriptor {
+    pub fd: i32,
+}
+
+// Allow ServiceFileDescriptor to be convered to a AsyncFd by implementing the
+// AsRawFd trait.
+impl std::os::unix::prelude::AsRawFd for ServiceFileDescriptor {
+    fn as_raw_fd(&self) -> i32 {
+        self.fd
+    }
+}
+
+// Source of the events of a service reference
+#[derive(Debug)]
+enum Events {
+    // The reference has a socket of its own, with a mutex to protect the
+    // reference
+    Owned(Mutex<()>, AsyncFd<ServiceFileDescriptor>),
+    // The reference shares a connection, which processes its events, and
+    // holds a user of the connection to keep processing
+    Shared {
+        shared: Arc<SharedConnection>,
+        _connection: Connection,
+    },
+}
+
+impl Events {
+    // Events of a reference with a socket of its own
+    fn owned(service_ref: DNSServiceRef) -> Result<Self, std::io::Error> {
+        log::trace!("Call DNSServiceRefSockFD");
+        let fd = unsafe { DNSServiceRefSockFD(service_ref) };
+        log::trace!("  FD:{}", fd);
+
+        let async_fd = AsyncFd::new(ServiceFileDescriptor { fd })?;
+        Ok(Events::Owned(Mutex::new(()), async_fd))
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct ServiceRefWrapper {
+    // Pointer to reference returned by C API
+    pub inner: DNSServiceRef,
+    // Source of events for the reference
+    events: Events,
+    // Hold a reference to an (optional) context used for C API callbacks
+    context: Option<Box<dyn Any + Send>>,
+    // Operation type that created this reference
+    op_type: OpType,
+    // Records added to a registered service, which are deallocated along
+    // with the service reference
+    records: Mutex<Vec<(u64, DNSRecordRef)>>,
+    // Identifier of the next record added
+    next_record: AtomicU64,
+}
+
+impl ServiceRefWrapper {
+    fn from_service(
+        service_ref: DNSServiceRef,
+        events: Events,
+        op_type: OpType,
+        context: Option<Box<dyn Any + Send>>,
+    ) -> Self {
+        ServiceRefWrapper {
+            inner: service_ref,
+            events,
+            context,
+            op_type,
+            records: Mutex::new(Vec::new()),
+            next_record: AtomicU64::new(0),
+        }
+    }
+
+    // Mutex protecting the service reference, which is shared with the
+    // connection if the reference shares one
+    fn mutex(&self) -> &Mutex<()> {
+        match &self.events {
+            Events::Owned(lock, _) => lock,
+            Events::Shared { shared, .. } => &shared.lock,
+        }
+    }
+
+    /// A future to wait for any pending events related to the service,
+    /// handling them and then completing the future.
+    async fn process_events(&self) -> Result<bool, ZeroconfError> {
+        let fd = match &self.events {
+            Events::Owned(_, fd) => fd,
+            // Events are handled by the task processing the connection
+            Events::Shared { shared, .. } => return shared.wait_closed().await,
+        };
+
+        // Wait on indication that file descriptor is readable
+        let mut fd = fd.readable().await?;
+
+        log::trace!("Call DNSServiceProcessResult");
+
+        // Process any pending events
+        let process_err = {
+            let _guard = lock(self.mutex());
+            unsafe { DNSServiceProcessResult(self.inner) }
+        };
+        // Clear ready flag for socket to wait for next event
+        // As there is no await point or polling between processing above and
+        // clearing the flag, there should be no opportunity to 'miss' an event
+        // between the DNSServiceProcessResult and clear_ready().
+        fd.clear_ready();
+        if process_err != 0 {
+            return Err(Into::<BonjourError>::into(process_err).into());
+        }
+
+        Ok(true)
+    }
+
+    // Hold a record reference until it is removed, returning its identifier
+    fn push_record(&self, record_ref: DNSRecordRef) -> u64 {
+        let id = self.next_record.fetch_add(1, Ordering::Relaxed);
+        lock(&self.records).push((id, record_ref));
+        id
+    }
+}
+
+impl BackendOperation for ServiceRefWrapper {
+    fn process(&self) -> ProcessFuture<'_> {
+        Box::pin(self.process_events())
+    }
+
+    fn update_txt(&self, txt: TxtRecord) -> UpdateFuture<'_> {
+        let result = if self.op_type.kind() == &OpKind::Publish {
+            let _guard = lock(self.mutex());
+            crate::c_intf::update_txt(self.inner, &txt)
+        } else {
+            Err(BonjourError::Unsupported.into())
+        };
+        Box::pin(async { result })
+    }
+
+    fn add_record(&self, rrtype: RecordType, rdata: Vec<u8>, ttl: u32) -> AddRecordFuture<'_> {
+        let result = if self.op_type.kind() == &OpKind::Publish {
+            let _guard = lock(self.mutex());
+            crate::c_intf::add_record(self.inner, rrtype, &rdata, ttl)
+                .map(|record_ref| self.push_record(record_ref))
+        } else {
+            Err(BonjourError::Unsupported.into())
+        };
+        Box::pin(async { result })
+    }
+
+    fn register_record(&self, record: ResourceRecord, unique: bool) -> AddRecordFuture<'_> {
+        Box::pin(async move {
+            let context = self
+                .context
+                .as_ref()
+                .and_then(|c| c.downcast_ref::<Arc<RegisterRecordContext>>())
+                .ok_or(BonjourError::Unsupported)?;
+            let rdata = record.data().to_rdata()?;
+            if rdata.len() > u16::MAX.into() {
+                return Err(BonjourError::BadParam.into());
+            }
+
+            let (tx, rx) = oneshot::channel();
+            let id = {
+                // Hold the lock until the channel is added, so the callback
+                // can't be called first
+                let _guard = lock(self.mutex());
+                let record_ref = crate::c_intf::register_record(
+                    self.inner,
+                    &context.interface,
+                    &record,
+                    &rdata,
+                    unique,
+                    Some(register_record_callback),
+                    Arc::as_ptr(context) as *mut libc::c_void,
+                )?;
+                lock(&context.pending).push((record_ref as usize, tx));
+                self.push_record(record_ref)
+            };
+
+            match rx.await {
+                Ok(Ok(())) => Ok(id),
+                Ok(Err(e)) => {
+                    if let Err(e) = self.remove_record(id) {
+                        log::debug!("Failed to remove record after error: {}", e);
+                    }
+                    Err(e.into())
+                }
+                Err(_) => Err(ZeroconfError::Dropped),
+            }
+        })
+    }
+
+    fn remove_record(&self, id: u64) -> Result<(), ZeroconfError> {
+        let record_ref = {
+            let mut records = lock(&self.records);
+            let position = records
+                .iter()
+                .position(|r| r.0 == id)
+                .ok_or(BonjourError::BadReference)?;
+            records.remove(position).1
+        };
+        let _guard = lock(self.mutex());
+        crate::c_intf::remove_record(self.inner, record_ref)
+    }
+}
+
+// Implement Send as reference is thread-safe
+unsafe impl Send for ServiceRefWrapper {}
+// Implement Sync as reference is protected by mutex
+unsafe impl Sync for ServiceRefWrapper {}
+
+impl Drop for ServiceRefWrapper {
+    fn drop(&mut self) {
+        log::debug!(
+            "Dropping and deallocating service reference ({})",
+            self.op_type
+        );
+        {
+            let _guard = lock(self.mutex());
+            unsafe { DNSServiceRefDeallocate(self.inner) };
+        }
+        if self.context.is_some() {
+            log::debug!("Context to be dropped ({})", self.op_type);
+        }
+    }
+}
@@ -0,0 +1,1080 @@
+// In-memory backend for testing without a daemon or network
+
+use crate::backend::task::{RegistrationRequest, TaskOperation};
+use crate::backend::{
+    AddrContext, Backend, BackendOperation, BrowseContext, DomainContext, PublishContext,
+    RecordContext, ResolveContext, ResolverInformation,
+};
+use crate::service_type::split_subtype;
+use crate::service_type_browser::SERVICE_TYPE_QUERY;
+use crate::sync::lock;
+use crate::{
+    AddrEvent, BonjourError, BrowseEvent, Domain, DomainEvent, DomainKind, Interface, OpKind,
+    OpType, Protocol, RecordData, RecordEvent, RecordQuery, RecordType, RegisteredService,
+    ResourceRecord, Service, ServiceInstanceName, TxtRecord, ZeroconfError,
+};
+
+use std::future::Future;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::sync::{mpsc, Notify};
+
+const DEFAULT_DOMAIN: &str = "local";
+const DEFAULT_HOST: &str = "mock";
+
+/// `MockNetwork` is an in-memory network shared by any number of
+/// [`MockBackend`]s. Services published with a backend on the network are
+/// visible to browse and resolve operations using any backend on the same
+/// network, without requiring a daemon or any network access.
+///
+/// The network can be used to control the latency of operations, simulate
+/// name conflicts with other hosts, inject errors into operations and stop
+/// the daemon.
+///
+/// # Examples
+/// ```
+/// # tokio_test::block_on(async {
+/// use async_zeroconf::backend::{MockBackend, MockNetwork};
+/// use std::sync::Arc;
+///
+/// let network = MockNetwork::new();
+/// let backend = Arc::new(MockBackend::new(&network));
+///
+/// let service_ref = async_zeroconf::Service::new("Server", "_http._tcp", 80)
+///                       .set_backend(backend.clone())
+///                       .publish().await?;
+///
+/// let mut browser = async_zeroconf::ServiceBrowserBuilder::new("_http._tcp");
+/// let mut services = browser
+///     .backend(backend)
+///     .browse()?;
+///
+/// let service = services.recv().await.unwrap()?;
+/// let resolved = async_zeroconf::ServiceResolver::r(&service).await?;
+/// assert_eq!(resolved.port(), 80);
+/// # Ok::<(), async_zeroconf::ZeroconfError>(())
+/// # });
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MockNetwork {
+    state: Arc<Mutex<NetworkState>>,
+    // Notified when the daemon stops
+    stopped: Arc<Notify>,
+}
+
+#[derive(Debug, Default)]
+struct NetworkState {
+    next_id: u64,
+    latency: Duration,
+    services: Vec<Published>,
+    browsers: Vec<Browser>,
+    hosts: Vec<(String, Vec<IpAddr>)>,
+    lookups: Vec<Lookup>,
+    conflicts: Vec<(String, String)>,
+    domains: Vec<(String, DomainKind)>,
+    records: Vec<ResourceRecord>,
+    registered: Vec<RegisteredRecord>,
+    errors: Vec<(OpKind, BonjourError)>,
+    reconfirmed: Vec<ResourceRecord>,
+    stopped: bool,
+}
+
+// A service published on the network
+#[derive(Debug)]
+struct Published {
+    id: u64,
+    service: Service,
+    domain: String,
+    hosttarget: String,
+    // Records added to the service, with their identifiers and TTLs
+    records: Vec<(u64, RecordData, u32)>,
+}
+
+// A record registered individually by an operation
+#[derive(Debug)]
+struct RegisteredRecord {
+    id: u64,
+    record_id: u64,
+    record: ResourceRecord,
+}
+
+// A browse operation waiting for services to be published
+#[derive(Debug)]
+struct Browser {
+    id: u64,
+    interface: Interface,
+    service_type: String,
+    subtype: Option<String>,
+    domain: String,
+    tx: mpsc::UnboundedSender<BrowseEvent>,
+}
+
+// An address lookup waiting for addresses to change
+#[derive(Debug)]
+struct Lookup {
+    id: u64,
+    hostname: String,
+    protocol: Protocol,
+    tx: mpsc::UnboundedSender<Vec<AddrEvent>>,
+}
+
+impl MockNetwork {
+    /// Create a new, empty, network.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Delay every subsequent operation by `latency` before it reports its
+    /// first result.
+    ///
+    /// By default operations complete without any delay.
+    pub fn set_latency(&self, latency: Duration) {
+        self.state().latency = latency;
+    }
+
+    /// Simulate another host using the name `name` for services of type
+    /// `service_type`, so that publishing a service with the same name
+    /// results in a name conflict.
+    pub fn add_conflict(&self, name: &str, service_type: &str) {
+        self.state()
+            .conflicts
+            .push((name.to_string(), normalise(service_type)));
+    }
+
+    /// Fail the next operation of kind `kind` with `error`.
+    ///
+    /// Errors are queued, so injecting multiple errors for the same kind of
+    /// operation fails that many operations.
+    ///
+    /// # Examples
+    /// ```
+    /// # tokio_test::block_on(async {
+    /// use async_zeroconf::backend::{MockBackend, MockNetwork};
+    /// use async_zeroconf::{BonjourError, OpKind, ZeroconfError};
+    /// use std::sync::Arc;
+    ///
+    /// let network = MockNetwork::new();
+    /// network.inject_error(OpKind::Publish, BonjourError::Refused);
+    ///
+    /// let result = async_zeroconf::Service::new("Server", "_http._tcp", 80)
+    ///                  .set_backend(Arc::new(MockBackend::new(&network)))
+    ///                  .publish().await;
+    /// assert!(matches!(result, Err(ZeroconfError::Bonjour(BonjourError::Refused))));
+    /// # });
+    /// ```
+    pub fn inject_error(&self, kind: OpKind, error: BonjourError) {
+        self.state().errors.push((kind, error));
+    }
+
+    /// Get the records that have been reconfirmed, for example with
+    /// [`Service::reconfirm`], in the order they were reconfirmed.
+    pub fn reconfirmed(&self) -> Vec<ResourceRecord> {
+        self.state().reconfirmed.clone()
+    }
+
+    /// Simulate the daemon stopping. Every operation in progress fails with
+    /// [`BonjourError::ServiceNotRunning`] and everything published on the
+    /// network is removed. New operations fail in the same way until
+    /// [`MockNetwork::start_daemon`] is called.
+    pub fn stop_daemon(&self) {
+        let mut state = self.state();
+        log::debug!("Mock daemon stopped");
+        state.stopped = true;
+        state.services.clear();
+        state.browsers.clear();
+        state.lookups.clear();
+        state.registered.clear();
+        self.stopped.notify_waiters();
+    }
+
+    /// Simulate the daemon starting again after
+    /// [`MockNetwork::stop_daemon`], so that new operations succeed.
+    pub fn start_daemon(&self) {
+        log::debug!("Mock daemon started");
+        self.state().stopped = false;
+    }
+
+    /// Add the domain `domain` to the domains of kind `kind` reported by
+    /// domain enumeration, in addition to the default domain `local.`.
+    pub fn add_domain(&self, domain: &str, kind: DomainKind) {
+        self.state()
+            .domains
+            .push((format!("{}.", normalise(domain)), kind));
+    }
+
+    /// Add `record` to the records found by record queries.
+    pub fn add_record(&self, record: ResourceRecord) {
+        self.state().records.push(record);
+    }
+
+    /// Set the addresses of the host `hostname` (e.g. `"mock.local"`),
+    /// replacing any previous addresses. Address lookups in progress are
+    /// notified of any addresses added or removed.
+    ///
+    /// Hosts have no addresses unless they are set.
+    pub fn set_addresses(&self, hostname: &str, addresses: &[IpAddr]) {
+        let mut state = self.state();
+        let hostname = normalise(hostname);
+
+        let previous = match state.hosts.iter().position(|(h, _)| *h == hostname) {
+            Some(i) => state.hosts.remove(i).1,
+            None => Vec::new(),
+        };
+        let mut events: Vec<AddrEvent> = previous
+            .iter()
+            .filter(|a| !addresses.contains(a))
+            .map(|a| AddrEvent::Removed(*a))
+            .collect();
+        events.extend(
+            addresses
+                .iter()
+                .filter(|a| !previous.contains(a))
+                .map(|a| AddrEvent::Added(*a)),
+        );
+
+        for lookup in state.lookups.iter().filter(|l| l.hostname == hostname) {
+            let events = events
+                .iter()
+                .filter(|e| match e {
+                    AddrEvent::Added(a) | AddrEvent::Removed(a) => lookup.protocol.matches(a),
+                })
+                .copied()
+                .collect::<Vec<_>>();
+            if !events.is_empty() && lookup.tx.send(events).is_err() {
+                log::warn!("Failed to send address events, lookup dropped");
+            }
+        }
+
+        state.hosts.push((hostname, addresses.to_vec()));
+    }
+
+    /// Get the services currently published on the network, with the names
+    /// they were registered with after any renaming.
+    pub fn services(&self) -> Vec<Service> {
+        self.state()
+            .services
+            .iter()
+            .map(|p| p.service.clone())
+            .collect()
+    }
+
+    fn state(&self) -> MutexGuard<'_, NetworkState> {
+        lock(&self.state)
+    }
+
+    // Fail if the daemon isn't running
+    fn check_running(&self) -> Result<(), ZeroconfError> {
+        if self.state().stopped {
+            Err(BonjourError::ServiceNotRunning.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    // Run the operation `future` until it completes or the daemon stops
+    fn until_stopped<F>(&self, future: F) -> impl Future<Output = Result<(), ZeroconfError>>
+    where
+        F: Future<Output = Result<(), ZeroconfError>>,
+    {
+        let network = self.clone();
+        async move {
+            let stopped = network.stopped.notified();
+            network.check_running()?;
+            tokio::select! {
+                r = future => r,
+                _ = stopped => Err(BonjourError::ServiceNotRunning.into()),
+            }
+        }
+    }
+
+    fn latency(&self) -> Duration {
+        self.state().latency
+    }
+
+    fn take_error(&self, kind: OpKind) -> Option<BonjourError> {
+        let mut state = self.state();
+        let index = state.errors.iter().position(|(k, _)| *k == kind)?;
+        Some(state.errors.remove(index).1)
+    }
+
+    // Add a service to the network, renaming it if allowed and there is a
+    // conflict, and notify any matching browsers
+    fn publish(
+        &self,
+        service: &Service,
+        host: &str,
+    ) -> Result<(Registration, RegisteredService), BonjourError> {
+        let mut state = self.state();
+        let service_type = normalise(service.service_type());
+        let domain = service
+            .domain()
+            .as_deref()
+            .map(normalise)
+            .unwrap_or_else(|| DEFAULT_DOMAIN.to_string());
+
+        let base_name = service.name().to_string();
+        let mut name = base_name.clone();
+        let mut rename_count = 1;
+        let interface = *service.interface();
+        while state.name_in_use(&name, &service_type, &domain, interface) {
+            if !service.allow_rename() {
+                return Err(BonjourError::NameConflict);
+            }
+            rename_count += 1;
+            name = format!("{} ({})", base_name, rename_count);
+            log::debug!("Name conflict, renaming to '{}'", name);
+        }
+
+        let mut published = Service::new_with_txt(
+            &name,
+            service.service_type(),
+            service.port(),
+            service.txt().clone(),
+        );
+        published
+            .set_interface(*service.interface())
+            .set_domain(domain.clone());
+        if let Some(host) = service.host() {
+            published.set_host(host.clone());
+        }
+        for subtype in service.subtypes() {
+            published.add_subtype(subtype);
+        }
+
+        let host = service.host().as_deref().unwrap_or(host);
+        let hosttarget = if host.ends_with('.') {
+            host.to_string()
+        } else {
+            format!("{}.{}.", host, domain)
+        };
+
+        state.next_id += 1;
+        let id = state.next_id;
+        log::debug!("Mock publish {} ({})", published, id);
+
+        for browser in state.browsers.iter() {
+            if browser.matches(&published, &service_type, &domain)
+                && !state.type_in_use(browser, &service_type, &domain)
+            {
+                let found = browser.result(&published, &domain);
+                if browser.tx.send(BrowseEvent::Added(found)).is_err() {
+                    log::warn!("Failed to send Service, browser dropped");
+                }
+            }
+        }
+
+        let registered = RegisteredService::new(
+            &name,
+            &format!("{}.", service.service_type().trim_end_matches('.')),
+            &format!("{}.", domain),
+        );
+        state.services.push(Published {
+            id,
+            service: published,
+            domain,
+            hosttarget,
+            records: Vec::new(),
+        });
+
+        let registration = Registration {
+            network: self.clone(),
+            id,
+        };
+        Ok((registration, registered))
+    }
+
+    // Add a browser to the network, returning the matching services already
+    // published
+    fn browse(
+        &self,
+        interface: Interface,
+        service_type: &str,
+        domain: Option<&str>,
+        tx: mpsc::UnboundedSender<BrowseEvent>,
+    ) -> (Registration, Vec<Service>) {
+        let mut state = self.state();
+        let (subtype, service_type) = split_subtype(service_type);
+        let domain = domain
+            .map(normalise)
+            .unwrap_or_else(|| DEFAULT_DOMAIN.to_string());
+
+        state.next_id += 1;
+        let browser = Browser {
+            id: state.next_id,
+            interface,
+            service_type: normalise(service_type),
+            subtype: subtype.map(|s| s.to_string()),
+            domain,
+            tx,
+        };
+
+        let mut existing: Vec<Service> = Vec::new();
+        for p in state.services.iter() {
+            if browser.matches(&p.service, &normalise(p.service.service_type()), &p.domain) {
+                let found = browser.result(&p.service, &p.domain);
+                // Only report each service type once to the meta-query
+                if !existing.contains(&found) {
+                    existing.push(found);
+                }
+            }
+        }
+
+        let registration = Registration {
+            network: self.clone(),
+            id: browser.id,
+        };
+        state.browsers.push(browser);
+
+        (registration, existing)
+    }
+
+    // Add an address lookup to the network, returning the matching addresses
+    // already known
+    fn get_addr_info(
+        &self,
+        hostname: &str,
+        protocol: Protocol,
+        tx: mpsc::UnboundedSender<Vec<AddrEvent>>,
+    ) -> (Registration, Vec<IpAddr>) {
+        let mut state = self.state();
+        let hostname = normalise(hostname);
+
+        let existing = state
+            .hosts
+            .iter()
+            .filter(|(h, _)| *h == hostname)
+            .flat_map(|(_, addresses)| addresses.iter())
+            .filter(|a| protocol.matches(a))
+            .copied()
+            .collect();
+
+        state.next_id += 1;
+        let id = state.next_id;
+        state.lookups.push(Lookup {
+            id,
+            hostname,
+            protocol,
+            tx,
+        });
+
+        (
+            Registration {
+                network: self.clone(),
+                id,
+            },
+            existing,
+        )
+    }
+
+    // Find the information for a service found by a browser
+    fn resolve(&self, service: &Service) -> Option<ResolverInformation> {
+        let service_type = normalise(service.service_type());
+        let domain = service
+            .domain()
+            .as_deref()
+            .map(normalise)
+            .unwrap_or_else(|| DEFAULT_DOMAIN.to_string());
+
+        let state = self.state();
+        let published = state.services.iter().find(|p| {
+            overlaps(*p.service.interface(), *service.interface())
+                && p.service.name().eq_ignore_ascii_case(service.name())
+                && normalise(p.service.service_type()) == service_type
+                && p.domain == domain
+        })?;
+
+        let fullname = ServiceInstanceName::new(published.service.name(), &service_type, &domain);
+        Some(ResolverInformation::new(
+            *service.interface(),
+            &fullname.to_string(),
+            &published.hosttarget,
+            published.service.port(),
+            published.service.txt().clone(),
+        ))
+    }
+
+    // Replace the TXT record of a published service
+    fn update_txt(&self, id: u64, txt: TxtRecord) {
+        let mut state = self.state();
+        if let Some(published) = state.services.iter_mut().find(|p| p.id == id) {
+            log::debug!("Mock update TXT {} ({})", published.service, id);
+            published.service.set_txt(txt);
+        }
+    }
+
+    // Add a record to a published service, returning the identifier of the
+    // record
+    fn add_service_record(
+        &self,
+        id: u64,
+        data: RecordData,
+        ttl: u32,
+    ) -> Result<u64, ZeroconfError> {
+        let mut state = self.state();
+        state.next_id += 1;
+        let record_id = state.next_id;
+        let published = state
+            .services
+            .iter_mut()
+            .find(|p| p.id == id)
+            .ok_or(BonjourError::BadReference)?;
+        log::debug!("Mock add record {} ({})", published.service, record_id);
+        published.records.push((record_id, data, ttl));
+        Ok(record_id)
+    }
+
+    // Remove a record added to a published service
+    fn remove_service_record(&self, id: u64, record_id: u64) {
+        let mut state = self.state();
+        if let Some(published) = state.services.iter_mut().find(|p| p.id == id) {
+            log::debug!("Mock remove record {} ({})", published.service, record_id);
+            published.records.retain(|r| r.0 != record_id);
+        }
+    }
+
+    // Add an operation registering records to the network
+    fn register_records(&self) -> Registration {
+        let mut state = self.state();
+        state.next_id += 1;
+        Registration {
+            network: self.clone(),
+            id: state.next_id,
+        }
+    }
+
+    // Register a record for the operation `id`, returning the identifier of
+    // the record. A unique record conflicts with any other record with the
+    // same name and type.
+    fn register_record(
+        &self,
+        id: u64,
+        record: ResourceRecord,
+        unique: bool,
+    ) -> Result<u64, ZeroconfError> {
+        let mut state = self.state();
+        let name = normalise(record.name());
+        let conflict = state
+            .records
+            .iter()
+            .chain(state.registered.iter().map(|r| &r.record))
+            .any(|r| {
+                normalise(r.name()) == name
+                    && r.rrtype() == record.rrtype()
+                    && r.data() != record.data()
+            });
+        if unique && conflict {
+            return Err(BonjourError::NameConflict.into());
+        }
+
+        state.next_id += 1;
+        let record_id = state.next_id;
+        log::debug!("Mock register record {} ({})", record, record_id);
+        state.registered.push(RegisteredRecord {
+            id,
+            record_id,
+            record,
+        });
+        Ok(record_id)
+    }
+
+    // Remove a record registered individually
+    fn remove_registered_record(&self, record_id: u64) {
+        log::debug!("Mock remove record ({})", record_id);
+        self.state().registered.retain(|r| r.record_id != record_id);
+    }
+
+    // Find the records with the full domain name `name`, including those
+    // registered individually or added to published services
+    fn find_records(&self, name: &str) -> Vec<ResourceRecord> {
+        let state = self.state();
+        let mut records: Vec<_> = state
+            .records
+            .iter()
+            .chain(state.registered.iter().map(|r| &r.record))
+            .filter(|r| normalise(r.name()) == name)
+            .cloned()
+            .collect();
+        for published in state.services.iter() {
+            let fullname = ServiceInstanceName::new(
+                published.service.name(),
+                &normalise(published.service.service_type()),
+                &published.domain,
+            )
+            .to_string();
+            if normalise(&fullname) == name {
+                records.extend(published.records.iter().map(|(_, data, ttl)| {
+                    ResourceRecord::new(&fullname, RecordQuery::CLASS_IN, Some(*ttl), data.clone())
+                }));
+            }
+        }
+        records
+    }
+
+    fn remove(&self, id: u64) {
+        let mut state = self.state();
+        if let Some(index) = state.services.iter().position(|p| p.id == id) {
+            let published = state.services.remove(index);
+            log::debug!("Mock unpublish {} ({})", published.service, id);
+
+            let service_type = normalise(published.service.service_type());
+            for browser in state.browsers.iter() {
+                if browser.matches(&published.service, &service_type, &published.domain)
+                    && !state.type_in_use(browser, &service_type, &published.domain)
+                {
+                    let removed = browser.result(&published.service, &published.domain);
+                    if browser.tx.send(BrowseEvent::Removed(removed)).is_err() {
+                        log::warn!("Failed to send Service, browser dropped");
+                    }
+                }
+            }
+        }
+        state.browsers.retain(|b| b.id != id);
+        state.lookups.retain(|l| l.id != id);
+        state.registered.retain(|r| r.id != id);
+    }
+}
+
+impl NetworkState {
+    fn name_in_use(
+        &self,
+        name: &str,
+        service_type: &str,
+        domain: &str,
+        interface: Interface,
+    ) -> bool {
+        let conflict = self
+            .conflicts
+            .iter()
+            .any(|(n, t)| n.eq_ignore_ascii_case(name) && t == service_type);
+        conflict
+            || self.services.iter().any(|p| {
+                overlaps(*p.service.interface(), interface)
+                    && p.service.name().eq_ignore_ascii_case(name)
+                    && normalise(p.service.service_type()) == service_type
+                    && p.domain == domain
+            })
+    }
+
+    // Check if a browser using the meta-query can already see a service of
+    // the type `service_type`, so the type shouldn't be reported again
+    fn type_in_use(&self, browser: &Browser, service_type: &str, domain: &str) -> bool {
+        browser.service_type == SERVICE_TYPE_QUERY
+            && self.services.iter().any(|p| {
+                browser.matches(&p.service, service_type, domain)
+                    && normalise(p.service.service_type()) == service_type
+                    && p.domain == domain
+            })
+    }
+}
+
+impl Browser {
+    fn matches(&self, service: &Service, service_type: &str, domain: &str) -> bool {
+        overlaps(self.interface, *service.interface())
+            && (self.service_type == service_type || self.service_type == SERVICE_TYPE_QUERY)
+            && self.domain == domain
+            && match &self.subtype {
+                Some(subtype) => service
+                    .subtypes()
+                    .iter()
+                    .any(|s| s.eq_ignore_ascii_case(subtype)),
+                None => true,
+            }
+    }
+
+    // Format a service as reported by this browser
+    fn result(&self, service: &Service, domain: &str) -> Service {
+        if self.service_type == SERVICE_TYPE_QUERY {
+            // The meta-query reports the service type with its first label
+            // as the name, in the same way as `DNSServiceBrowse`
+            let service_type = service.service_type().trim_end_matches('.');
+            let (name, protocol) = service_type.split_once('.').unwrap_or((service_type, ""));
+            let mut found = Service::new(name, &format!("{}.", protocol), 0);
+            found
+                .set_interface(*service.interface())
+                .set_domain(format!("{}.", domain));
+            found
+        } else {
+            browse_result(service, domain)
+        }
+    }
+}
+
+// Keeps a service or browser on the network until dropped
+#[derive(Debug)]
+struct Registration {
+    network: MockNetwork,
+    id: u64,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.network.remove(self.id);
+    }
+}
+
+// Check if two interfaces could see the same services
+fn overlaps(a: Interface, b: Interface) -> bool {
+    match (a, b) {
+        (Interface::Interface(a), Interface::Interface(b)) => a == b,
+        _ => true,
+    }
+}
+
+// Strip any trailing dot and convert to lowercase for comparison
+fn normalise(s: &str) -> String {
+    s.trim_end_matches('.').to_ascii_lowercase()
+}
+
+// Format a service as reported by a browse operation
+fn browse_result(service: &Service, domain: &str) -> Service {
+    let service_type = format!("{}.", service.service_type().trim_end_matches('.'));
+    let mut found = Service::new(service.name(), &service_type, 0);
+    found
+        .set_interface(*service.interface())
+        .set_domain(format!("{}.", domain));
+    found
+}
+
+/// `MockBackend` implements the `ZeroConf` operations on a [`MockNetwork`],
+/// for deterministic tests that don't require a daemon.
+///
+/// Each backend behaves as a separate host, named `mock` unless a service
+/// sets a host. The addresses of hosts are set with
+/// [`MockNetwork::set_addresses`].
+///
+/// Services that are not found when resolving produce a
+/// [`ZeroconfError::Timeout`] immediately, rather than waiting for the timeout
+/// of the [`ServiceResolver`][`crate::ServiceResolver`].
+#[derive(Debug, Clone)]
+pub struct MockBackend {
+    network: MockNetwork,
+    host: String,
+}
+
+impl MockBackend {
+    /// Create a new `MockBackend` on `network`.
+    pub fn new(network: &MockNetwork) -> Self {
+        MockBackend {
+            network: network.clone(),
+            host: DEFAULT_HOST.to_string(),
+        }
+    }
+
+    /// Set the name of the host used for services that don't set a host.
+    pub fn set_host(&mut self, host: &str) -> &mut Self {
+        self.host = host.to_string();
+        self
+    }
+}
+
+impl Backend for MockBackend {
+    fn register(
+        &self,
+        service: &Service,
+        context: PublishContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        self.network.check_running()?;
+        let network = self.network.clone();
+        let latency = network.latency();
+        let service = service.clone();
+        let host = self.host.clone();
+        let op_type = OpType::new(service.service_type(), OpKind::Publish);
+        let (requests_tx, mut requests) = mpsc::unbounded_channel();
+
+        Ok(TaskOperation::start_registration(
+            self.network.until_stopped(async move {
+                tokio::time::sleep(latency).await;
+                if let Some(e) = network.take_error(OpKind::Publish) {
+                    context.send(Err(e));
+                    return Ok(());
+                }
+
+                let (registration, registered) = match network.publish(&service, &host) {
+                    Ok(r) => r,
+                    Err(e) => {
+                        context.send(Err(e));
+                        return Ok(());
+                    }
+                };
+                context.send_registered(
+                    registered.name(),
+                    registered.service_type(),
+                    registered.domain(),
+                );
+
+                // Keep the service published until the operation is dropped
+                while let Some(request) = requests.recv().await {
+                    match request {
+                        RegistrationRequest::UpdateTxt(txt, tx) => {
+                            network.update_txt(registration.id, txt);
+                            if tx.send(Ok(())).is_err() {
+                                log::warn!("Failed to send TXT update result, receiver dropped");
+                            }
+                        }
+                        RegistrationRequest::AddRecord(rrtype, rdata, ttl, tx) => {
+                            let data = RecordData::from_rdata(rrtype.into(), &rdata);
+                            let result = network.add_service_record(registration.id, data, ttl);
+                            if tx.send(result).is_err() {
+                                log::warn!("Failed to send added record, receiver dropped");
+                            }
+                        }
+                        RegistrationRequest::RemoveRecord(id) => {
+                            network.remove_service_record(registration.id, id)
+                        }
+                        RegistrationRequest::RegisterRecord(_, _, tx) => {
+                            if tx.send(Err(BonjourError::Unsupported.into())).is_err() {
+                                log::warn!("Failed to send registered record, receiver dropped");
+                            }
+                        }
+                    }
+                }
+                futures::future::pending().await
+            }),
+            op_type,
+            requests_tx,
+        ))
+    }
+
+    fn browse(
+        &self,
+        interface: &Interface,
+        service_type: &str,
+        domain: Option<&str>,
+        context: BrowseContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        self.network.check_running()?;
+        let network = self.network.clone();
+        let latency = network.latency();
+        let interface = *interface;
+        let regtype = service_type.to_string();
+        let domain = domain.map(|d| d.to_string());
+
+        Ok(TaskOperation::start(
+            self.network.until_stopped(async move {
+                tokio::time::sleep(latency).await;
+                if let Some(e) = network.take_error(OpKind::Browse) {
+                    context.send(Err(e.into()), false);
+                    return Ok(());
+                }
+
+                let (tx, mut rx) = mpsc::unbounded_channel();
+                let (_registration, existing) =
+                    network.browse(interface, &regtype, domain.as_deref(), tx);
+
+                let count = existing.len();
+                for (i, service) in existing.into_iter().enumerate() {
+                    context.send(Ok(service), i + 1 == count);
+                }
+                while let Some(event) = rx.recv().await {
+                    match event {
+                        BrowseEvent::Added(s) => context.send(Ok(s), true),
+                        BrowseEvent::Removed(s) => context.send_removed(s, true),
+                    }
+                }
+                Ok(())
+            }),
+            OpType::new(service_type, OpKind::Browse),
+        ))
+    }
+
+    fn resolve(
+        &self,
+        service: &Service,
+        context: ResolveContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        self.network.check_running()?;
+        let network = self.network.clone();
+        let latency = network.latency();
+        let service = service.clone();
+        let op_type = OpType::new(service.service_type(), OpKind::Resolve);
+
+        Ok(TaskOperation::start(
+            self.network.until_stopped(async move {
+                tokio::time::sleep(latency).await;
+                if let Some(e) = network.take_error(OpKind::Resolve) {
+                    context.send(Err(e.into()));
+                    return Ok(());
+                }
+
+                match network.resolve(&service) {
+                    Some(info) => context.send(Ok(info)),
+                    None => log::debug!("Mock resolve, {} not found", service),
+                }
+                Ok(())
+            }),
+            op_type,
+        ))
+    }
+
+    fn get_addr_info(
+        &self,
+        _interface: &Interface,
+        protocol: Protocol,
+        hostname: &str,
+        context: AddrContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        self.network.check_running()?;
+        let network = self.network.clone();
+        let latency = network.latency();
+        let host = hostname.to_string();
+
+        Ok(TaskOperation::start(
+            self.network.until_stopped(async move {
+                tokio::time::sleep(latency).await;
+                if let Some(e) = network.take_error(OpKind::GetAddrInfo) {
+                    context.send(Err(e.into()), false);
+                    return Ok(());
+                }
+
+                let (tx, mut rx) = mpsc::unbounded_channel();
+                let (_registration, existing) = network.get_addr_info(&host, protocol, tx);
+
+                let count = existing.len();
+                for (i, address) in existing.into_iter().enumerate() {
+                    context.send(Ok(AddrEvent::Added(address)), i + 1 == count);
+                }
+                while let Some(events) = rx.recv().await {
+                    let count = events.len();
+                    for (i, event) in events.into_iter().enumerate() {
+                        context.send(Ok(event), i + 1 == count);
+                    }
+                }
+                Ok(())
+            }),
+            OpType::new(hostname, OpKind::GetAddrInfo),
+        ))
+    }
+
+    fn enumerate_domains(
+        &self,
+        _interface: &Interface,
+        kind: DomainKind,
+        context: DomainContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        self.network.check_running()?;
+        let network = self.network.clone();
+        let latency = network.latency();
+
+        Ok(TaskOperation::start(
+            self.network.until_stopped(async move {
+                tokio::time::sleep(latency).await;
+                if let Some(e) = network.take_error(OpKind::EnumerateDomains) {
+                    context.send(Err(e.into()));
+                    return Ok(());
+                }
+
+                let mut domains = vec![Domain::new(&format!("{}.", DEFAULT_DOMAIN), true)];
+                domains.extend(
+                    network
+                        .state()
+                        .domains
+                        .iter()
+                        .filter(|(_, k)| *k == kind)
+                        .map(|(d, _)| Domain::new(d, false)),
+                );
+                for domain in domains {
+                    context.send(Ok(DomainEvent::Added(domain)));
+                }
+
+                // The domains don't change, so wait until dropped
+                futures::future::pending::<()>().await;
+                Ok(())
+            }),
+            OpType::new(&kind.to_string(), OpKind::EnumerateDomains),
+        ))
+    }
+
+    fn query_record(
+        &self,
+        _interface: &Interface,
+        fullname: &str,
+        rrtype: RecordType,
+        rrclass: u16,
+        context: RecordContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        self.network.check_running()?;
+        let network = self.network.clone();
+        let latency = network.latency();
+        let name = normalise(fullname);
+
+        Ok(TaskOperation::start(
+            self.network.until_stopped(async move {
+                tokio::time::sleep(latency).await;
+                if let Some(e) = network.take_error(OpKind::QueryRecord) {
+                    context.send(Err(e.into()));
+                    return Ok(());
+                }
+
+                let records = network.find_records(&name).into_iter().filter(|r| {
+                    r.rrclass() == rrclass && (rrtype == RecordType::Any || r.rrtype() == rrtype)
+                });
+                for record in records {
+                    context.send(Ok(RecordEvent::Added(record)));
+                }
+
+                // The records don't change, so wait until dropped
+                futures::future::pending::<()>().await;
+                Ok(())
+            }),
+            OpType::new(fullname, OpKind::QueryRecord),
+        ))
+    }
+
+    fn register_records(
+        &self,
+        interface: &Interface,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        self.network.check_running()?;
+        let network = self.network.clone();
+        let latency = network.latency();
+        let (requests_tx, mut requests) = mpsc::unbounded_channel();
+
+        Ok(TaskOperation::start_registration(
+            self.network.until_stopped(async move {
+                let registration = network.register_records();
+                while let Some(request) = requests.recv().await {
+                    match request {
+                        RegistrationRequest::RegisterRecord(record, unique, tx) => {
+                            tokio::time::sleep(latency).await;
+                            let result = match network.take_error(OpKind::RegisterRecord) {
+                                Some(e) => Err(e.into()),
+                                None => network.register_record(registration.id, record, unique),
+                            };
+                            if tx.send(result).is_err() {
+                                log::warn!("Failed to send registered record, receiver dropped");
+                            }
+                        }
+                        RegistrationRequest::RemoveRecord(id) => {
+                            network.remove_registered_record(id)
+                        }
+                        RegistrationRequest::UpdateTxt(_, tx) => {
+                            if tx.send(Err(BonjourError::Unsupported.into())).is_err() {
+                                log::warn!("Failed to send TXT update result, receiver dropped");
+                            }
+                        }
+                        RegistrationRequest::AddRecord(_, _, _, tx) => {
+                            if tx.send(Err(BonjourError::Unsupported.into())).is_err() {
+                                log::warn!("Failed to send added record, receiver dropped");
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            }),
+            OpType::new(&interface.to_string(), OpKind::RegisterRecord),
+            requests_tx,
+        ))
+    }
+
+    fn reconfirm_record(
+        &self,
+        _interface: &Interface,
+        record: &ResourceRecord,
+    ) -> Result<(), ZeroconfError> {
+        self.network.check_running()?;
+        log::debug!("Mock reconfirm record {}", record);
+        self.network.state().reconfirmed.push(record.clone());
+        Ok(())
+    }
+}
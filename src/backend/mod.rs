@@ -0,0 +1,519 @@
+//! Backends provide the implementation of the `ZeroConf` operations used by
+//! [`Service`], [`ServiceBrowserBuilder`][`crate::ServiceBrowserBuilder`] and
+//! [`ServiceResolver`][`crate::ServiceResolver`].
+//!
+//! The backend used when none is specified is chosen at compile time by cargo
+//! feature (`bonjour` by default, using `BonjourBackend`, `avahi`, using
+//! `AvahiBackend`, or `native`, using `NativeBackend`) and can be replaced
+//! at runtime with [`set_default`]. Individual operations can also select a
+//! backend with [`Service::set_backend`],
+//! [`ServiceBrowserBuilder::backend`][`crate::ServiceBrowserBuilder::backend`]
+//! or [`ServiceResolver::set_backend`][`crate::ServiceResolver::set_backend`].
+//!
+//! With the `bonjour` feature, `Connection` can be used as the backend of
+//! many operations so that they share a single connection to the daemon,
+//! rather than each opening a socket.
+//!
+//! The `mock` feature provides `MockBackend`, which publishes services on an
+//! in-memory `MockNetwork` for tests that can't rely on a daemon.
+//!
+//! # Examples
+//! ```
+//! # tokio_test::block_on(async {
+//! // Publish a service using the backend selected by cargo feature, or the
+//! // backend set with `set_default`
+//! let backend = async_zeroconf::backend::default_backend()?;
+//! let service_ref = async_zeroconf::Service::new("Server", "_http._tcp", 80)
+//!                       .set_backend(backend)
+//!                       .publish().await?;
+//! # Ok::<(), async_zeroconf::ZeroconfError>(())
+//! # });
+//! ```
+
+#[cfg(feature = "avahi")]
+mod avahi;
+#[cfg(feature = "bonjour")]
+mod bonjour;
+#[cfg(any(test, feature = "mock"))]
+mod mock;
+#[cfg(feature = "native")]
+mod native;
+#[cfg(any(test, feature = "avahi", feature = "mock"))]
+mod task;
+
+#[cfg(feature = "avahi")]
+pub use avahi::AvahiBackend;
+#[cfg(feature = "bonjour")]
+pub use bonjour::{BonjourBackend, Connection};
+#[cfg(any(test, feature = "mock"))]
+pub use mock::{MockBackend, MockNetwork};
+#[cfg(feature = "native")]
+pub use native::NativeBackend;
+
+use crate::service_ref::RegistrationState;
+use crate::sync::{read, write};
+use crate::{
+    AddrEvent, BonjourError, BrowseEvent, DomainEvent, DomainKind, Interface, Protocol,
+    RecordEvent, RecordType, RegisteredService, ResourceRecord, Service, TxtRecord, ZeroconfError,
+};
+
+use std::fmt;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use tokio::sync::mpsc;
+
+/// Future returned by [`BackendOperation::process`].
+pub type ProcessFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<bool, ZeroconfError>> + Send + Sync + 'a>>;
+
+/// Future returned by [`BackendOperation::update_txt`].
+pub type UpdateFuture<'a> = Pin<Box<dyn Future<Output = Result<(), ZeroconfError>> + Send + 'a>>;
+
+/// Future returned by [`BackendOperation::add_record`] and
+/// [`BackendOperation::register_record`], producing the identifier of the
+/// record added.
+pub type AddRecordFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<u64, ZeroconfError>> + Send + 'a>>;
+
+/// The `Backend` trait is implemented by each way of providing the
+/// `ZeroConf` operations, for example the Bonjour API.
+///
+/// Each method starts an operation, returning a [`BackendOperation`] that is
+/// processed until the associated [`ServiceRef`][`crate::ServiceRef`] is
+/// dropped. Results are reported through the context passed to the method.
+pub trait Backend: fmt::Debug + Send + Sync {
+    /// Register `service`, including any of its
+    /// [`subtypes`][`Service::subtypes`], reporting the result of the
+    /// registration to `context`.
+    fn register(
+        &self,
+        service: &Service,
+        context: PublishContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError>;
+
+    /// Browse for services of type `service_type`, reporting each service
+    /// found or removed to `context`.
+    ///
+    /// If browsing for a subtype, `service_type` has the form
+    /// `_printer._sub._http._tcp`.
+    fn browse(
+        &self,
+        interface: &Interface,
+        service_type: &str,
+        domain: Option<&str>,
+        context: BrowseContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError>;
+
+    /// Resolve `service`, which has been found by a browse operation,
+    /// reporting the resolved information to `context`.
+    fn resolve(
+        &self,
+        service: &Service,
+        context: ResolveContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError>;
+
+    /// Look up the addresses of `hostname`, reporting each address added or
+    /// removed to `context`.
+    ///
+    /// The default implementation returns [`BonjourError::Unsupported`] for
+    /// backends that can't look up addresses.
+    fn get_addr_info(
+        &self,
+        _interface: &Interface,
+        _protocol: Protocol,
+        _hostname: &str,
+        _context: AddrContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        Err(BonjourError::Unsupported.into())
+    }
+
+    /// Find the domains of kind `kind`, reporting each domain added or
+    /// removed to `context`.
+    ///
+    /// The default implementation returns [`BonjourError::Unsupported`] for
+    /// backends that can't enumerate domains.
+    fn enumerate_domains(
+        &self,
+        _interface: &Interface,
+        _kind: DomainKind,
+        _context: DomainContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        Err(BonjourError::Unsupported.into())
+    }
+
+    /// Query for the records of type `rrtype` and class `rrclass` with the
+    /// full domain name `fullname`, reporting each record added or removed
+    /// to `context`.
+    ///
+    /// The default implementation returns [`BonjourError::Unsupported`] for
+    /// backends that can't query for records.
+    fn query_record(
+        &self,
+        _interface: &Interface,
+        _fullname: &str,
+        _rrtype: RecordType,
+        _rrclass: u16,
+        _context: RecordContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        Err(BonjourError::Unsupported.into())
+    }
+
+    /// Start an operation that registers individual records on `interface`,
+    /// which are added with [`BackendOperation::register_record`].
+    ///
+    /// The default implementation returns [`BonjourError::Unsupported`] for
+    /// backends that can't register individual records.
+    fn register_records(
+        &self,
+        _interface: &Interface,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        Err(BonjourError::Unsupported.into())
+    }
+
+    /// Ask for `record`, found on `interface`, to be verified so that it is
+    /// removed if it is no longer valid.
+    ///
+    /// The default implementation returns [`BonjourError::Unsupported`] for
+    /// backends that can't reconfirm records.
+    fn reconfirm_record(
+        &self,
+        _interface: &Interface,
+        _record: &ResourceRecord,
+    ) -> Result<(), ZeroconfError> {
+        Err(BonjourError::Unsupported.into())
+    }
+}
+
+/// An operation started by a [`Backend`], which is kept alive until it is
+/// dropped.
+pub trait BackendOperation: fmt::Debug + Send + Sync {
+    /// Wait for any pending events related to the operation and handle them.
+    ///
+    /// Returns `Ok(false)` if the operation has no more events to process.
+    fn process(&self) -> ProcessFuture<'_>;
+
+    /// Replace the TXT record of a registered service with `txt`, which has
+    /// already been validated.
+    ///
+    /// The default implementation returns [`BonjourError::Unsupported`] for
+    /// operations that don't register a service.
+    fn update_txt(&self, _txt: TxtRecord) -> UpdateFuture<'_> {
+        Box::pin(async { Err(BonjourError::Unsupported.into()) })
+    }
+
+    /// Add a record of type `rrtype` with the data `rdata` to a registered
+    /// service, returning an identifier for the record that is unique within
+    /// the operation. The length of `rdata` has already been checked to fit
+    /// in a record.
+    ///
+    /// The default implementation returns [`BonjourError::Unsupported`] for
+    /// operations that don't register a service.
+    fn add_record(&self, _rrtype: RecordType, _rdata: Vec<u8>, _ttl: u32) -> AddRecordFuture<'_> {
+        Box::pin(async { Err(BonjourError::Unsupported.into()) })
+    }
+
+    /// Register `record` with an operation started by
+    /// [`Backend::register_records`], returning an identifier for the record
+    /// that is unique within the operation. The future completes once the
+    /// record is registered, failing with [`BonjourError::NameConflict`] if
+    /// `unique` is set and another host has a record with the same name and
+    /// type.
+    ///
+    /// The default implementation returns [`BonjourError::Unsupported`] for
+    /// other operations.
+    fn register_record(&self, _record: ResourceRecord, _unique: bool) -> AddRecordFuture<'_> {
+        Box::pin(async { Err(BonjourError::Unsupported.into()) })
+    }
+
+    /// Remove the record with the identifier `id` returned by
+    /// [`BackendOperation::add_record`] or
+    /// [`BackendOperation::register_record`].
+    ///
+    /// This is called when a [`RecordRef`][`crate::RecordRef`] is dropped, so
+    /// it must not block waiting for the record to be removed.
+    fn remove_record(&self, _id: u64) -> Result<(), ZeroconfError> {
+        Err(BonjourError::Unsupported.into())
+    }
+}
+
+/// Context used by a [`Backend`] to report the result of registering a
+/// service.
+#[derive(Debug)]
+pub struct PublishContext {
+    tx: mpsc::UnboundedSender<Result<(), BonjourError>>,
+    state: Arc<RegistrationState>,
+}
+
+impl PublishContext {
+    pub(crate) fn new(
+        tx: mpsc::UnboundedSender<Result<(), BonjourError>>,
+        state: Arc<RegistrationState>,
+    ) -> Self {
+        PublishContext { tx, state }
+    }
+
+    /// Report the result of the registration.
+    ///
+    /// [`PublishContext::send_registered`] should be used instead when the
+    /// registration succeeds, so that the registered name is known.
+    pub fn send(&self, e: Result<(), BonjourError>) {
+        if let Err(e) = e {
+            self.state.update(Err(e));
+        }
+        if let Err(e) = self.tx.send(e) {
+            log::debug!("Status not sent, receiver dropped: {}", e);
+        }
+    }
+
+    /// Report that the service has been registered with `name`, in
+    /// `service_type` and `domain`, which may differ from those requested.
+    /// This is reported again each time the service is renamed.
+    pub fn send_registered(&self, name: &str, service_type: &str, domain: &str) {
+        let registered = RegisteredService::new(name, service_type, domain);
+        self.state.update(Ok(registered));
+        if let Err(e) = self.tx.send(Ok(())) {
+            log::debug!("Status not sent, receiver dropped: {}", e);
+        }
+    }
+}
+
+/// Context used by a [`Backend`] to report services found or removed by a
+/// browse operation.
+#[derive(Debug)]
+pub struct BrowseContext {
+    tx: mpsc::UnboundedSender<(Result<BrowseEvent, ZeroconfError>, bool)>,
+    backend: BackendSelection,
+}
+
+impl BrowseContext {
+    pub(crate) fn new(
+        tx: mpsc::UnboundedSender<(Result<BrowseEvent, ZeroconfError>, bool)>,
+        backend: BackendSelection,
+    ) -> Self {
+        BrowseContext { tx, backend }
+    }
+
+    /// Report a service found by the browser. `last` should be set if no
+    /// further events are immediately expected.
+    ///
+    /// The service is marked as being from a browser, so that it can be
+    /// resolved, and will be resolved with the same backend.
+    pub fn send(&self, result: Result<Service, ZeroconfError>, last: bool) {
+        let result = result.map(|service| BrowseEvent::Added(self.mark(service)));
+        if let Err(e) = self.tx.send((result, last)) {
+            log::warn!("Failed to send Service, receiver dropped: {}", e);
+        }
+    }
+
+    /// Report a service previously found by the browser that has been
+    /// removed. `last` should be set if no further events are immediately
+    /// expected.
+    pub fn send_removed(&self, service: Service, last: bool) {
+        let event = BrowseEvent::Removed(self.mark(service));
+        if let Err(e) = self.tx.send((Ok(event), last)) {
+            log::warn!("Failed to send removed Service, receiver dropped: {}", e);
+        }
+    }
+
+    // Mark a service as being from this browser
+    fn mark(&self, mut service: Service) -> Service {
+        service.set_browse().select_backend(self.backend.clone());
+        service
+    }
+}
+
+impl Drop for BrowseContext {
+    fn drop(&mut self) {
+        log::trace!("Dropping BrowseContext");
+    }
+}
+
+/// Context used by a [`Backend`] to report the result of resolving a
+/// service.
+#[derive(Debug)]
+pub struct ResolveContext {
+    tx: mpsc::UnboundedSender<Result<ResolverInformation, ZeroconfError>>,
+}
+
+impl ResolveContext {
+    pub(crate) fn new(
+        tx: mpsc::UnboundedSender<Result<ResolverInformation, ZeroconfError>>,
+    ) -> Self {
+        ResolveContext { tx }
+    }
+
+    /// Report the information obtained by resolving the service.
+    pub fn send(&self, info: Result<ResolverInformation, ZeroconfError>) {
+        if self.tx.send(info).is_err() {
+            log::warn!("Failed to send resolved information, receiver dropped");
+        }
+    }
+}
+
+/// Context used by a [`Backend`] to report the addresses found by looking up
+/// a host.
+#[derive(Debug)]
+pub struct AddrContext {
+    tx: mpsc::UnboundedSender<(Result<AddrEvent, ZeroconfError>, bool)>,
+}
+
+impl AddrContext {
+    pub(crate) fn new(tx: mpsc::UnboundedSender<(Result<AddrEvent, ZeroconfError>, bool)>) -> Self {
+        AddrContext { tx }
+    }
+
+    /// Report an address being added or removed. `last` should be set if no
+    /// further events are immediately expected.
+    pub fn send(&self, event: Result<AddrEvent, ZeroconfError>, last: bool) {
+        if self.tx.send((event, last)).is_err() {
+            log::warn!("Failed to send address event, receiver dropped");
+        }
+    }
+}
+
+/// Context passed to [`Backend::enumerate_domains`], used to report the
+/// domains found.
+#[derive(Debug)]
+pub struct DomainContext {
+    tx: mpsc::UnboundedSender<Result<DomainEvent, ZeroconfError>>,
+}
+
+impl DomainContext {
+    pub(crate) fn new(tx: mpsc::UnboundedSender<Result<DomainEvent, ZeroconfError>>) -> Self {
+        DomainContext { tx }
+    }
+
+    /// Report a domain being added or removed.
+    pub fn send(&self, event: Result<DomainEvent, ZeroconfError>) {
+        if self.tx.send(event).is_err() {
+            log::warn!("Failed to send domain event, receiver dropped");
+        }
+    }
+}
+
+/// Context passed to [`Backend::query_record`], used to report the records
+/// found.
+#[derive(Debug)]
+pub struct RecordContext {
+    tx: mpsc::UnboundedSender<Result<RecordEvent, ZeroconfError>>,
+}
+
+impl RecordContext {
+    pub(crate) fn new(tx: mpsc::UnboundedSender<Result<RecordEvent, ZeroconfError>>) -> Self {
+        RecordContext { tx }
+    }
+
+    /// Report a record being added or removed.
+    pub fn send(&self, event: Result<RecordEvent, ZeroconfError>) {
+        if self.tx.send(event).is_err() {
+            log::warn!("Failed to send record event, receiver dropped");
+        }
+    }
+}
+
+/// Information obtained by resolving a service.
+#[derive(Debug)]
+pub struct ResolverInformation {
+    pub(crate) interface: Interface,
+    pub(crate) fullname: String,
+    pub(crate) hosttarget: String,
+    pub(crate) port: u16,
+    pub(crate) txt_record: TxtRecord,
+}
+
+impl ResolverInformation {
+    /// Create the information for a resolved service, where `fullname` is
+    /// the full domain name of the service instance and `hosttarget` is the
+    /// full domain name of the host providing the service.
+    pub fn new(
+        interface: Interface,
+        fullname: &str,
+        hosttarget: &str,
+        port: u16,
+        txt_record: TxtRecord,
+    ) -> Self {
+        ResolverInformation {
+            interface,
+            fullname: fullname.to_string(),
+            hosttarget: hosttarget.to_string(),
+            port,
+            txt_record,
+        }
+    }
+}
+
+// Backend set at runtime with `set_default`
+static DEFAULT_BACKEND: RwLock<Option<Arc<dyn Backend>>> = RwLock::new(None);
+
+/// Set the backend used by any operation that doesn't select a backend.
+pub fn set_default(backend: Arc<dyn Backend>) {
+    *write(&DEFAULT_BACKEND) = Some(backend);
+}
+
+/// Get the backend used by any operation that doesn't select a backend.
+///
+/// This is the backend set with [`set_default`], or if that hasn't been
+/// called, the backend selected by cargo feature.
+pub fn default_backend() -> Result<Arc<dyn Backend>, ZeroconfError> {
+    let backend = read(&DEFAULT_BACKEND).clone();
+    backend
+        .or_else(builtin_backend)
+        .ok_or(ZeroconfError::NoBackend)
+}
+
+// Backend selected by cargo feature
+fn builtin_backend() -> Option<Arc<dyn Backend>> {
+    #[cfg(feature = "bonjour")]
+    return Some(Arc::new(BonjourBackend::new()));
+
+    #[cfg(all(feature = "avahi", not(feature = "bonjour")))]
+    return Some(Arc::new(AvahiBackend::new()));
+
+    #[cfg(all(feature = "native", not(any(feature = "bonjour", feature = "avahi"))))]
+    return Some(Arc::new(NativeBackend::new()));
+
+    #[cfg(not(any(feature = "bonjour", feature = "avahi", feature = "native")))]
+    None
+}
+
+// Internal type to hold the backend selected for an operation. The backend is
+// not considered part of the identity of a service, so all selections compare
+// equal.
+#[derive(Clone, Default)]
+pub(crate) struct BackendSelection(Option<Arc<dyn Backend>>);
+
+impl BackendSelection {
+    pub fn new(backend: Arc<dyn Backend>) -> Self {
+        BackendSelection(Some(backend))
+    }
+
+    pub fn get(&self) -> Result<Arc<dyn Backend>, ZeroconfError> {
+        match &self.0 {
+            Some(backend) => Ok(backend.clone()),
+            None => default_backend(),
+        }
+    }
+}
+
+impl fmt::Debug for BackendSelection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.0 {
+            Some(backend) => write!(f, "{:?}", backend),
+            None => write!(f, "Default"),
+        }
+    }
+}
+
+impl PartialEq for BackendSelection {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for BackendSelection {}
+
+impl Hash for BackendSelection {
+    fn hash<H: Hasher>(&self, _: &mut H) {}
+}
@@ -0,0 +1,465 @@
+// Minimal DNS message encoding and decoding, as required for multicast DNS
+
+use std::cmp::Ordering;
+use std::convert::TryInto;
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::instance_name::split_labels;
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_PTR: u16 = 12;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_AAAA: u16 = 28;
+pub const TYPE_SRV: u16 = 33;
+pub const TYPE_ANY: u16 = 255;
+
+const CLASS_IN: u16 = 1;
+// The top bit of the class is the unicast-response bit for questions and the
+// cache-flush bit for resource records
+const CLASS_TOP_BIT: u16 = 0x8000;
+
+const FLAG_RESPONSE: u16 = 0x8000;
+const FLAG_AUTHORITATIVE: u16 = 0x0400;
+
+// Limit on compression pointers followed when decoding a name
+const MAX_POINTERS: usize = 64;
+
+/// Maximum length of a label in bytes, from RFC 1035
+pub const MAX_LABEL_LEN: usize = 63;
+
+/// Error when a message cannot be decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError;
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "malformed DNS message")
+    }
+}
+
+/// A domain name, stored as a list of labels. Comparisons are case-insensitive
+/// as required for DNS names.
+#[derive(Debug, Clone, Default)]
+pub struct Name(Vec<String>);
+
+impl Name {
+    /// Create a name from a dot-separated string, ignoring any empty labels.
+    pub fn parse(s: &str) -> Self {
+        Name(
+            s.split('.')
+                .filter(|l| !l.is_empty())
+                .map(|l| l.to_string())
+                .collect(),
+        )
+    }
+
+    /// Create a name from an escaped string, such as the full name of a
+    /// service instance, or `None` if the escaping is invalid.
+    pub fn parse_escaped(s: &str) -> Option<Self> {
+        split_labels(s).map(Name)
+    }
+
+    /// Create a new name with `label` prepended to this name.
+    pub fn prepend(&self, label: &str) -> Self {
+        let mut labels = vec![label.to_string()];
+        labels.extend(self.0.iter().cloned());
+        Name(labels)
+    }
+
+    /// Create a new name with `other` appended to this name.
+    pub fn append(&self, other: &Name) -> Self {
+        let mut labels = self.0.clone();
+        labels.extend(other.0.iter().cloned());
+        Name(labels)
+    }
+
+    /// Get the labels of this name
+    pub fn labels(&self) -> &[String] {
+        &self.0
+    }
+
+    /// Check that no label is longer than can be encoded in a message.
+    pub fn is_valid(&self) -> bool {
+        self.0.iter().all(|l| l.len() <= MAX_LABEL_LEN)
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        debug_assert!(self.is_valid(), "label too long in '{}'", self);
+        for label in &self.0 {
+            buf.push(label.len() as u8);
+            buf.extend_from_slice(label.as_bytes());
+        }
+        buf.push(0);
+    }
+
+    fn decode(msg: &[u8], pos: &mut usize) -> Result<Self, DecodeError> {
+        let mut labels = Vec::new();
+        let mut cur = *pos;
+        let mut end = None;
+        let mut pointers = 0;
+
+        loop {
+            let len = *msg.get(cur).ok_or(DecodeError)? as usize;
+            if len & 0xC0 == 0xC0 {
+                let low = *msg.get(cur + 1).ok_or(DecodeError)? as usize;
+                if end.is_none() {
+                    end = Some(cur + 2);
+                }
+                pointers += 1;
+                if pointers > MAX_POINTERS {
+                    return Err(DecodeError);
+                }
+                cur = ((len & 0x3F) << 8) | low;
+            } else if len == 0 {
+                cur += 1;
+                break;
+            } else {
+                let label = msg.get(cur + 1..cur + 1 + len).ok_or(DecodeError)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                cur += 1 + len;
+            }
+        }
+
+        *pos = end.unwrap_or(cur);
+        Ok(Name(labels))
+    }
+}
+
+impl PartialEq for Name {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len()
+            && self
+                .0
+                .iter()
+                .zip(other.0.iter())
+                .all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+}
+
+impl Eq for Name {}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for label in &self.0 {
+            write!(f, "{}.", label.replace('\\', "\\\\").replace('.', "\\."))?;
+        }
+        Ok(())
+    }
+}
+
+/// A question in a DNS message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Question {
+    pub name: Name,
+    pub qtype: u16,
+    pub unicast: bool,
+}
+
+impl Question {
+    pub fn new(name: Name, qtype: u16) -> Self {
+        Question {
+            name,
+            qtype,
+            unicast: false,
+        }
+    }
+
+    /// Check if a record of type `rtype` answers this question
+    pub fn matches(&self, rtype: u16) -> bool {
+        self.qtype == rtype || self.qtype == TYPE_ANY
+    }
+}
+
+/// The data associated with a resource record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Ptr(Name),
+    Srv {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: Name,
+    },
+    Txt(Vec<u8>),
+    Other(u16, Vec<u8>),
+}
+
+impl RData {
+    pub fn rtype(&self) -> u16 {
+        match self {
+            RData::A(_) => TYPE_A,
+            RData::Aaaa(_) => TYPE_AAAA,
+            RData::Ptr(_) => TYPE_PTR,
+            RData::Srv { .. } => TYPE_SRV,
+            RData::Txt(_) => TYPE_TXT,
+            RData::Other(t, _) => *t,
+        }
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            RData::A(a) => buf.extend_from_slice(&a.octets()),
+            RData::Aaaa(a) => buf.extend_from_slice(&a.octets()),
+            RData::Ptr(n) => n.encode(buf),
+            RData::Srv {
+                priority,
+                weight,
+                port,
+                target,
+            } => {
+                buf.extend_from_slice(&priority.to_be_bytes());
+                buf.extend_from_slice(&weight.to_be_bytes());
+                buf.extend_from_slice(&port.to_be_bytes());
+                target.encode(buf);
+            }
+            RData::Txt(t) => buf.extend_from_slice(t),
+            RData::Other(_, d) => buf.extend_from_slice(d),
+        }
+    }
+
+    /// Get the uncompressed record data
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.encode(&mut buf);
+        buf
+    }
+
+    /// Create the data of a record of type `rtype` from uncompressed record
+    /// data, which is kept as it is if it can't be decoded
+    pub fn from_rdata(rtype: u16, data: Vec<u8>) -> Self {
+        match RData::decode(&data, rtype, 0, data.len()) {
+            Ok(rdata) => rdata,
+            Err(_) => RData::Other(rtype, data),
+        }
+    }
+
+    fn decode(msg: &[u8], rtype: u16, start: usize, len: usize) -> Result<Self, DecodeError> {
+        let data = msg.get(start..start + len).ok_or(DecodeError)?;
+        let mut pos = start;
+        let rdata = match rtype {
+            TYPE_A => {
+                let octets: [u8; 4] = data.try_into().map_err(|_| DecodeError)?;
+                RData::A(octets.into())
+            }
+            TYPE_AAAA => {
+                let octets: [u8; 16] = data.try_into().map_err(|_| DecodeError)?;
+                RData::Aaaa(octets.into())
+            }
+            TYPE_PTR => RData::Ptr(Name::decode(msg, &mut pos)?),
+            TYPE_SRV => {
+                if len < 7 {
+                    return Err(DecodeError);
+                }
+                pos += 6;
+                RData::Srv {
+                    priority: u16::from_be_bytes([data[0], data[1]]),
+                    weight: u16::from_be_bytes([data[2], data[3]]),
+                    port: u16::from_be_bytes([data[4], data[5]]),
+                    target: Name::decode(msg, &mut pos)?,
+                }
+            }
+            TYPE_TXT => RData::Txt(data.to_vec()),
+            _ => RData::Other(rtype, data.to_vec()),
+        };
+        Ok(rdata)
+    }
+}
+
+/// A resource record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub name: Name,
+    pub cache_flush: bool,
+    pub ttl: u32,
+    pub data: RData,
+}
+
+impl Record {
+    pub fn new(name: Name, ttl: u32, data: RData) -> Self {
+        Record {
+            name,
+            cache_flush: false,
+            ttl,
+            data,
+        }
+    }
+
+    /// Create a record for a unique resource, which sets the cache-flush bit
+    pub fn unique(name: Name, ttl: u32, data: RData) -> Self {
+        Record {
+            name,
+            cache_flush: true,
+            ttl,
+            data,
+        }
+    }
+
+    /// Check if this is the same resource as `other`, ignoring the TTL
+    pub fn same_resource(&self, other: &Record) -> bool {
+        self.name == other.name && self.data == other.data
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.name.encode(buf);
+        let class = if self.cache_flush {
+            CLASS_IN | CLASS_TOP_BIT
+        } else {
+            CLASS_IN
+        };
+        buf.extend_from_slice(&self.data.rtype().to_be_bytes());
+        buf.extend_from_slice(&class.to_be_bytes());
+        buf.extend_from_slice(&self.ttl.to_be_bytes());
+        let len_pos = buf.len();
+        buf.extend_from_slice(&[0, 0]);
+        self.data.encode(buf);
+        let len = (buf.len() - len_pos - 2) as u16;
+        buf[len_pos..len_pos + 2].copy_from_slice(&len.to_be_bytes());
+    }
+
+    fn decode(msg: &[u8], pos: &mut usize) -> Result<Self, DecodeError> {
+        let name = Name::decode(msg, pos)?;
+        let rtype = read_u16(msg, pos)?;
+        let class = read_u16(msg, pos)?;
+        let ttl = (u32::from(read_u16(msg, pos)?) << 16) | u32::from(read_u16(msg, pos)?);
+        let len = read_u16(msg, pos)? as usize;
+        let data = RData::decode(msg, rtype, *pos, len)?;
+        *pos += len;
+        Ok(Record {
+            name,
+            cache_flush: class & CLASS_TOP_BIT != 0,
+            ttl,
+            data,
+        })
+    }
+}
+
+/// Compare the records of two hosts probing for the same name at the same
+/// time, as described in RFC 6762 §8.2. The records of each are sorted and
+/// compared in turn by class, type and uncompressed data, and if all of
+/// those match, the host with more records is greater. Only the `IN` class
+/// is used, so the class is not compared.
+pub fn compare_probes(ours: &[&Record], theirs: &[&Record]) -> Ordering {
+    let sorted = |records: &[&Record]| {
+        let mut keys: Vec<(u16, Vec<u8>)> = records
+            .iter()
+            .map(|r| (r.data.rtype(), r.data.to_bytes()))
+            .collect();
+        keys.sort();
+        keys
+    };
+    sorted(ours).cmp(&sorted(theirs))
+}
+
+/// A DNS message
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Message {
+    pub id: u16,
+    pub response: bool,
+    pub questions: Vec<Question>,
+    pub answers: Vec<Record>,
+    pub authorities: Vec<Record>,
+    pub additionals: Vec<Record>,
+}
+
+impl Message {
+    /// Create an empty query
+    pub fn query() -> Self {
+        Message::default()
+    }
+
+    /// Create an empty response
+    pub fn response() -> Self {
+        Message {
+            response: true,
+            ..Default::default()
+        }
+    }
+
+    /// Iterate over all the records in the message
+    pub fn records(&self) -> impl Iterator<Item = &Record> {
+        self.answers
+            .iter()
+            .chain(self.authorities.iter())
+            .chain(self.additionals.iter())
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(512);
+        let flags = if self.response {
+            FLAG_RESPONSE | FLAG_AUTHORITATIVE
+        } else {
+            0
+        };
+        for v in [
+            self.id,
+            flags,
+            self.questions.len() as u16,
+            self.answers.len() as u16,
+            self.authorities.len() as u16,
+            self.additionals.len() as u16,
+        ] {
+            buf.extend_from_slice(&v.to_be_bytes());
+        }
+        for q in &self.questions {
+            q.name.encode(&mut buf);
+            let class = if q.unicast {
+                CLASS_IN | CLASS_TOP_BIT
+            } else {
+                CLASS_IN
+            };
+            buf.extend_from_slice(&q.qtype.to_be_bytes());
+            buf.extend_from_slice(&class.to_be_bytes());
+        }
+        for r in self.records() {
+            r.encode(&mut buf);
+        }
+        buf
+    }
+
+    pub fn decode(msg: &[u8]) -> Result<Self, DecodeError> {
+        let mut pos = 0;
+        let id = read_u16(msg, &mut pos)?;
+        let flags = read_u16(msg, &mut pos)?;
+        let qdcount = read_u16(msg, &mut pos)?;
+        let ancount = read_u16(msg, &mut pos)?;
+        let nscount = read_u16(msg, &mut pos)?;
+        let arcount = read_u16(msg, &mut pos)?;
+
+        let mut questions = Vec::new();
+        for _ in 0..qdcount {
+            let name = Name::decode(msg, &mut pos)?;
+            let qtype = read_u16(msg, &mut pos)?;
+            let class = read_u16(msg, &mut pos)?;
+            questions.push(Question {
+                name,
+                qtype,
+                unicast: class & CLASS_TOP_BIT != 0,
+            });
+        }
+
+        let mut records = |count| -> Result<Vec<Record>, DecodeError> {
+            (0..count).map(|_| Record::decode(msg, &mut pos)).collect()
+        };
+        let answers = records(ancount)?;
+        let authorities = records(nscount)?;
+        let additionals = records(arcount)?;
+
+        Ok(Message {
+            id,
+            response: flags & FLAG_RESPONSE != 0,
+            questions,
+            answers,
+            authorities,
+            additionals,
+        })
+    }
+}
+
+fn read_u16(msg: &[u8], pos: &mut usize) -> Result<u16, DecodeError> {
+    let bytes = msg.get(*pos..*pos + 2).ok_or(DecodeError)?;
+    *pos += 2;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
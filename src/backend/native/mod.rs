@@ -0,0 +1,1601 @@
+// Backend implementing multicast DNS in Rust, without any system daemon
+
+mod dns;
+mod socket;
+
+use dns::{Message, Name, Question, RData, Record, MAX_LABEL_LEN};
+use socket::MdnsSocket;
+
+use crate::backend::{
+    AddRecordFuture, AddrContext, Backend, BackendOperation, BrowseContext, DomainContext,
+    ProcessFuture, PublishContext, RecordContext, ResolveContext, ResolverInformation,
+    UpdateFuture,
+};
+use crate::service_type::split_subtype;
+use crate::service_type_browser::SERVICE_TYPE_QUERY;
+use crate::sync::lock;
+use crate::{
+    AddrEvent, BonjourError, Domain, DomainEvent, DomainKind, Interface, OpKind, OpType, Protocol,
+    RecordData, RecordEvent, RecordQuery, RecordType, ResourceRecord, Service, TxtRecord,
+    ZeroconfError,
+};
+
+use std::cmp::Ordering;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::{oneshot, Notify};
+use tokio::time::Instant;
+
+// TTL for records relating to the host, as recommended by RFC 6762
+const HOST_TTL: u32 = 120;
+// TTL for other records, as recommended by RFC 6762
+const OTHER_TTL: u32 = 4500;
+// Maximum TTL for responses to legacy unicast queries
+const LEGACY_TTL: u32 = 10;
+
+// Number of probes sent before claiming a name
+const PROBE_COUNT: u8 = 3;
+const PROBE_INTERVAL: Duration = Duration::from_millis(250);
+// Number of announcements sent after claiming a name
+const ANNOUNCE_COUNT: u8 = 2;
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+// Delay before probing again after losing a simultaneous probe tie-break
+const PROBE_DEFER: Duration = Duration::from_secs(1);
+// Initial and maximum intervals between repeated queries
+const QUERY_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_QUERY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// `NativeBackend` implements the `ZeroConf` operations with multicast DNS
+/// (RFC 6762) and DNS-SD (RFC 6763) directly in Rust, so does not require
+/// any system daemon such as `mDNSResponder` or Avahi.
+///
+/// Each operation uses its own sockets bound to the mDNS port on IPv4 and
+/// IPv6, so multiple operations and processes on the same host can be used
+/// together. Only the `local` domain is supported.
+///
+/// This is the default backend if the `native` feature is enabled and the
+/// `bonjour` feature is not.
+///
+/// # Examples
+/// ```
+/// # tokio_test::block_on(async {
+/// use std::sync::Arc;
+///
+/// let service_ref = async_zeroconf::Service::new("Server", "_http._tcp", 80)
+///                       .set_backend(Arc::new(async_zeroconf::backend::NativeBackend::new()))
+///                       .publish().await?;
+/// # Ok::<(), async_zeroconf::ZeroconfError>(())
+/// # });
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NativeBackend;
+
+impl NativeBackend {
+    /// Create a new `NativeBackend`.
+    pub fn new() -> Self {
+        NativeBackend
+    }
+}
+
+impl Backend for NativeBackend {
+    fn register(
+        &self,
+        service: &Service,
+        context: PublishContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        let domain = local_domain(service.domain().as_deref())?;
+        let service_type = Name::parse(service.service_type()).append(&domain);
+        let subtypes = service
+            .subtypes()
+            .iter()
+            .map(|s| service_type.prepend("_sub").prepend(s))
+            .collect::<Vec<_>>();
+
+        let (host, own_host) = match service.host() {
+            Some(h) if h.ends_with('.') => (Name::parse(h), false),
+            Some(h) => (Name::parse(h).append(&domain), false),
+            None => (Name::parse(&socket::hostname()).append(&domain), true),
+        };
+        let name = if service.name().is_empty() {
+            socket::hostname()
+        } else {
+            service.name().to_string()
+        };
+        // Labels that are too long are rejected rather than being truncated,
+        // which would publish the service under a different name
+        if name.len() > MAX_LABEL_LEN {
+            log::warn!("Service name '{}' is too long", name);
+            return Err(BonjourError::BadParam.into());
+        }
+        let service_type = valid_name(service_type)?;
+        let subtypes = subtypes
+            .into_iter()
+            .map(valid_name)
+            .collect::<Result<_, _>>()?;
+        let host = valid_name(host)?;
+
+        let state = RegisterState {
+            name: name.clone(),
+            base_name: name,
+            rename_count: 1,
+            service_type,
+            subtypes,
+            domain,
+            host,
+            own_host,
+            port: service.port(),
+            txt: service.txt().to_bytes()?,
+            interface: *service.interface(),
+            allow_rename: service.allow_rename(),
+            extra: Vec::new(),
+            next_record: 0,
+            phase: Phase::Probing(0),
+            next: Some(Instant::now() + random_delay(PROBE_INTERVAL)),
+            context,
+        };
+
+        NativeOperation::start(
+            state,
+            service.interface(),
+            OpType::new(service.service_type(), OpKind::Publish),
+        )
+    }
+
+    fn browse(
+        &self,
+        interface: &Interface,
+        service_type: &str,
+        domain: Option<&str>,
+        context: BrowseContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        let domain = local_domain(domain)?;
+        let (_, regtype) = split_subtype(service_type);
+        // Service types found by the meta-query have no instance name, so
+        // only need the service name and protocol labels
+        let min_labels = if regtype == SERVICE_TYPE_QUERY {
+            2
+        } else {
+            Name::parse(regtype).labels().len() + 1
+        };
+
+        let state = BrowseState {
+            query: valid_name(Name::parse(service_type).append(&domain))?,
+            min_labels: min_labels + domain.labels().len(),
+            domain,
+            interface: *interface,
+            known: Vec::new(),
+            interval: QUERY_INTERVAL,
+            next: Instant::now(),
+            context,
+        };
+
+        NativeOperation::start(state, interface, OpType::new(service_type, OpKind::Browse))
+    }
+
+    fn resolve(
+        &self,
+        service: &Service,
+        context: ResolveContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        let domain = service
+            .domain()
+            .as_deref()
+            .ok_or_else(|| ZeroconfError::NotFromBrowser(service.clone()))?;
+        let domain = local_domain(Some(domain))?;
+
+        let state = ResolveState {
+            instance: valid_name(
+                Name::parse(service.service_type())
+                    .append(&domain)
+                    .prepend(service.name()),
+            )?,
+            interface: *service.interface(),
+            srv: None,
+            txt: None,
+            interval: QUERY_INTERVAL,
+            next: Instant::now(),
+            context,
+        };
+
+        NativeOperation::start(
+            state,
+            service.interface(),
+            OpType::new(service.service_type(), OpKind::Resolve),
+        )
+    }
+
+    fn get_addr_info(
+        &self,
+        interface: &Interface,
+        protocol: Protocol,
+        hostname: &str,
+        context: AddrContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        let state = AddrState {
+            hostname: valid_name(Name::parse(hostname))?,
+            protocol,
+            known: Vec::new(),
+            interval: QUERY_INTERVAL,
+            next: Instant::now(),
+            context,
+        };
+
+        NativeOperation::start(state, interface, OpType::new(hostname, OpKind::GetAddrInfo))
+    }
+
+    fn enumerate_domains(
+        &self,
+        interface: &Interface,
+        kind: DomainKind,
+        context: DomainContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        let state = DomainState {
+            reported: false,
+            context,
+        };
+
+        NativeOperation::start(
+            state,
+            interface,
+            OpType::new(&kind.to_string(), OpKind::EnumerateDomains),
+        )
+    }
+
+    fn query_record(
+        &self,
+        interface: &Interface,
+        fullname: &str,
+        rrtype: RecordType,
+        rrclass: u16,
+        context: RecordContext,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        if rrclass != RecordQuery::CLASS_IN {
+            log::warn!("Class {} not supported by NativeBackend", rrclass);
+            return Err(BonjourError::Unsupported.into());
+        }
+
+        let state = RecordState {
+            name: valid_name(Name::parse_escaped(fullname).ok_or(BonjourError::BadParam)?)?,
+            rrtype: rrtype.into(),
+            known: Vec::new(),
+            interval: QUERY_INTERVAL,
+            next: Instant::now(),
+            context,
+        };
+
+        NativeOperation::start(state, interface, OpType::new(fullname, OpKind::QueryRecord))
+    }
+
+    fn register_records(
+        &self,
+        interface: &Interface,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        let state = RegisterRecordsState {
+            records: Vec::new(),
+            next_record: 0,
+        };
+
+        NativeOperation::start(
+            state,
+            interface,
+            OpType::new(&interface.to_string(), OpKind::RegisterRecord),
+        )
+    }
+}
+
+// Get the domain to use, only the local domain is supported
+fn local_domain(domain: Option<&str>) -> Result<Name, ZeroconfError> {
+    let local = Name::parse("local");
+    match domain {
+        None => Ok(local),
+        Some(d) if Name::parse(d) == local => Ok(local),
+        Some(d) => {
+            log::warn!("Domain '{}' not supported by NativeBackend", d);
+            Err(BonjourError::Unsupported.into())
+        }
+    }
+}
+
+// Check if the records being probed for with the same name lose the
+// tie-break against the records in the authority section of a probe from
+// another host, see RFC 6762 §8.2. A probe that only contains the records
+// being probed for, such as one sent by this host, doesn't conflict.
+fn loses_tie_break(ours: &[&Record], probe: &Message) -> bool {
+    let name = match ours.first() {
+        Some(r) => &r.name,
+        None => return false,
+    };
+    let theirs: Vec<&Record> = probe
+        .authorities
+        .iter()
+        .filter(|r| r.name == *name)
+        .collect();
+    if theirs
+        .iter()
+        .all(|t| ours.iter().any(|o| o.same_resource(t)))
+    {
+        return false;
+    }
+    dns::compare_probes(ours, &theirs) == Ordering::Less
+}
+
+// Check that a name can be encoded, as labels are limited to 63 bytes
+fn valid_name(name: Name) -> Result<Name, ZeroconfError> {
+    if name.is_valid() {
+        Ok(name)
+    } else {
+        log::warn!("Label too long in '{}'", name);
+        Err(BonjourError::BadParam.into())
+    }
+}
+
+// Truncate a label to at most `max` bytes, without splitting a character
+fn truncate_label(label: &str, max: usize) -> &str {
+    let mut end = label.len().min(max);
+    while !label.is_char_boundary(end) {
+        end -= 1;
+    }
+    &label[..end]
+}
+
+// Get a random delay up to `max` to avoid collisions between hosts
+fn random_delay(max: Duration) -> Duration {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0);
+    let millis = max.as_millis() as u64;
+    Duration::from_millis(hasher.finish() % (millis + 1))
+}
+
+// State of an operation, updated on incoming messages and timer events
+trait Handler: Send {
+    // Time of the next timer event, if any
+    fn next(&self) -> Option<Instant>;
+
+    // Handle a timer event, returning false if the operation has finished
+    fn on_timer(&mut self, socket: &MdnsSocket) -> bool;
+
+    // Handle a received message, returning false if the operation has finished
+    fn on_message(&mut self, msg: Message, src: SocketAddr, socket: &MdnsSocket) -> bool;
+
+    // Handle the operation being dropped
+    fn on_drop(&mut self, _socket: &MdnsSocket) {}
+
+    // Replace the TXT record of a registered service
+    fn update_txt(&mut self, _txt: &TxtRecord, _socket: &MdnsSocket) -> Result<(), ZeroconfError> {
+        Err(BonjourError::Unsupported.into())
+    }
+
+    // Add a record to a registered service, returning its identifier
+    fn add_record(
+        &mut self,
+        _rrtype: u16,
+        _rdata: Vec<u8>,
+        _ttl: u32,
+        _socket: &MdnsSocket,
+    ) -> Result<u64, ZeroconfError> {
+        Err(BonjourError::Unsupported.into())
+    }
+
+    // Register an individual record, returning its identifier and a channel
+    // for the result of the registration
+    fn register_record(
+        &mut self,
+        _record: &ResourceRecord,
+        _unique: bool,
+    ) -> Result<(u64, RegisterResult), ZeroconfError> {
+        Err(BonjourError::Unsupported.into())
+    }
+
+    // Remove a record added to a registered service or registered
+    // individually
+    fn remove_record(&mut self, _id: u64, _socket: &MdnsSocket) -> Result<(), ZeroconfError> {
+        Err(BonjourError::Unsupported.into())
+    }
+}
+
+// Channel for the result of registering an individual record
+type RegisterResult = oneshot::Receiver<Result<(), BonjourError>>;
+
+#[derive(Debug)]
+struct NativeOperation<H: Handler> {
+    socket: MdnsSocket,
+    state: Mutex<H>,
+    op_type: OpType,
+    // Notified when the state is changed outside of event processing
+    changed: Notify,
+}
+
+impl<H> NativeOperation<H>
+where
+    H: Handler + std::fmt::Debug + 'static,
+{
+    fn start(
+        state: H,
+        interface: &Interface,
+        op_type: OpType,
+    ) -> Result<Box<dyn BackendOperation>, ZeroconfError> {
+        let socket = MdnsSocket::new(interface)?;
+        log::debug!("Created native operation ({})", op_type);
+        Ok(Box::new(NativeOperation {
+            socket,
+            state: Mutex::new(state),
+            op_type,
+            changed: Notify::new(),
+        }))
+    }
+
+    /// A future to wait for the next message or timer event and handle it
+    async fn process_events(&self) -> Result<bool, ZeroconfError> {
+        let next = lock(&self.state).next();
+        let timer = async {
+            match next {
+                Some(t) => tokio::time::sleep_until(t).await,
+                None => futures::future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            r = self.socket.recv() => {
+                let (msg, src) = r?;
+                Ok(lock(&self.state).on_message(msg, src, &self.socket))
+            }
+            _ = timer => {
+                Ok(lock(&self.state).on_timer(&self.socket))
+            }
+            // Check the time of the next timer event again
+            _ = self.changed.notified() => Ok(true),
+        }
+    }
+}
+
+impl<H> BackendOperation for NativeOperation<H>
+where
+    H: Handler + std::fmt::Debug + 'static,
+{
+    fn process(&self) -> ProcessFuture<'_> {
+        Box::pin(self.process_events())
+    }
+
+    fn update_txt(&self, txt: TxtRecord) -> UpdateFuture<'_> {
+        let result = lock(&self.state).update_txt(&txt, &self.socket);
+        self.changed.notify_one();
+        Box::pin(async { result })
+    }
+
+    fn add_record(&self, rrtype: RecordType, rdata: Vec<u8>, ttl: u32) -> AddRecordFuture<'_> {
+        let result = lock(&self.state).add_record(rrtype.into(), rdata, ttl, &self.socket);
+        Box::pin(async { result })
+    }
+
+    fn register_record(&self, record: ResourceRecord, unique: bool) -> AddRecordFuture<'_> {
+        let result = lock(&self.state).register_record(&record, unique);
+        self.changed.notify_one();
+        Box::pin(async move {
+            let (id, rx) = result?;
+            match rx.await {
+                Ok(Ok(())) => Ok(id),
+                Ok(Err(e)) => Err(e.into()),
+                Err(_) => Err(ZeroconfError::Dropped),
+            }
+        })
+    }
+
+    fn remove_record(&self, id: u64) -> Result<(), ZeroconfError> {
+        lock(&self.state).remove_record(id, &self.socket)
+    }
+}
+
+impl<H: Handler> Drop for NativeOperation<H> {
+    fn drop(&mut self) {
+        log::debug!("Dropping native operation ({})", self.op_type);
+        lock(&self.state).on_drop(&self.socket);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    // Probing for the name, with the number of probes sent
+    Probing(u8),
+    // Announcing the service, with the number of announcements sent
+    Announcing(u8),
+    // Registered and responding to queries
+    Registered,
+    // Failed due to a name conflict
+    Failed,
+}
+
+// A record added to a registered service
+#[derive(Debug)]
+struct ExtraRecord {
+    id: u64,
+    data: RData,
+    ttl: u32,
+}
+
+#[derive(Debug)]
+struct RegisterState {
+    name: String,
+    base_name: String,
+    rename_count: u32,
+    service_type: Name,
+    subtypes: Vec<Name>,
+    domain: Name,
+    host: Name,
+    own_host: bool,
+    port: u16,
+    txt: Vec<u8>,
+    interface: Interface,
+    allow_rename: bool,
+    extra: Vec<ExtraRecord>,
+    next_record: u64,
+    phase: Phase,
+    next: Option<Instant>,
+    context: PublishContext,
+}
+
+impl RegisterState {
+    fn instance(&self) -> Name {
+        self.service_type.prepend(&self.name)
+    }
+
+    // The service type without the domain
+    fn regtype(&self) -> String {
+        let labels = self.service_type.labels();
+        format!(
+            "{}.",
+            labels[..labels.len() - self.domain.labels().len()].join(".")
+        )
+    }
+
+    fn srv(&self, ttl: u32) -> Record {
+        Record::unique(
+            self.instance(),
+            ttl,
+            RData::Srv {
+                priority: 0,
+                weight: 0,
+                port: self.port,
+                target: self.host.clone(),
+            },
+        )
+    }
+
+    fn txt(&self, ttl: u32) -> Record {
+        Record::unique(self.instance(), ttl, RData::Txt(self.txt.clone()))
+    }
+
+    fn ptr(&self, ttl: u32) -> Record {
+        Record::new(self.service_type.clone(), ttl, RData::Ptr(self.instance()))
+    }
+
+    fn subtype_ptrs(&self, ttl: u32) -> Vec<Record> {
+        self.subtypes
+            .iter()
+            .map(|s| Record::new(s.clone(), ttl, RData::Ptr(self.instance())))
+            .collect()
+    }
+
+    // Records added to the service, with a TTL of at most `ttl`
+    fn extra_records(&self, ttl: u32) -> Vec<Record> {
+        self.extra
+            .iter()
+            .map(|e| Record::new(self.instance(), ttl.min(e.ttl), e.data.clone()))
+            .collect()
+    }
+
+    fn enumeration(&self, ttl: u32) -> Record {
+        Record::new(
+            Name::parse(SERVICE_TYPE_QUERY).append(&self.domain),
+            ttl,
+            RData::Ptr(self.service_type.clone()),
+        )
+    }
+
+    fn addresses(&self, ttl: u32) -> Vec<Record> {
+        if !self.own_host {
+            return Vec::new();
+        }
+        socket::interface_addresses(&self.interface)
+            .into_iter()
+            .map(|a| {
+                let data = match a {
+                    IpAddr::V4(a) => RData::A(a),
+                    IpAddr::V6(a) => RData::Aaaa(a),
+                };
+                Record::unique(self.host.clone(), ttl, data)
+            })
+            .collect()
+    }
+
+    // All the records associated with the service
+    fn records(&self, ttl: u32) -> Vec<Record> {
+        let mut records = vec![
+            self.ptr(ttl),
+            self.srv(ttl),
+            self.txt(ttl),
+            self.enumeration(ttl),
+        ];
+        records.extend(self.subtype_ptrs(ttl));
+        records.extend(self.extra_records(ttl));
+        records.extend(self.addresses(ttl));
+        records
+    }
+
+    fn send_probe(&self, socket: &MdnsSocket) {
+        let mut msg = Message::query();
+        msg.questions
+            .push(Question::new(self.instance(), dns::TYPE_ANY));
+        msg.authorities.push(self.srv(HOST_TTL));
+        msg.authorities.push(self.txt(OTHER_TTL));
+        socket.send(&msg);
+    }
+
+    fn send_announcement(&self, socket: &MdnsSocket) {
+        let mut msg = Message::response();
+        msg.answers = self.records(OTHER_TTL);
+        for r in msg.answers.iter_mut() {
+            if matches!(r.data, RData::Srv { .. } | RData::A(_) | RData::Aaaa(_)) {
+                r.ttl = HOST_TTL;
+            }
+        }
+        socket.send(&msg);
+    }
+
+    fn send_goodbye(&self, socket: &MdnsSocket) {
+        let mut msg = Message::response();
+        msg.answers.push(self.ptr(0));
+        msg.answers.extend(self.subtype_ptrs(0));
+        msg.answers.push(self.srv(0));
+        msg.answers.push(self.txt(0));
+        msg.answers.extend(self.extra_records(0));
+        socket.send(&msg);
+    }
+
+    // Check if a record from another responder conflicts with this service
+    fn conflicts(&self, record: &Record) -> bool {
+        if record.name != self.instance() {
+            return false;
+        }
+        match self.phase {
+            Phase::Probing(_) => true,
+            Phase::Announcing(_) | Phase::Registered => {
+                record.cache_flush
+                    && record.ttl > 0
+                    && !(record.same_resource(&self.srv(0)) || record.same_resource(&self.txt(0)))
+            }
+            Phase::Failed => false,
+        }
+    }
+
+    // Probe again after a delay if another host probing for the same name
+    // wins the tie-break
+    fn on_probe(&mut self, msg: &Message) {
+        if !matches!(self.phase, Phase::Probing(_)) {
+            return;
+        }
+        let (srv, txt) = (self.srv(HOST_TTL), self.txt(OTHER_TTL));
+        if loses_tie_break(&[&srv, &txt], msg) {
+            log::debug!("Lost probe tie-break for '{}', deferring", self.name);
+            self.phase = Phase::Probing(0);
+            self.next = Some(Instant::now() + PROBE_DEFER);
+        }
+    }
+
+    fn on_conflict(&mut self, socket: &MdnsSocket) -> bool {
+        if matches!(self.phase, Phase::Announcing(_) | Phase::Registered) {
+            self.send_goodbye(socket);
+        }
+
+        if self.allow_rename {
+            self.rename_count += 1;
+            let suffix = format!(" ({})", self.rename_count);
+            let base = truncate_label(&self.base_name, MAX_LABEL_LEN - suffix.len());
+            self.name = format!("{}{}", base, suffix);
+            log::debug!("Name conflict, renaming to '{}'", self.name);
+            self.phase = Phase::Probing(0);
+            self.next = Some(Instant::now() + random_delay(PROBE_INTERVAL));
+            true
+        } else {
+            log::debug!("Name conflict on '{}'", self.name);
+            self.phase = Phase::Failed;
+            self.next = None;
+            self.context.send(Err(BonjourError::NameConflict));
+            false
+        }
+    }
+
+    // Build the answers to a query
+    fn answer(&self, msg: &Message, ttl: u32) -> Message {
+        let mut response = Message::response();
+        let instance = self.instance();
+        let enumeration = self.enumeration(ttl);
+        let mut additional = false;
+
+        for q in &msg.questions {
+            if q.name == self.service_type && q.matches(dns::TYPE_PTR) {
+                response.answers.push(self.ptr(ttl));
+                additional = true;
+            }
+            if self.subtypes.contains(&q.name) && q.matches(dns::TYPE_PTR) {
+                response.answers.push(Record::new(
+                    q.name.clone(),
+                    ttl,
+                    RData::Ptr(instance.clone()),
+                ));
+                additional = true;
+            }
+            if q.name == enumeration.name && q.matches(dns::TYPE_PTR) {
+                response.answers.push(enumeration.clone());
+            }
+            if q.name == instance {
+                if q.matches(dns::TYPE_SRV) {
+                    response.answers.push(self.srv(ttl.min(HOST_TTL)));
+                    additional = true;
+                }
+                if q.matches(dns::TYPE_TXT) {
+                    response.answers.push(self.txt(ttl));
+                }
+                response.answers.extend(
+                    self.extra_records(ttl)
+                        .into_iter()
+                        .filter(|r| q.matches(r.data.rtype())),
+                );
+            }
+            if q.name == self.host {
+                response.answers.extend(
+                    self.addresses(ttl.min(HOST_TTL))
+                        .into_iter()
+                        .filter(|r| q.matches(r.data.rtype())),
+                );
+            }
+        }
+
+        // Known answer suppression
+        response.answers.retain(|r| {
+            !msg.answers
+                .iter()
+                .any(|k| k.same_resource(r) && k.ttl >= r.ttl / 2)
+        });
+
+        if additional && !response.answers.is_empty() {
+            let mut additionals = vec![self.srv(ttl.min(HOST_TTL)), self.txt(ttl)];
+            additionals.extend(self.addresses(ttl.min(HOST_TTL)));
+            additionals.retain(|r| !response.answers.iter().any(|a| a.same_resource(r)));
+            response.additionals = additionals;
+        }
+
+        response
+    }
+}
+
+impl Handler for RegisterState {
+    fn next(&self) -> Option<Instant> {
+        self.next
+    }
+
+    fn on_timer(&mut self, socket: &MdnsSocket) -> bool {
+        let now = Instant::now();
+        match self.phase {
+            Phase::Probing(n) if n < PROBE_COUNT => {
+                log::trace!("Sending probe {} for '{}'", n + 1, self.name);
+                self.send_probe(socket);
+                self.phase = Phase::Probing(n + 1);
+                self.next = Some(now + PROBE_INTERVAL);
+            }
+            Phase::Probing(_) => {
+                log::debug!("Registered '{}'", self.instance());
+                self.send_announcement(socket);
+                self.phase = Phase::Announcing(1);
+                self.next = Some(now + ANNOUNCE_INTERVAL);
+                self.context
+                    .send_registered(&self.name, &self.regtype(), &self.domain.to_string());
+            }
+            Phase::Announcing(n) if n < ANNOUNCE_COUNT => {
+                self.send_announcement(socket);
+                self.phase = Phase::Announcing(n + 1);
+                self.next = Some(now + ANNOUNCE_INTERVAL);
+            }
+            Phase::Announcing(_) | Phase::Registered => {
+                self.phase = Phase::Registered;
+                self.next = None;
+            }
+            Phase::Failed => return false,
+        }
+        true
+    }
+
+    fn on_message(&mut self, msg: Message, src: SocketAddr, socket: &MdnsSocket) -> bool {
+        if msg.response {
+            if msg.answers.iter().any(|r| self.conflicts(r)) {
+                return self.on_conflict(socket);
+            }
+        } else if matches!(self.phase, Phase::Probing(_)) {
+            self.on_probe(&msg);
+        } else if matches!(self.phase, Phase::Announcing(_) | Phase::Registered) {
+            if src.port() != socket::MDNS_PORT {
+                // Legacy unicast query, respond directly with a capped TTL
+                let mut response = self.answer(&msg, LEGACY_TTL);
+                if !response.answers.is_empty() {
+                    response.id = msg.id;
+                    response.questions = msg.questions;
+                    socket.send_to(&response, src);
+                }
+            } else {
+                let response = self.answer(&msg, OTHER_TTL);
+                if !response.answers.is_empty() {
+                    if msg.questions.iter().all(|q| q.unicast) {
+                        socket.send_to(&response, src);
+                    } else {
+                        socket.send(&response);
+                    }
+                }
+            }
+        }
+        self.phase != Phase::Failed
+    }
+
+    fn on_drop(&mut self, socket: &MdnsSocket) {
+        if matches!(self.phase, Phase::Announcing(_) | Phase::Registered) {
+            log::debug!("Sending goodbye for '{}'", self.instance());
+            self.send_goodbye(socket);
+        }
+    }
+
+    fn update_txt(&mut self, txt: &TxtRecord, socket: &MdnsSocket) -> Result<(), ZeroconfError> {
+        self.txt = txt.to_bytes()?;
+        // Announce the new record again, as for a new registration
+        if matches!(self.phase, Phase::Announcing(_) | Phase::Registered) {
+            log::debug!("Announcing updated TXT for '{}'", self.instance());
+            self.send_announcement(socket);
+            self.phase = Phase::Announcing(1);
+            self.next = Some(Instant::now() + ANNOUNCE_INTERVAL);
+        }
+        Ok(())
+    }
+
+    fn add_record(
+        &mut self,
+        rrtype: u16,
+        rdata: Vec<u8>,
+        ttl: u32,
+        socket: &MdnsSocket,
+    ) -> Result<u64, ZeroconfError> {
+        let id = self.next_record;
+        self.next_record += 1;
+        // A TTL of zero selects the default, as for `DNSServiceAddRecord`
+        let ttl = if ttl == 0 { OTHER_TTL } else { ttl };
+        let data = RData::from_rdata(rrtype, rdata);
+        // Announce the new record, the others are unchanged
+        if matches!(self.phase, Phase::Announcing(_) | Phase::Registered) {
+            let mut msg = Message::response();
+            msg.answers
+                .push(Record::new(self.instance(), ttl, data.clone()));
+            socket.send(&msg);
+        }
+        self.extra.push(ExtraRecord { id, data, ttl });
+        Ok(id)
+    }
+
+    fn remove_record(&mut self, id: u64, socket: &MdnsSocket) -> Result<(), ZeroconfError> {
+        let position = self
+            .extra
+            .iter()
+            .position(|e| e.id == id)
+            .ok_or(BonjourError::BadReference)?;
+        let extra = self.extra.remove(position);
+        if matches!(self.phase, Phase::Announcing(_) | Phase::Registered) {
+            let mut msg = Message::response();
+            msg.answers
+                .push(Record::new(self.instance(), 0, extra.data));
+            socket.send(&msg);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct KnownService {
+    instance: Name,
+    ttl: u32,
+    received: Instant,
+}
+
+#[derive(Debug)]
+struct BrowseState {
+    query: Name,
+    // The number of labels of the shortest name that can be found
+    min_labels: usize,
+    domain: Name,
+    interface: Interface,
+    known: Vec<KnownService>,
+    interval: Duration,
+    next: Instant,
+    context: BrowseContext,
+}
+
+impl BrowseState {
+    fn service(&self, instance: &Name) -> Option<Service> {
+        let (name, service_type) = instance.labels().split_first()?;
+        let type_labels = &service_type[..service_type.len() - self.domain.labels().len()];
+        let mut service = Service::new(name, &format!("{}.", type_labels.join(".")), 0);
+        service
+            .set_interface(self.interface)
+            .set_domain(self.domain.to_string());
+        Some(service)
+    }
+}
+
+impl Handler for BrowseState {
+    fn next(&self) -> Option<Instant> {
+        Some(self.next)
+    }
+
+    fn on_timer(&mut self, socket: &MdnsSocket) -> bool {
+        let now = Instant::now();
+        let (known, expired): (Vec<_>, Vec<_>) = self
+            .known
+            .drain(..)
+            .partition(|k| now < k.received + Duration::from_secs(k.ttl.into()));
+        self.known = known;
+
+        let removed: Vec<_> = expired
+            .iter()
+            .filter_map(|k| {
+                log::debug!("ServiceBrowse Expired {}", k.instance);
+                self.service(&k.instance)
+            })
+            .collect();
+        let count = removed.len();
+        for (i, service) in removed.into_iter().enumerate() {
+            self.context.send_removed(service, i + 1 == count);
+        }
+
+        let mut msg = Message::query();
+        msg.questions
+            .push(Question::new(self.query.clone(), dns::TYPE_PTR));
+        for k in &self.known {
+            let remaining = k.ttl - now.duration_since(k.received).as_secs() as u32;
+            if remaining > k.ttl / 2 {
+                msg.answers.push(Record::new(
+                    self.query.clone(),
+                    remaining,
+                    RData::Ptr(k.instance.clone()),
+                ));
+            }
+        }
+        socket.send(&msg);
+
+        self.next = now + self.interval;
+        self.interval = (self.interval * 2).min(MAX_QUERY_INTERVAL);
+        true
+    }
+
+    fn on_message(&mut self, msg: Message, _src: SocketAddr, _socket: &MdnsSocket) -> bool {
+        if !msg.response {
+            return true;
+        }
+
+        let query = self.query.clone();
+        let mut found = Vec::new();
+        let mut removed = Vec::new();
+        for r in msg.records().filter(|r| r.name == query) {
+            let instance = match &r.data {
+                RData::Ptr(instance) => instance,
+                _ => continue,
+            };
+            let existing = self.known.iter().position(|k| &k.instance == instance);
+
+            if r.ttl == 0 {
+                if let Some(i) = existing {
+                    log::debug!("ServiceBrowse Remove {}", instance);
+                    self.known.remove(i);
+                    removed.extend(self.service(instance));
+                }
+            } else if let Some(i) = existing {
+                self.known[i].ttl = r.ttl;
+                self.known[i].received = Instant::now();
+            } else if instance.labels().len() >= self.min_labels {
+                self.known.push(KnownService {
+                    instance: instance.clone(),
+                    ttl: r.ttl,
+                    received: Instant::now(),
+                });
+                found.extend(self.service(instance));
+            }
+        }
+
+        let count = removed.len() + found.len();
+        for (i, service) in removed.into_iter().enumerate() {
+            self.context.send_removed(service, i + 1 == count);
+        }
+        let offset = count - found.len();
+        for (i, service) in found.into_iter().enumerate() {
+            log::debug!("ServiceBrowse Found {}", service);
+            self.context.send(Ok(service), offset + i + 1 == count);
+        }
+        true
+    }
+}
+
+#[derive(Debug)]
+struct ResolveState {
+    instance: Name,
+    interface: Interface,
+    srv: Option<(u16, Name)>,
+    txt: Option<Vec<u8>>,
+    interval: Duration,
+    next: Instant,
+    context: ResolveContext,
+}
+
+impl Handler for ResolveState {
+    fn next(&self) -> Option<Instant> {
+        Some(self.next)
+    }
+
+    fn on_timer(&mut self, socket: &MdnsSocket) -> bool {
+        let mut msg = Message::query();
+        msg.questions
+            .push(Question::new(self.instance.clone(), dns::TYPE_SRV));
+        msg.questions
+            .push(Question::new(self.instance.clone(), dns::TYPE_TXT));
+        socket.send(&msg);
+
+        self.next = Instant::now() + self.interval;
+        self.interval = (self.interval * 2).min(MAX_QUERY_INTERVAL);
+        true
+    }
+
+    fn on_message(&mut self, msg: Message, _src: SocketAddr, _socket: &MdnsSocket) -> bool {
+        if !msg.response {
+            return true;
+        }
+
+        let instance = self.instance.clone();
+        for r in msg.records().filter(|r| r.name == instance && r.ttl > 0) {
+            match &r.data {
+                RData::Srv { port, target, .. } => self.srv = Some((*port, target.clone())),
+                RData::Txt(txt) => self.txt = Some(txt.clone()),
+                _ => (),
+            }
+        }
+
+        match (&self.srv, &self.txt) {
+            (Some((port, target)), Some(txt)) => {
+                log::debug!("ServiceResolve OK ({}:{}:{})", self.instance, target, port);
+                let info = ResolverInformation::new(
+                    self.interface,
+                    &self.instance.to_string(),
+                    &target.to_string(),
+                    *port,
+                    TxtRecord::from_bytes_lossy(txt),
+                );
+                self.context.send(Ok(info));
+                false
+            }
+            _ => true,
+        }
+    }
+}
+
+// Only the local domain is supported, so it is the only domain reported
+#[derive(Debug)]
+struct DomainState {
+    reported: bool,
+    context: DomainContext,
+}
+
+impl Handler for DomainState {
+    fn next(&self) -> Option<Instant> {
+        if self.reported {
+            None
+        } else {
+            Some(Instant::now())
+        }
+    }
+
+    fn on_timer(&mut self, _socket: &MdnsSocket) -> bool {
+        let domain = Domain::new("local.", true);
+        self.context.send(Ok(DomainEvent::Added(domain)));
+        self.reported = true;
+        true
+    }
+
+    fn on_message(&mut self, _msg: Message, _src: SocketAddr, _socket: &MdnsSocket) -> bool {
+        true
+    }
+}
+
+#[derive(Debug)]
+struct KnownAddress {
+    address: IpAddr,
+    ttl: u32,
+    received: Instant,
+}
+
+#[derive(Debug)]
+struct AddrState {
+    hostname: Name,
+    protocol: Protocol,
+    known: Vec<KnownAddress>,
+    interval: Duration,
+    next: Instant,
+    context: AddrContext,
+}
+
+impl Handler for AddrState {
+    fn next(&self) -> Option<Instant> {
+        Some(self.next)
+    }
+
+    fn on_timer(&mut self, socket: &MdnsSocket) -> bool {
+        let now = Instant::now();
+        let mut expired = Vec::new();
+        self.known.retain(|k| {
+            let alive = now < k.received + Duration::from_secs(k.ttl.into());
+            if !alive {
+                expired.push(k.address);
+            }
+            alive
+        });
+        let count = expired.len();
+        for (i, address) in expired.into_iter().enumerate() {
+            log::debug!("GetAddrInfo Expired {}", address);
+            self.context
+                .send(Ok(AddrEvent::Removed(address)), i + 1 == count);
+        }
+
+        let mut msg = Message::query();
+        if self.protocol != Protocol::IPv6 {
+            msg.questions
+                .push(Question::new(self.hostname.clone(), dns::TYPE_A));
+        }
+        if self.protocol != Protocol::IPv4 {
+            msg.questions
+                .push(Question::new(self.hostname.clone(), dns::TYPE_AAAA));
+        }
+        socket.send(&msg);
+
+        self.next = now + self.interval;
+        self.interval = (self.interval * 2).min(MAX_QUERY_INTERVAL);
+        true
+    }
+
+    fn on_message(&mut self, msg: Message, _src: SocketAddr, _socket: &MdnsSocket) -> bool {
+        if !msg.response {
+            return true;
+        }
+
+        let hostname = self.hostname.clone();
+        let mut events = Vec::new();
+        for r in msg.records().filter(|r| r.name == hostname) {
+            let address = match r.data {
+                RData::A(a) => IpAddr::V4(a),
+                RData::Aaaa(a) => IpAddr::V6(a),
+                _ => continue,
+            };
+            if !self.protocol.matches(&address) {
+                continue;
+            }
+            let existing = self.known.iter().position(|k| k.address == address);
+
+            if r.ttl == 0 {
+                if let Some(i) = existing {
+                    self.known.remove(i);
+                    events.push(AddrEvent::Removed(address));
+                }
+            } else if let Some(i) = existing {
+                self.known[i].ttl = r.ttl;
+                self.known[i].received = Instant::now();
+            } else {
+                self.known.push(KnownAddress {
+                    address,
+                    ttl: r.ttl,
+                    received: Instant::now(),
+                });
+                events.push(AddrEvent::Added(address));
+            }
+        }
+
+        let count = events.len();
+        for (i, event) in events.into_iter().enumerate() {
+            log::debug!("GetAddrInfo {:?} ({})", event, self.hostname);
+            self.context.send(Ok(event), i + 1 == count);
+        }
+        true
+    }
+}
+
+#[derive(Debug)]
+struct KnownRecord {
+    record: Record,
+    received: Instant,
+}
+
+#[derive(Debug)]
+struct RecordState {
+    name: Name,
+    rrtype: u16,
+    known: Vec<KnownRecord>,
+    interval: Duration,
+    next: Instant,
+    context: RecordContext,
+}
+
+impl Handler for RecordState {
+    fn next(&self) -> Option<Instant> {
+        Some(self.next)
+    }
+
+    fn on_timer(&mut self, socket: &MdnsSocket) -> bool {
+        let now = Instant::now();
+        let mut expired = Vec::new();
+        self.known.retain(|k| {
+            let alive = now < k.received + Duration::from_secs(k.record.ttl.into());
+            if !alive {
+                expired.push(resource_record(&k.record));
+            }
+            alive
+        });
+        for record in expired {
+            log::debug!("QueryRecord Expired {}", record);
+            self.context.send(Ok(RecordEvent::Removed(record)));
+        }
+
+        let mut msg = Message::query();
+        msg.questions
+            .push(Question::new(self.name.clone(), self.rrtype));
+        for k in &self.known {
+            let remaining = k.record.ttl - now.duration_since(k.received).as_secs() as u32;
+            if remaining > k.record.ttl / 2 {
+                let mut known = k.record.clone();
+                known.ttl = remaining;
+                msg.answers.push(known);
+            }
+        }
+        socket.send(&msg);
+
+        self.next = now + self.interval;
+        self.interval = (self.interval * 2).min(MAX_QUERY_INTERVAL);
+        true
+    }
+
+    fn on_message(&mut self, msg: Message, _src: SocketAddr, _socket: &MdnsSocket) -> bool {
+        if !msg.response {
+            return true;
+        }
+
+        let question = Question::new(self.name.clone(), self.rrtype);
+        let mut events = Vec::new();
+        for r in msg
+            .records()
+            .filter(|r| r.name == question.name && question.matches(r.data.rtype()))
+        {
+            let existing = self.known.iter().position(|k| k.record.same_resource(r));
+
+            if r.ttl == 0 {
+                if let Some(i) = existing {
+                    let known = self.known.remove(i);
+                    events.push(RecordEvent::Removed(resource_record(&known.record)));
+                }
+            } else if let Some(i) = existing {
+                self.known[i].record.ttl = r.ttl;
+                self.known[i].received = Instant::now();
+            } else {
+                self.known.push(KnownRecord {
+                    record: r.clone(),
+                    received: Instant::now(),
+                });
+                events.push(RecordEvent::Added(resource_record(r)));
+            }
+        }
+
+        for event in events {
+            log::debug!("QueryRecord {:?} ({})", event, self.name);
+            self.context.send(Ok(event));
+        }
+        true
+    }
+}
+
+// Convert a record received by the socket into the form reported by a query
+fn resource_record(record: &Record) -> ResourceRecord {
+    let data = match &record.data {
+        RData::A(a) => RecordData::A(*a),
+        RData::Aaaa(a) => RecordData::Aaaa(*a),
+        RData::Ptr(name) => RecordData::Ptr(name.to_string()),
+        RData::Srv {
+            priority,
+            weight,
+            port,
+            target,
+        } => RecordData::Srv {
+            priority: *priority,
+            weight: *weight,
+            port: *port,
+            target: target.to_string(),
+        },
+        RData::Txt(txt) => RecordData::from_rdata(dns::TYPE_TXT, txt),
+        RData::Other(rrtype, data) => RecordData::from_rdata(*rrtype, data),
+    };
+    ResourceRecord::new(
+        &record.name.to_string(),
+        RecordQuery::CLASS_IN,
+        Some(record.ttl),
+        data,
+    )
+}
+
+// A record registered individually
+#[derive(Debug)]
+struct OwnedRecord {
+    id: u64,
+    record: Record,
+    phase: Phase,
+    next: Option<Instant>,
+    // Channel for the result, until the record is registered
+    result: Option<oneshot::Sender<Result<(), BonjourError>>>,
+}
+
+impl OwnedRecord {
+    fn announced(&self) -> bool {
+        matches!(self.phase, Phase::Announcing(_) | Phase::Registered)
+    }
+
+    // Only unique records are probed for
+    fn probing(&self) -> bool {
+        self.record.cache_flush && matches!(self.phase, Phase::Probing(_))
+    }
+
+    // Check if a record from another responder conflicts with this record,
+    // which is only possible for unique records
+    fn conflicts(&self, other: &Record) -> bool {
+        let conflict = self.record.cache_flush
+            && other.name == self.record.name
+            && other.data.rtype() == self.record.data.rtype()
+            && !other.same_resource(&self.record);
+        match self.phase {
+            Phase::Probing(_) => conflict,
+            Phase::Announcing(_) | Phase::Registered => {
+                conflict && other.cache_flush && other.ttl > 0
+            }
+            Phase::Failed => false,
+        }
+    }
+
+    fn on_timer(&mut self, now: Instant, socket: &MdnsSocket) {
+        match self.phase {
+            Phase::Probing(n) if n < PROBE_COUNT => {
+                let mut msg = Message::query();
+                msg.questions
+                    .push(Question::new(self.record.name.clone(), dns::TYPE_ANY));
+                msg.authorities.push(self.record.clone());
+                socket.send(&msg);
+                self.phase = Phase::Probing(n + 1);
+                self.next = Some(now + PROBE_INTERVAL);
+            }
+            Phase::Probing(_) => {
+                log::debug!("Registered record {}", self.record.name);
+                self.announce(socket);
+                self.phase = Phase::Announcing(1);
+                self.next = Some(now + ANNOUNCE_INTERVAL);
+                if let Some(tx) = self.result.take() {
+                    if tx.send(Ok(())).is_err() {
+                        log::debug!("Record result not sent, receiver dropped");
+                    }
+                }
+            }
+            Phase::Announcing(n) if n < ANNOUNCE_COUNT => {
+                self.announce(socket);
+                self.phase = Phase::Announcing(n + 1);
+                self.next = Some(now + ANNOUNCE_INTERVAL);
+            }
+            Phase::Announcing(_) | Phase::Registered => {
+                self.phase = Phase::Registered;
+                self.next = None;
+            }
+            Phase::Failed => self.next = None,
+        }
+    }
+
+    fn announce(&self, socket: &MdnsSocket) {
+        let mut msg = Message::response();
+        msg.answers.push(self.record.clone());
+        socket.send(&msg);
+    }
+
+    fn send_goodbye(&self, socket: &MdnsSocket) {
+        let mut msg = Message::response();
+        let mut record = self.record.clone();
+        record.ttl = 0;
+        msg.answers.push(record);
+        socket.send(&msg);
+    }
+}
+
+#[derive(Debug)]
+struct RegisterRecordsState {
+    records: Vec<OwnedRecord>,
+    next_record: u64,
+}
+
+impl RegisterRecordsState {
+    // Probe again after a delay for any records where another host probing
+    // for the same name wins the tie-break
+    fn on_probe(&mut self, msg: &Message) {
+        let deferred: Vec<u64> = self
+            .records
+            .iter()
+            .filter(|owned| owned.probing())
+            .filter(|owned| {
+                let ours: Vec<&Record> = self
+                    .records
+                    .iter()
+                    .filter(|r| r.probing() && r.record.name == owned.record.name)
+                    .map(|r| &r.record)
+                    .collect();
+                loses_tie_break(&ours, msg)
+            })
+            .map(|owned| owned.id)
+            .collect();
+
+        let next = Instant::now() + PROBE_DEFER;
+        for owned in self.records.iter_mut() {
+            if deferred.contains(&owned.id) {
+                log::debug!("Lost probe tie-break for {}, deferring", owned.record.name);
+                owned.phase = Phase::Probing(0);
+                owned.next = Some(next);
+            }
+        }
+    }
+
+    // Build the answers to a query, with the TTL of each record capped at
+    // `ttl`
+    fn answer(&self, msg: &Message, ttl: u32) -> Message {
+        let mut response = Message::response();
+        for q in &msg.questions {
+            for owned in self.records.iter().filter(|r| r.announced()) {
+                if q.name == owned.record.name && q.matches(owned.record.data.rtype()) {
+                    let mut record = owned.record.clone();
+                    record.ttl = record.ttl.min(ttl);
+                    response.answers.push(record);
+                }
+            }
+        }
+
+        // Known answer suppression
+        response.answers.retain(|r| {
+            !msg.answers
+                .iter()
+                .any(|k| k.same_resource(r) && k.ttl >= r.ttl / 2)
+        });
+        response
+    }
+}
+
+impl Handler for RegisterRecordsState {
+    fn next(&self) -> Option<Instant> {
+        self.records.iter().filter_map(|r| r.next).min()
+    }
+
+    fn on_timer(&mut self, socket: &MdnsSocket) -> bool {
+        let now = Instant::now();
+        for owned in self.records.iter_mut() {
+            if matches!(owned.next, Some(t) if t <= now) {
+                owned.on_timer(now, socket);
+            }
+        }
+        true
+    }
+
+    fn on_message(&mut self, msg: Message, src: SocketAddr, socket: &MdnsSocket) -> bool {
+        if msg.response {
+            let (conflicts, records): (Vec<_>, Vec<_>) = self
+                .records
+                .drain(..)
+                .partition(|r| msg.answers.iter().any(|a| r.conflicts(a)));
+            self.records = records;
+            for owned in conflicts {
+                log::debug!("Name conflict on record {}", owned.record.name);
+                if owned.announced() {
+                    owned.send_goodbye(socket);
+                }
+                match owned.result {
+                    Some(tx) => {
+                        if tx.send(Err(BonjourError::NameConflict)).is_err() {
+                            log::debug!("Record result not sent, receiver dropped");
+                        }
+                    }
+                    None => log::warn!("Registered record {} conflicts", owned.record.name),
+                }
+            }
+            return true;
+        }
+
+        self.on_probe(&msg);
+        if src.port() != socket::MDNS_PORT {
+            // Legacy unicast query, respond directly with a capped TTL
+            let mut response = self.answer(&msg, LEGACY_TTL);
+            if !response.answers.is_empty() {
+                response.id = msg.id;
+                response.questions = msg.questions;
+                socket.send_to(&response, src);
+            }
+        } else {
+            let response = self.answer(&msg, OTHER_TTL);
+            if !response.answers.is_empty() {
+                if msg.questions.iter().all(|q| q.unicast) {
+                    socket.send_to(&response, src);
+                } else {
+                    socket.send(&response);
+                }
+            }
+        }
+        true
+    }
+
+    fn on_drop(&mut self, socket: &MdnsSocket) {
+        for owned in self.records.iter().filter(|r| r.announced()) {
+            owned.send_goodbye(socket);
+        }
+    }
+
+    fn register_record(
+        &mut self,
+        record: &ResourceRecord,
+        unique: bool,
+    ) -> Result<(u64, RegisterResult), ZeroconfError> {
+        if record.rrclass() != RecordQuery::CLASS_IN {
+            log::warn!("Class {} not supported by NativeBackend", record.rrclass());
+            return Err(BonjourError::Unsupported.into());
+        }
+        let name = valid_name(Name::parse_escaped(record.name()).ok_or(BonjourError::BadParam)?)?;
+        let data = RData::from_rdata(record.rrtype().into(), record.data().to_rdata()?);
+        let now = Instant::now();
+        // Unique records are probed for first, shared records are announced
+        // straight away
+        let (record, phase, next) = if unique {
+            let ttl = record.ttl().unwrap_or(HOST_TTL);
+            (
+                Record::unique(name, ttl, data),
+                Phase::Probing(0),
+                now + random_delay(PROBE_INTERVAL),
+            )
+        } else {
+            let ttl = record.ttl().unwrap_or(OTHER_TTL);
+            (
+                Record::new(name, ttl, data),
+                Phase::Probing(PROBE_COUNT),
+                now,
+            )
+        };
+
+        let id = self.next_record;
+        self.next_record += 1;
+        let (tx, rx) = oneshot::channel();
+        self.records.push(OwnedRecord {
+            id,
+            record,
+            phase,
+            next: Some(next),
+            result: Some(tx),
+        });
+        Ok((id, rx))
+    }
+
+    fn remove_record(&mut self, id: u64, socket: &MdnsSocket) -> Result<(), ZeroconfError> {
+        let position = self
+            .records
+            .iter()
+            .position(|r| r.id == id)
+            .ok_or(BonjourError::BadReference)?;
+        let owned = self.records.remove(position);
+        if owned.announced() {
+            owned.send_goodbye(socket);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+// Multicast sockets used to send and receive mDNS messages
+
+use super::dns::Message;
+use crate::Interface;
+
+use socket2::{Domain, InterfaceIndexOrAddress, Protocol, Socket, Type};
+use std::ffi::CStr;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6};
+use tokio::net::UdpSocket;
+
+pub const MDNS_PORT: u16 = 5353;
+pub const MDNS_V4: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+pub const MDNS_V6: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0xfb);
+
+// Maximum size of a multicast DNS message
+const MAX_MESSAGE: usize = 9000;
+
+/// Sockets bound to the mDNS port and joined to the mDNS multicast groups
+#[derive(Debug)]
+pub struct MdnsSocket {
+    v4: Option<UdpSocket>,
+    v6: Option<UdpSocket>,
+    index: u32,
+}
+
+impl MdnsSocket {
+    /// Open sockets for IPv4 and IPv6 on the specified interface. Only one of
+    /// the address families is required to be available.
+    pub fn new(interface: &Interface) -> io::Result<Self> {
+        let index = match interface {
+            Interface::Unspecified => 0,
+            Interface::Interface(id) => *id,
+        };
+
+        let v4 = open_v4(index).map_err(|e| {
+            log::debug!("Failed to open IPv4 mDNS socket: {}", e);
+            e
+        });
+        let v6 = open_v6(index).map_err(|e| {
+            log::debug!("Failed to open IPv6 mDNS socket: {}", e);
+            e
+        });
+
+        match (v4, v6) {
+            (Err(e), Err(_)) => Err(e),
+            (v4, v6) => Ok(MdnsSocket {
+                v4: v4.ok(),
+                v6: v6.ok(),
+                index,
+            }),
+        }
+    }
+
+    /// Send a message to the multicast groups
+    pub fn send(&self, msg: &Message) {
+        let buf = msg.encode();
+        if let Some(v4) = &self.v4 {
+            try_send(v4, &buf, SocketAddr::from((MDNS_V4, MDNS_PORT)));
+        }
+        if let Some(v6) = &self.v6 {
+            let addr = SocketAddrV6::new(MDNS_V6, MDNS_PORT, 0, self.index);
+            try_send(v6, &buf, addr.into());
+        }
+    }
+
+    /// Send a message directly to `addr`
+    pub fn send_to(&self, msg: &Message, addr: SocketAddr) {
+        let socket = match addr {
+            SocketAddr::V4(_) => &self.v4,
+            SocketAddr::V6(_) => &self.v6,
+        };
+        if let Some(socket) = socket {
+            try_send(socket, &msg.encode(), addr);
+        }
+    }
+
+    /// Receive the next message that can be decoded, along with the address
+    /// it was sent from
+    pub async fn recv(&self) -> io::Result<(Message, SocketAddr)> {
+        loop {
+            let (buf, addr) = tokio::select! {
+                r = recv_on(&self.v4) => r?,
+                r = recv_on(&self.v6) => r?,
+            };
+            match Message::decode(&buf) {
+                Ok(msg) => return Ok((msg, addr)),
+                Err(e) => log::debug!("Ignoring message from {}: {}", addr, e),
+            }
+        }
+    }
+}
+
+async fn recv_on(socket: &Option<UdpSocket>) -> io::Result<(Vec<u8>, SocketAddr)> {
+    match socket {
+        Some(socket) => {
+            let mut buf = vec![0; MAX_MESSAGE];
+            let (len, addr) = socket.recv_from(&mut buf).await?;
+            buf.truncate(len);
+            Ok((buf, addr))
+        }
+        None => futures::future::pending().await,
+    }
+}
+
+fn try_send(socket: &UdpSocket, buf: &[u8], addr: SocketAddr) {
+    if let Err(e) = socket.try_send_to(buf, addr) {
+        log::debug!("Failed to send mDNS message to {}: {}", addr, e);
+    }
+}
+
+fn open_v4(index: u32) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.set_reuse_port(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, MDNS_PORT)).into())?;
+    socket.join_multicast_v4_n(&MDNS_V4, &InterfaceIndexOrAddress::Index(index))?;
+    if index != 0 {
+        let addr = interface_addresses(&Interface::Interface(index))
+            .into_iter()
+            .find_map(|a| match a {
+                IpAddr::V4(a) => Some(a),
+                IpAddr::V6(_) => None,
+            });
+        if let Some(addr) = addr {
+            socket.set_multicast_if_v4(&addr)?;
+        }
+    }
+    socket.set_multicast_loop_v4(true)?;
+    socket.set_multicast_ttl_v4(255)?;
+    UdpSocket::from_std(socket.into())
+}
+
+fn open_v6(index: u32) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_only_v6(true)?;
+    socket.set_reuse_address(true)?;
+    socket.set_reuse_port(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, MDNS_PORT)).into())?;
+    socket.join_multicast_v6(&MDNS_V6, index)?;
+    if index != 0 {
+        socket.set_multicast_if_v6(index)?;
+    }
+    socket.set_multicast_loop_v6(true)?;
+    socket.set_multicast_hops_v6(255)?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// Get the addresses of the specified interface, or of all interfaces if
+/// unspecified. Loopback addresses are only included if there are no others.
+pub fn interface_addresses(interface: &Interface) -> Vec<IpAddr> {
+    let mut addrs = Vec::new();
+    let mut loopback = Vec::new();
+
+    let mut ifaddrs: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut ifaddrs) } != 0 {
+        log::warn!(
+            "Failed to get interface addresses: {}",
+            io::Error::last_os_error()
+        );
+        return addrs;
+    }
+
+    let mut cur = ifaddrs;
+    while !cur.is_null() {
+        let ifa = unsafe { &*cur };
+        cur = ifa.ifa_next;
+
+        if ifa.ifa_addr.is_null() {
+            continue;
+        }
+        if let Interface::Interface(index) = interface {
+            if unsafe { libc::if_nametoindex(ifa.ifa_name) } != *index {
+                continue;
+            }
+        }
+
+        let addr = match unsafe { (*ifa.ifa_addr).sa_family } as i32 {
+            libc::AF_INET => {
+                let sin = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in) };
+                IpAddr::V4(Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr)))
+            }
+            libc::AF_INET6 => {
+                let sin6 = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in6) };
+                IpAddr::V6(Ipv6Addr::from(sin6.sin6_addr.s6_addr))
+            }
+            _ => continue,
+        };
+
+        if addr.is_loopback() {
+            loopback.push(addr);
+        } else {
+            addrs.push(addr);
+        }
+    }
+    unsafe { libc::freeifaddrs(ifaddrs) };
+
+    if addrs.is_empty() {
+        loopback
+    } else {
+        addrs
+    }
+}
+
+/// Get the name of this host, without any domain
+pub fn hostname() -> String {
+    let mut buf = vec![0u8; 256];
+    let err = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    let name = if err == 0 {
+        // Ensure null terminated if truncated
+        buf.push(0);
+        CStr::from_bytes_until_nul(&buf)
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    } else {
+        String::new()
+    };
+
+    match name.split('.').next() {
+        Some(n) if !n.is_empty() => n.to_string(),
+        _ => "localhost".to_string(),
+    }
+}
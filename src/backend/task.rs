@@ -0,0 +1,144 @@
+// Operation driven by a future, for backends that are implemented as async
+// tasks rather than by polling a file descriptor
+
+use crate::backend::{AddRecordFuture, BackendOperation, ProcessFuture, UpdateFuture};
+use crate::sync::lock;
+use crate::{BonjourError, OpType, RecordType, ResourceRecord, TxtRecord, ZeroconfError};
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::Poll;
+use tokio::sync::{mpsc, oneshot};
+
+type OperationFuture = Pin<Box<dyn Future<Output = Result<(), ZeroconfError>> + Send>>;
+
+// Request to change a registration, handled by the future registering the
+// service
+pub(crate) enum RegistrationRequest {
+    // Replace the TXT record, with a channel for the result
+    UpdateTxt(TxtRecord, oneshot::Sender<Result<(), ZeroconfError>>),
+    // Add a record, with a channel for the identifier of the record
+    AddRecord(
+        RecordType,
+        Vec<u8>,
+        u32,
+        oneshot::Sender<Result<u64, ZeroconfError>>,
+    ),
+    // Register an individual record, which is unique if set, with a
+    // channel for the identifier of the record
+    RegisterRecord(
+        ResourceRecord,
+        bool,
+        oneshot::Sender<Result<u64, ZeroconfError>>,
+    ),
+    // Remove a record previously added or registered
+    RemoveRecord(u64),
+}
+
+// The future is held in a mutex so the operation can be shared between
+// threads. Once it completes the operation has no more events to process.
+pub(crate) struct TaskOperation {
+    future: Mutex<Option<OperationFuture>>,
+    op_type: OpType,
+    requests: Option<mpsc::UnboundedSender<RegistrationRequest>>,
+}
+
+impl TaskOperation {
+    pub fn start<F>(future: F, op_type: OpType) -> Box<dyn BackendOperation>
+    where
+        F: Future<Output = Result<(), ZeroconfError>> + Send + 'static,
+    {
+        log::debug!("Created task operation ({})", op_type);
+        Box::new(TaskOperation {
+            future: Mutex::new(Some(Box::pin(future))),
+            op_type,
+            requests: None,
+        })
+    }
+
+    // Start an operation registering a service, where the future handles
+    // requests to change the registration received from `requests`
+    pub fn start_registration<F>(
+        future: F,
+        op_type: OpType,
+        requests: mpsc::UnboundedSender<RegistrationRequest>,
+    ) -> Box<dyn BackendOperation>
+    where
+        F: Future<Output = Result<(), ZeroconfError>> + Send + 'static,
+    {
+        log::debug!("Created task operation ({})", op_type);
+        Box::new(TaskOperation {
+            future: Mutex::new(Some(Box::pin(future))),
+            op_type,
+            requests: Some(requests),
+        })
+    }
+
+    // Send a request to the future registering a service
+    fn request(&self, request: RegistrationRequest) -> Result<(), ZeroconfError> {
+        let requests = self.requests.as_ref().ok_or(BonjourError::Unsupported)?;
+        requests.send(request).map_err(|_| ZeroconfError::Dropped)
+    }
+}
+
+impl fmt::Debug for TaskOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaskOperation")
+            .field("op_type", &self.op_type)
+            .finish()
+    }
+}
+
+impl BackendOperation for TaskOperation {
+    fn process(&self) -> ProcessFuture<'_> {
+        Box::pin(futures::future::poll_fn(move |cx| {
+            let mut guard = lock(&self.future);
+            let result = match guard.as_mut() {
+                Some(future) => match future.as_mut().poll(cx) {
+                    Poll::Ready(r) => r,
+                    Poll::Pending => return Poll::Pending,
+                },
+                None => Ok(()),
+            };
+            // Operation has completed, so release any resources it holds
+            *guard = None;
+            Poll::Ready(result.map(|_| false))
+        }))
+    }
+
+    fn update_txt(&self, txt: TxtRecord) -> UpdateFuture<'_> {
+        Box::pin(async move {
+            let (tx, rx) = oneshot::channel();
+            self.request(RegistrationRequest::UpdateTxt(txt, tx))?;
+            rx.await.map_err(|_| ZeroconfError::Dropped)?
+        })
+    }
+
+    fn add_record(&self, rrtype: RecordType, rdata: Vec<u8>, ttl: u32) -> AddRecordFuture<'_> {
+        Box::pin(async move {
+            let (tx, rx) = oneshot::channel();
+            self.request(RegistrationRequest::AddRecord(rrtype, rdata, ttl, tx))?;
+            rx.await.map_err(|_| ZeroconfError::Dropped)?
+        })
+    }
+
+    fn register_record(&self, record: ResourceRecord, unique: bool) -> AddRecordFuture<'_> {
+        Box::pin(async move {
+            let (tx, rx) = oneshot::channel();
+            self.request(RegistrationRequest::RegisterRecord(record, unique, tx))?;
+            rx.await.map_err(|_| ZeroconfError::Dropped)?
+        })
+    }
+
+    fn remove_record(&self, id: u64) -> Result<(), ZeroconfError> {
+        self.request(RegistrationRequest::RemoveRecord(id))
+    }
+}
+
+impl Drop for TaskOperation {
+    fn drop(&mut self) {
+        log::debug!("Dropping task operation ({})", self.op_type);
+    }
+}
@@ -1,29 +1,36 @@
-use crate::{Interface, TxtRecord, ZeroconfError};
+use crate::{
+    DomainKind, Interface, Protocol, RecordType, ResourceRecord, TxtRecord, ZeroconfError,
+};
 use std::convert::TryInto;
-use std::{ffi, mem, ptr};
+use std::{ffi, ptr};
 
 use bonjour_sys::{
-    kDNSServiceFlagsNoAutoRename, DNSServiceBrowse, DNSServiceBrowseReply, DNSServiceRef,
-    DNSServiceRegister, DNSServiceRegisterReply, DNSServiceResolve, DNSServiceResolveReply,
-    TXTRecordCreate, TXTRecordDeallocate, TXTRecordGetBytesPtr, TXTRecordGetLength, TXTRecordRef,
-    TXTRecordSetValue,
+    kDNSServiceFlagsBrowseDomains, kDNSServiceFlagsNoAutoRename,
+    kDNSServiceFlagsRegistrationDomains, kDNSServiceFlagsShareConnection, kDNSServiceFlagsShared,
+    kDNSServiceFlagsUnique, kDNSServiceProtocol_IPv4, kDNSServiceProtocol_IPv6, DNSRecordRef,
+    DNSServiceAddRecord, DNSServiceBrowse, DNSServiceBrowseReply, DNSServiceCreateConnection,
+    DNSServiceDomainEnumReply, DNSServiceEnumerateDomains, DNSServiceFlags, DNSServiceGetAddrInfo,
+    DNSServiceGetAddrInfoReply, DNSServiceQueryRecord, DNSServiceQueryRecordReply,
+    DNSServiceReconfirmRecord, DNSServiceRef, DNSServiceRegister, DNSServiceRegisterRecord,
+    DNSServiceRegisterRecordReply, DNSServiceRegisterReply, DNSServiceRemoveRecord,
+    DNSServiceResolve, DNSServiceResolveReply, DNSServiceUpdateRecord,
 };
 
 pub(crate) fn service_register(
+    connection: Option<DNSServiceRef>,
     reqtype: (&str, &str, u16),
     interface: &Interface,
     domain_host: (Option<&str>, Option<&str>),
     txt: &TxtRecord,
-    callback: DNSServiceRegisterReply,
     allow_rename: bool,
-    context: *mut libc::c_void,
+    callback_context: (DNSServiceRegisterReply, *mut libc::c_void),
 ) -> Result<DNSServiceRef, ZeroconfError> {
     log::trace!("Formatting C arguments for DNSServiceRegister");
-    let mut service_ref: DNSServiceRef = ptr::null_mut();
+    let (mut service_ref, flags) = share_connection(connection);
     let flags = if allow_rename {
-        0
+        flags
     } else {
-        kDNSServiceFlagsNoAutoRename
+        flags | kDNSServiceFlagsNoAutoRename
     };
     let intf_index = match interface {
         Interface::Unspecified => 0,
@@ -32,6 +39,7 @@ pub(crate) fn service_register(
 
     let (name, reqtype, port) = reqtype;
     let (domain, host) = domain_host;
+    let (callback, context) = callback_context;
 
     let cname = ffi::CString::new(name)?;
     let name = if !name.is_empty() {
@@ -54,27 +62,7 @@ pub(crate) fn service_register(
     };
     let port = port.to_be();
 
-    let mut txt_record = unsafe {
-        let mut txt: TXTRecordRef = mem::zeroed();
-        TXTRecordCreate(&mut txt, 0, ptr::null_mut());
-        txt
-    };
-    for (k, v) in txt.iter() {
-        let key = ffi::CString::new(k.clone())?;
-        let value_size = v.len().try_into().expect("max size should be checked");
-        let value = ffi::CString::new(v.clone())?;
-        unsafe {
-            TXTRecordSetValue(
-                &mut txt_record,
-                key.as_ptr(),
-                value_size,
-                value.as_ptr() as *const libc::c_void,
-            );
-        }
-    }
-
-    let txt_len = unsafe { TXTRecordGetLength(&txt_record) };
-    let txt_ptr = unsafe { TXTRecordGetBytesPtr(&txt_record) };
+    let (txt_len, txt_bytes) = txt_bytes(txt)?;
     log::trace!("Call DNSServiceRegister");
     let err = unsafe {
         DNSServiceRegister(
@@ -87,13 +75,11 @@ pub(crate) fn service_register(
             host,
             port,
             txt_len,
-            txt_ptr,
+            txt_bytes.as_ptr() as *const libc::c_void,
             callback,
             context,
         )
     };
-    // Deallocate any TXT resources
-    unsafe { TXTRecordDeallocate(&mut txt_record) };
 
     if err == 0 {
         Ok(service_ref)
@@ -102,7 +88,76 @@ pub(crate) fn service_register(
     }
 }
 
+pub(crate) fn update_txt(service_ref: DNSServiceRef, txt: &TxtRecord) -> Result<(), ZeroconfError> {
+    let (txt_len, txt_bytes) = txt_bytes(txt)?;
+    let txt_ptr = txt_bytes.as_ptr() as *const libc::c_void;
+    // A null record reference updates the primary TXT record of the service
+    log::trace!("Call DNSServiceUpdateRecord");
+    let err =
+        unsafe { DNSServiceUpdateRecord(service_ref, ptr::null_mut(), 0, txt_len, txt_ptr, 0) };
+
+    if err == 0 {
+        Ok(())
+    } else {
+        Err(err.into())
+    }
+}
+
+pub(crate) fn add_record(
+    service_ref: DNSServiceRef,
+    rrtype: RecordType,
+    rdata: &[u8],
+    ttl: u32,
+) -> Result<DNSRecordRef, ZeroconfError> {
+    let mut record_ref: DNSRecordRef = ptr::null_mut();
+    let rdlen = rdata.len().try_into().expect("max size should be checked");
+
+    log::trace!("Call DNSServiceAddRecord");
+    let err = unsafe {
+        DNSServiceAddRecord(
+            service_ref,
+            &mut record_ref as *mut DNSRecordRef,
+            0,
+            rrtype.into(),
+            rdlen,
+            rdata.as_ptr() as *const libc::c_void,
+            ttl,
+        )
+    };
+
+    if err == 0 {
+        Ok(record_ref)
+    } else {
+        Err(err.into())
+    }
+}
+
+pub(crate) fn remove_record(
+    service_ref: DNSServiceRef,
+    record_ref: DNSRecordRef,
+) -> Result<(), ZeroconfError> {
+    log::trace!("Call DNSServiceRemoveRecord");
+    let err = unsafe { DNSServiceRemoveRecord(service_ref, record_ref, 0) };
+
+    if err == 0 {
+        Ok(())
+    } else {
+        Err(err.into())
+    }
+}
+
+// Encode a TXT record, returning its length and data
+fn txt_bytes(txt: &TxtRecord) -> Result<(u16, Vec<u8>), ZeroconfError> {
+    let bytes = txt.to_bytes()?;
+    let len = bytes
+        .len()
+        .try_into()
+        .map_err(|_| ZeroconfError::InvalidTxtRecord(txt.to_string()))?;
+    Ok((len, bytes))
+}
+
 pub(crate) fn service_browse(
+    connection: Option<DNSServiceRef>,
     intf: &Interface,
     reqtype: &str,
     domain: Option<&str>,
@@ -110,7 +165,7 @@ pub(crate) fn service_browse(
     context: *mut libc::c_void,
 ) -> Result<DNSServiceRef, ZeroconfError> {
     log::trace!("Formatting C arguments for DNSServiceBrowse");
-    let mut service_ref: DNSServiceRef = ptr::null_mut();
+    let (mut service_ref, flags) = share_connection(connection);
 
     let intf_index = match intf {
         Interface::Unspecified => 0,
@@ -130,7 +185,7 @@ pub(crate) fn service_browse(
     let err = unsafe {
         DNSServiceBrowse(
             &mut service_ref as *mut DNSServiceRef,
-            0,
+            flags,
             intf_index,
             reqtype,
             domain,
@@ -147,6 +202,7 @@ pub(crate) fn service_browse(
 }
 
 pub(crate) fn service_resolve(
+    connection: Option<DNSServiceRef>,
     name: &str,
     intf: &Interface,
     reqtype: &str,
@@ -155,7 +211,7 @@ pub(crate) fn service_resolve(
     context: *mut libc::c_void,
 ) -> Result<DNSServiceRef, ZeroconfError> {
     log::trace!("Formatting C arguments for DNSServiceResolve");
-    let mut service_ref: DNSServiceRef = ptr::null_mut();
+    let (mut service_ref, flags) = share_connection(connection);
 
     let cname = ffi::CString::new(name)?;
     let name = cname.as_ptr();
@@ -175,7 +231,7 @@ pub(crate) fn service_resolve(
     let err = unsafe {
         DNSServiceResolve(
             &mut service_ref as *mut DNSServiceRef,
-            0,
+            flags,
             intf_index,
             name,
             reqtype,
@@ -191,3 +247,237 @@ pub(crate) fn service_resolve(
         Err(err.into())
     }
 }
+
+pub(crate) fn get_addr_info(
+    connection: Option<DNSServiceRef>,
+    intf: &Interface,
+    protocol: Protocol,
+    hostname: &str,
+    callback: DNSServiceGetAddrInfoReply,
+    context: *mut libc::c_void,
+) -> Result<DNSServiceRef, ZeroconfError> {
+    log::trace!("Formatting C arguments for DNSServiceGetAddrInfo");
+    let (mut service_ref, flags) = share_connection(connection);
+
+    let intf_index = match intf {
+        Interface::Unspecified => 0,
+        Interface::Interface(id) => *id,
+    };
+
+    let protocol = match protocol {
+        Protocol::Any => 0,
+        Protocol::IPv4 => kDNSServiceProtocol_IPv4,
+        Protocol::IPv6 => kDNSServiceProtocol_IPv6,
+    };
+
+    let chostname = ffi::CString::new(hostname)?;
+    let hostname = chostname.as_ptr();
+
+    log::trace!("Call DNSServiceGetAddrInfo");
+    let err = unsafe {
+        DNSServiceGetAddrInfo(
+            &mut service_ref as *mut DNSServiceRef,
+            flags,
+            intf_index,
+            protocol,
+            hostname,
+            callback,
+            context,
+        )
+    };
+
+    if err == 0 {
+        Ok(service_ref)
+    } else {
+        Err(err.into())
+    }
+}
+
+pub(crate) fn enumerate_domains(
+    connection: Option<DNSServiceRef>,
+    intf: &Interface,
+    kind: DomainKind,
+    callback: DNSServiceDomainEnumReply,
+    context: *mut libc::c_void,
+) -> Result<DNSServiceRef, ZeroconfError> {
+    log::trace!("Formatting C arguments for DNSServiceEnumerateDomains");
+    let (mut service_ref, flags) = share_connection(connection);
+
+    let intf_index = match intf {
+        Interface::Unspecified => 0,
+        Interface::Interface(id) => *id,
+    };
+
+    let flags = match kind {
+        DomainKind::Browse => flags | kDNSServiceFlagsBrowseDomains,
+        DomainKind::Registration => flags | kDNSServiceFlagsRegistrationDomains,
+    };
+
+    log::trace!("Call DNSServiceEnumerateDomains");
+    let err = unsafe {
+        DNSServiceEnumerateDomains(
+            &mut service_ref as *mut DNSServiceRef,
+            flags,
+            intf_index,
+            callback,
+            context,
+        )
+    };
+
+    if err == 0 {
+        Ok(service_ref)
+    } else {
+        Err(err.into())
+    }
+}
+
+pub(crate) fn query_record(
+    connection: Option<DNSServiceRef>,
+    intf: &Interface,
+    fullname: &str,
+    rrtype: RecordType,
+    rrclass: u16,
+    callback: DNSServiceQueryRecordReply,
+    context: *mut libc::c_void,
+) -> Result<DNSServiceRef, ZeroconfError> {
+    log::trace!("Formatting C arguments for DNSServiceQueryRecord");
+    let (mut service_ref, flags) = share_connection(connection);
+
+    let intf_index = match intf {
+        Interface::Unspecified => 0,
+        Interface::Interface(id) => *id,
+    };
+
+    let cfullname = ffi::CString::new(fullname)?;
+    let fullname = cfullname.as_ptr();
+
+    log::trace!("Call DNSServiceQueryRecord");
+    let err = unsafe {
+        DNSServiceQueryRecord(
+            &mut service_ref as *mut DNSServiceRef,
+            flags,
+            intf_index,
+            fullname,
+            rrtype.into(),
+            rrclass,
+            callback,
+            context,
+        )
+    };
+
+    if err == 0 {
+        Ok(service_ref)
+    } else {
+        Err(err.into())
+    }
+}
+
+// Get the service reference and flags to start an operation with, which
+// shares `connection` if set, so that the operation's events are processed
+// with those of the connection
+fn share_connection(connection: Option<DNSServiceRef>) -> (DNSServiceRef, DNSServiceFlags) {
+    match connection {
+        Some(connection) => (connection, kDNSServiceFlagsShareConnection),
+        None => (ptr::null_mut(), 0),
+    }
+}
+
+pub(crate) fn create_connection() -> Result<DNSServiceRef, ZeroconfError> {
+    let mut service_ref: DNSServiceRef = ptr::null_mut();
+
+    log::trace!("Call DNSServiceCreateConnection");
+    let err = unsafe { DNSServiceCreateConnection(&mut service_ref as *mut DNSServiceRef) };
+
+    if err == 0 {
+        Ok(service_ref)
+    } else {
+        Err(err.into())
+    }
+}
+
+pub(crate) fn register_record(
+    service_ref: DNSServiceRef,
+    intf: &Interface,
+    record: &ResourceRecord,
+    rdata: &[u8],
+    unique: bool,
+    callback: DNSServiceRegisterRecordReply,
+    context: *mut libc::c_void,
+) -> Result<DNSRecordRef, ZeroconfError> {
+    log::trace!("Formatting C arguments for DNSServiceRegisterRecord");
+    let mut record_ref: DNSRecordRef = ptr::null_mut();
+
+    let intf_index = match intf {
+        Interface::Unspecified => 0,
+        Interface::Interface(id) => *id,
+    };
+
+    let flags = if unique {
+        kDNSServiceFlagsUnique
+    } else {
+        kDNSServiceFlagsShared
+    };
+
+    let cfullname = ffi::CString::new(record.name())?;
+    let fullname = cfullname.as_ptr();
+    let rdlen = rdata.len().try_into().expect("max size should be checked");
+
+    log::trace!("Call DNSServiceRegisterRecord");
+    let err = unsafe {
+        DNSServiceRegisterRecord(
+            service_ref,
+            &mut record_ref as *mut DNSRecordRef,
+            flags,
+            intf_index,
+            fullname,
+            record.rrtype().into(),
+            record.rrclass(),
+            rdlen,
+            rdata.as_ptr() as *const libc::c_void,
+            record.ttl().unwrap_or(0),
+            callback,
+            context,
+        )
+    };
+
+    if err == 0 {
+        Ok(record_ref)
+    } else {
+        Err(err.into())
+    }
+}
+
+pub(crate) fn reconfirm_record(
+    intf: &Interface,
+    record: &ResourceRecord,
+    rdata: &[u8],
+) -> Result<(), ZeroconfError> {
+    log::trace!("Formatting C arguments for DNSServiceReconfirmRecord");
+    let intf_index = match intf {
+        Interface::Unspecified => 0,
+        Interface::Interface(id) => *id,
+    };
+
+    let cfullname = ffi::CString::new(record.name())?;
+    let fullname = cfullname.as_ptr();
+    let rdlen = rdata.len().try_into().expect("max size should be checked");
+
+    log::trace!("Call DNSServiceReconfirmRecord");
+    let err = unsafe {
+        DNSServiceReconfirmRecord(
+            0,
+            intf_index,
+            fullname,
+            record.rrtype().into(),
+            record.rrclass(),
+            rdlen,
+            rdata.as_ptr() as *const libc::c_void,
+        )
+    };
+
+    if err == 0 {
+        Ok(())
+    } else {
+        Err(err.into())
+    }
+}
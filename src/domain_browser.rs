@@ -0,0 +1,223 @@
+use crate::backend::{Backend, BackendSelection, DomainContext};
+use crate::{Interface, OpKind, OpType, ProcessTask, ServiceRef, ZeroconfError};
+
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures_core::Stream;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// `DomainKind` selects the kind of domains found by a [`DomainBrowser`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum DomainKind {
+    /// Domains recommended for browsing for services.
+    Browse,
+    /// Domains recommended for registering services.
+    Registration,
+}
+
+impl fmt::Display for DomainKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DomainKind::Browse => write!(f, "browse"),
+            DomainKind::Registration => write!(f, "registration"),
+        }
+    }
+}
+
+/// Struct representing a domain found by a [`DomainBrowser`].
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Domain {
+    name: String,
+    default: bool,
+}
+
+impl Domain {
+    /// Create a new `Domain` called `name`, which is the default domain of
+    /// its kind if `default` is set.
+    pub fn new(name: &str, default: bool) -> Self {
+        Domain {
+            name: name.to_string(),
+            default,
+        }
+    }
+
+    /// Get the name of the domain, e.g. `local.`
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get whether this is the default domain, which is used if no domain is
+    /// specified
+    pub fn is_default(&self) -> bool {
+        self.default
+    }
+}
+
+impl fmt::Display for Domain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.default {
+            write!(f, "{} (default)", self.name)
+        } else {
+            write!(f, "{}", self.name)
+        }
+    }
+}
+
+/// `DomainEvent` is produced by a [`DomainBrowser`] when a domain is found or
+/// removed.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum DomainEvent {
+    /// A domain has been found.
+    Added(Domain),
+    /// A domain previously found is no longer recommended.
+    Removed(Domain),
+}
+
+impl DomainEvent {
+    /// Get the domain that was added or removed.
+    pub fn domain(&self) -> &Domain {
+        match self {
+            DomainEvent::Added(d) | DomainEvent::Removed(d) => d,
+        }
+    }
+}
+
+/// `DomainBrowserBuilder` is used to find the domains that should be used to
+/// browse for or register services, for example to select a wide-area
+/// domain to pass to
+/// [`ServiceBrowserBuilder::domain`][`crate::ServiceBrowserBuilder::domain`].
+///
+/// # Examples
+/// ```
+/// # tokio_test::block_on(async {
+/// use async_zeroconf::{DomainBrowserBuilder, DomainEvent, DomainKind};
+///
+/// let mut builder = DomainBrowserBuilder::new(DomainKind::Browse);
+/// let mut domains = builder
+///     .timeout(tokio::time::Duration::from_secs(2))
+///     .browse()?;
+///
+/// while let Some(Ok(event)) = domains.recv().await {
+///     match event {
+///         DomainEvent::Added(d) => println!("Added = {}", d),
+///         DomainEvent::Removed(d) => println!("Removed = {}", d),
+///     }
+/// }
+/// # Ok::<(), async_zeroconf::ZeroconfError>(())
+/// # });
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct DomainBrowserBuilder {
+    kind: DomainKind,
+    interface: Interface,
+    timeout: Option<Duration>,
+    backend: BackendSelection,
+}
+
+impl DomainBrowserBuilder {
+    /// Create a new `DomainBrowserBuilder` for domains of kind `kind`
+    pub fn new(kind: DomainKind) -> Self {
+        DomainBrowserBuilder {
+            kind,
+            interface: Default::default(),
+            timeout: None,
+            backend: Default::default(),
+        }
+    }
+
+    /// Set the timeout
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the interface to find domains on rather than all
+    pub fn interface(&mut self, interface: Interface) -> &mut Self {
+        self.interface = interface;
+        self
+    }
+
+    /// Set the backend used to find domains rather than the default backend
+    pub fn backend(&mut self, backend: Arc<dyn Backend>) -> &mut Self {
+        self.backend = BackendSelection::new(backend);
+        self
+    }
+
+    /// Start finding domains, which will continue until the specified
+    /// timeout or until the [`DomainBrowser`] is dropped.
+    pub fn browse(&self) -> Result<DomainBrowser, ZeroconfError> {
+        let (browser, task) = self.browse_task()?;
+
+        tokio::spawn(task);
+
+        Ok(browser)
+    }
+
+    /// Start finding domains, which will continue until the specified
+    /// timeout or until the [`DomainBrowser`] is dropped. The returned
+    /// [`ProcessTask`] future must be awaited to process events associated
+    /// with the browser.
+    ///
+    /// # Note
+    /// This method is intended if more control is needed over how the task
+    /// is spawned. [`DomainBrowserBuilder::browse`] will automatically spawn
+    /// the task.
+    pub fn browse_task(&self) -> Result<(DomainBrowser, impl ProcessTask), ZeroconfError> {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        let operation = self.backend.get()?.enumerate_domains(
+            &self.interface,
+            self.kind,
+            DomainContext::new(tx),
+        )?;
+
+        let (delegate, task) = ServiceRef::from_operation(
+            operation,
+            OpType::new(&self.kind.to_string(), OpKind::EnumerateDomains),
+            self.timeout,
+        );
+
+        log::debug!("Created DomainBrowser ({})", self.kind);
+        Ok((DomainBrowser { rx, delegate }, task))
+    }
+}
+
+/// Struct used to get the [`DomainEvent`]s of a domain browser, which should
+/// be constructed with a [`DomainBrowserBuilder`]. It can be used as a stream
+/// or with [`DomainBrowser::recv`].
+#[derive(Debug)]
+pub struct DomainBrowser {
+    // Channel to receive events
+    rx: mpsc::UnboundedReceiver<Result<DomainEvent, ZeroconfError>>,
+    // Reference to the operation finding domains
+    delegate: ServiceRef,
+}
+
+impl DomainBrowser {
+    /// Receive an event from the browser.
+    ///
+    /// A response of `None` indicates that the browse operation has
+    /// finished, for example due to a timeout or error.
+    pub async fn recv(&mut self) -> Option<Result<DomainEvent, ZeroconfError>> {
+        self.rx.recv().await
+    }
+
+    /// Get the [`OpType`] of the operation finding domains.
+    pub fn op_type(&self) -> &OpType {
+        self.delegate.op_type()
+    }
+}
+
+impl Stream for DomainBrowser {
+    type Item = Result<DomainEvent, ZeroconfError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<<Self as futures_core::Stream>::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
@@ -2,7 +2,6 @@ use std::ffi::NulError;
 use std::fmt;
 
 use crate::Service;
-use bonjour_sys::DNSServiceErrorType;
 use std::error::Error;
 use std::str::Utf8Error;
 use std::sync::PoisonError;
@@ -23,6 +22,10 @@ pub enum ZeroconfError {
     Timeout(Service),
     /// The service type specified is invalid
     InvalidServiceType(String),
+    /// The service subtype specified is invalid
+    InvalidSubtype(String),
+    /// The full name of a service instance is invalid
+    InvalidInstanceName(String),
     /// The TXT record specified is invalid
     InvalidTxtRecord(String),
     /// A service was passed to resolve that was not from a
@@ -32,6 +35,10 @@ pub enum ZeroconfError {
     /// about domain, interface and so on to be in the format provided from
     /// the browse operation.
     NotFromBrowser(Service),
+    /// A service was passed to look up addresses that has not been resolved
+    /// by a [`ServiceResolver`][crate::ServiceResolver], so the host is not
+    /// known.
+    NotResolved(Service),
     /// Null byte in a string conversion
     NullString(NulError),
     /// Poisoned Mutex
@@ -42,6 +49,13 @@ pub enum ZeroconfError {
     InterfaceNotFound(String),
     /// Dropped a task
     Dropped,
+    /// No backend is available to perform the operation
+    NoBackend,
+    /// An error communicating with `avahi-daemon` over D-Bus
+    ///
+    /// Only returned by the Avahi backend, the error is boxed so that the
+    /// variant doesn't depend on the `avahi` feature.
+    DBus(Box<dyn Error + Send + Sync>),
 }
 
 impl From<PoisonError<std::sync::MutexGuard<'_, ()>>> for ZeroconfError {
@@ -68,6 +82,20 @@ impl From<std::io::Error> for ZeroconfError {
     }
 }
 
+#[cfg(feature = "avahi")]
+impl From<zbus::Error> for ZeroconfError {
+    fn from(s: zbus::Error) -> Self {
+        ZeroconfError::DBus(Box::new(s))
+    }
+}
+
+#[cfg(feature = "avahi")]
+impl From<zbus::zvariant::Error> for ZeroconfError {
+    fn from(s: zbus::zvariant::Error) -> Self {
+        ZeroconfError::DBus(Box::new(zbus::Error::from(s)))
+    }
+}
+
 impl From<i32> for ZeroconfError {
     fn from(s: i32) -> Self {
         ZeroconfError::Bonjour(s.into())
@@ -87,15 +115,22 @@ impl fmt::Display for ZeroconfError {
             ZeroconfError::Io(e) => e.to_string(),
             ZeroconfError::Timeout(s) => format!("timeout on {}", s.service_type()),
             ZeroconfError::InvalidServiceType(s) => format!("invalid service type '{}'", s),
+            ZeroconfError::InvalidSubtype(s) => format!("invalid subtype '{}'", s),
+            ZeroconfError::InvalidInstanceName(s) => format!("invalid instance name '{}'", s),
             ZeroconfError::InvalidTxtRecord(s) => format!("invalid txt record '{}'", s),
             ZeroconfError::NotFromBrowser(s) => {
                 format!("'{}' service not from browser", s.service_type())
             }
+            ZeroconfError::NotResolved(s) => {
+                format!("'{}' service not resolved", s.service_type())
+            }
             ZeroconfError::NullString(s) => s.to_string(),
             ZeroconfError::Poison => "mutex was poisoned".to_string(),
             ZeroconfError::Utf8(e) => e.to_string(),
             ZeroconfError::InterfaceNotFound(s) => format!("interface not found '{}'", s),
             ZeroconfError::Dropped => "task dropped before expected".to_string(),
+            ZeroconfError::NoBackend => "no backend available".to_string(),
+            ZeroconfError::DBus(e) => format!("error from avahi - {}", e),
         };
         write!(f, "{}", s)
     }
@@ -108,6 +143,7 @@ impl Error for ZeroconfError {
             ZeroconfError::Io(e) => Some(e),
             ZeroconfError::NullString(e) => Some(e),
             ZeroconfError::Utf8(e) => Some(e),
+            ZeroconfError::DBus(e) => Some(e.as_ref()),
             _ => None,
         }
     }
@@ -165,6 +201,8 @@ pub enum BonjourError {
     DoubleNAT,
     /// Bad time
     BadTime,
+    /// The daemon is not running, or has stopped
+    ServiceNotRunning,
     /// Undefined error
     Undefined,
 }
@@ -194,6 +232,7 @@ impl fmt::Display for BonjourError {
             BonjourError::NATTraversal => "NAT traversal",
             BonjourError::DoubleNAT => "double NAT",
             BonjourError::BadTime => "bad time",
+            BonjourError::ServiceNotRunning => "service not running",
             BonjourError::Undefined => "undefined error",
         };
         write!(f, "{}", s)
@@ -202,8 +241,8 @@ impl fmt::Display for BonjourError {
 
 impl std::error::Error for BonjourError {}
 
-impl From<DNSServiceErrorType> for BonjourError {
-    fn from(err: DNSServiceErrorType) -> Self {
+impl From<i32> for BonjourError {
+    fn from(err: i32) -> Self {
         match err {
             -65537 => BonjourError::Unknown,
             -65538 => BonjourError::NoSuchName,
@@ -227,6 +266,7 @@ impl From<DNSServiceErrorType> for BonjourError {
             -65557 => BonjourError::NATTraversal,
             -65558 => BonjourError::DoubleNAT,
             -65559 => BonjourError::BadTime,
+            -65563 => BonjourError::ServiceNotRunning,
             _ => BonjourError::Undefined,
         }
     }
@@ -0,0 +1,163 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::ZeroconfError;
+
+/// Struct representing the full domain name of a service instance, made up
+/// of the instance name, the service type and the domain, e.g.
+/// `My\032Printer._ipp._tcp.local.`.
+///
+/// The instance name may contain any characters, so is escaped as described
+/// in [RFC 6763 §4.3][rfc] when formatted, in the same way as
+/// [`DNSServiceConstructFullName`][construct]. Dots and backslashes are
+/// escaped with a backslash and other characters up to and including space
+/// are escaped as `\DDD` decimal values.
+///
+/// # Examples
+/// ```
+/// use async_zeroconf::ServiceInstanceName;
+///
+/// let instance = ServiceInstanceName::new("My Printer", "_ipp._tcp", "local.");
+/// assert_eq!(instance.to_string(), "My\\032Printer._ipp._tcp.local.");
+///
+/// let parsed: ServiceInstanceName = "My\\032Printer._ipp._tcp.local.".parse()?;
+/// assert_eq!(parsed.name(), "My Printer");
+/// assert_eq!(parsed.service_type(), "_ipp._tcp");
+/// assert_eq!(parsed.domain(), "local");
+/// # Ok::<(), async_zeroconf::ZeroconfError>(())
+/// ```
+/// [rfc]: https://www.rfc-editor.org/rfc/rfc6763#section-4.3
+/// [construct]: https://developer.apple.com/documentation/dnssd/1804753-dnsserviceconstructfullname?language=objc
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ServiceInstanceName {
+    name: String,
+    service_type: String,
+    domain: String,
+}
+
+impl ServiceInstanceName {
+    /// Create a new `ServiceInstanceName` for the instance called `name`, of
+    /// type `service_type` (e.g. `_ipp._tcp`) in the domain `domain`.
+    ///
+    /// The service type and domain are in their usual dotted form, so are not
+    /// escaped, and any trailing dot is ignored.
+    pub fn new(name: &str, service_type: &str, domain: &str) -> Self {
+        ServiceInstanceName {
+            name: name.to_string(),
+            service_type: service_type.trim_end_matches('.').to_string(),
+            domain: domain.trim_end_matches('.').to_string(),
+        }
+    }
+
+    /// Get the instance name, without any escaping
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the service type, without a trailing dot
+    pub fn service_type(&self) -> &str {
+        &self.service_type
+    }
+
+    /// Get the domain, without a trailing dot
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+}
+
+impl fmt::Display for ServiceInstanceName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}.",
+            escape_label(&self.name),
+            self.service_type,
+            self.domain
+        )
+    }
+}
+
+impl FromStr for ServiceInstanceName {
+    type Err = ZeroconfError;
+
+    /// Parse an escaped full domain name, such as one reported by
+    /// `DNSServiceResolve`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ZeroconfError::InvalidInstanceName(s.to_string());
+
+        let labels = split_labels(s).ok_or_else(invalid)?;
+        let (name, rest) = labels.split_first().ok_or_else(invalid)?;
+        if rest.len() < 3 {
+            return Err(invalid());
+        }
+        let (service_type, domain) = rest.split_at(2);
+        let protocol = &service_type[1];
+        if !service_type[0].starts_with('_')
+            || !(protocol.eq_ignore_ascii_case("_tcp") || protocol.eq_ignore_ascii_case("_udp"))
+        {
+            return Err(invalid());
+        }
+
+        let domain: Vec<String> = domain.iter().map(|l| escape_label(l)).collect();
+        Ok(ServiceInstanceName {
+            name: name.clone(),
+            service_type: service_type.join("."),
+            domain: domain.join("."),
+        })
+    }
+}
+
+// Escape a label as done by `DNSServiceConstructFullName`
+pub(crate) fn escape_label(label: &str) -> String {
+    let mut escaped = String::with_capacity(label.len());
+    for c in label.chars() {
+        match c {
+            '.' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c if c <= ' ' => escaped.push_str(&format!("\\{:03}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Split an escaped domain name into its unescaped labels, the trailing dot is
+// optional
+pub(crate) fn split_labels(s: &str) -> Option<Vec<String>> {
+    let mut labels = Vec::new();
+    let mut label = Vec::new();
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'\\' => {
+                let c = bytes.next()?;
+                if c.is_ascii_digit() {
+                    let digits = [c, bytes.next()?, bytes.next()?];
+                    if !digits.iter().all(|d| d.is_ascii_digit()) {
+                        return None;
+                    }
+                    let value = digits
+                        .iter()
+                        .fold(0u32, |v, d| v * 10 + u32::from(d - b'0'));
+                    label.push(u8::try_from(value).ok()?);
+                } else {
+                    label.push(c);
+                }
+            }
+            b'.' => {
+                if label.is_empty() {
+                    return None;
+                }
+                labels.push(String::from_utf8(std::mem::take(&mut label)).ok()?);
+            }
+            b => label.push(b),
+        }
+    }
+    if !label.is_empty() {
+        labels.push(String::from_utf8(label).ok()?);
+    }
+    Some(labels)
+}
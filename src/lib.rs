@@ -16,27 +16,60 @@
 //! ```
 //!
 //! [`ServiceBrowserBuilder`] and [`ServiceResolver`] can be used to browse and
-//! resolve services respectively.
+//! resolve services respectively, and [`AddrResolver`] to look up the
+//! addresses of a resolved service. [`ServiceDirectory`] maintains the
+//! current set of services found by a browser.
+//!
+//! The operations are implemented by a [`Backend`][`backend::Backend`], which
+//! by default uses the Bonjour API. See the [`backend`] module for how to
+//! select a different backend.
 
 #![warn(clippy::doc_markdown, missing_docs)]
 
+mod addr_resolver;
+pub mod backend;
+#[cfg(feature = "bonjour")]
 mod c_intf;
+mod domain_browser;
 mod error;
+mod instance_name;
 mod interface;
+mod record_query;
+mod record_registrar;
 mod service;
 mod service_browser;
+mod service_directory;
 mod service_ref;
 mod service_resolver;
+mod service_type;
+mod service_type_browser;
+mod supervision;
+mod sync;
 mod txt;
 
-pub(crate) use service_ref::ServiceRefWrapper;
-
+pub use addr_resolver::{AddrEvent, AddrEvents, AddrResolver, Protocol};
+pub use domain_browser::{Domain, DomainBrowser, DomainBrowserBuilder, DomainEvent, DomainKind};
 pub use error::{BonjourError, ZeroconfError};
+pub use instance_name::ServiceInstanceName;
 pub use interface::Interface;
+pub use record_query::{
+    RecordData, RecordEvent, RecordEvents, RecordQuery, RecordType, ResourceRecord,
+};
+pub use record_registrar::{RecordRegistrar, RecordRegistrarBuilder};
 pub use service::Service;
-pub use service_browser::{ServiceBrowser, ServiceBrowserBuilder};
-pub use service_ref::{OpKind, OpType, ProcessTask, ServiceRef};
+pub use service_browser::{BrowseEvent, ServiceBrowser, ServiceBrowserBuilder};
+pub use service_directory::{
+    DirectoryEntry, DirectoryEvent, DirectoryEvents, DirectoryServices, ServiceDirectory,
+    ServiceKey,
+};
+pub use service_ref::{
+    OpKind, OpType, ProcessTask, RecordRef, RegisteredService, RegistrationEvent,
+    RegistrationEvents, Renames, ServiceRef,
+};
 pub use service_resolver::ServiceResolver;
+pub use service_type::{ServiceProtocol, ServiceType};
+pub use service_type_browser::{ServiceTypeBrowser, ServiceTypeBrowserBuilder, ServiceTypeEvent};
+pub use supervision::{ReconnectEvent, ReconnectEvents, Supervision};
 pub use txt::TxtRecord;
 
 #[cfg(test)]
@@ -0,0 +1,466 @@
+use crate::backend::{Backend, BackendSelection, RecordContext};
+use crate::instance_name::{escape_label, split_labels};
+use crate::{
+    BonjourError, Interface, OpKind, OpType, ProcessTask, ServiceRef, TxtRecord, ZeroconfError,
+};
+
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures_core::Stream;
+use std::convert::TryInto;
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Enum to hold the type of a DNS resource record.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum RecordType {
+    /// An IPv4 address (`A`)
+    A,
+    /// An IPv6 address (`AAAA`)
+    Aaaa,
+    /// A pointer to another name (`PTR`)
+    Ptr,
+    /// The host and port of a service (`SRV`)
+    Srv,
+    /// Text strings, such as the key/value pairs of a service (`TXT`)
+    Txt,
+    /// The CPU and operating system of a host (`HINFO`)
+    Hinfo,
+    /// Any type, only used to query for records (`ANY`)
+    Any,
+    /// Any other type, identified by its numeric value
+    Other(u16),
+}
+
+impl From<u16> for RecordType {
+    fn from(rrtype: u16) -> Self {
+        match rrtype {
+            1 => RecordType::A,
+            12 => RecordType::Ptr,
+            13 => RecordType::Hinfo,
+            16 => RecordType::Txt,
+            28 => RecordType::Aaaa,
+            33 => RecordType::Srv,
+            255 => RecordType::Any,
+            t => RecordType::Other(t),
+        }
+    }
+}
+
+impl From<RecordType> for u16 {
+    fn from(rrtype: RecordType) -> Self {
+        match rrtype {
+            RecordType::A => 1,
+            RecordType::Ptr => 12,
+            RecordType::Hinfo => 13,
+            RecordType::Txt => 16,
+            RecordType::Aaaa => 28,
+            RecordType::Srv => 33,
+            RecordType::Any => 255,
+            RecordType::Other(t) => t,
+        }
+    }
+}
+
+/// Enum to hold the data of a DNS resource record, for the types that are
+/// commonly used with `ZeroConf`. Other types are held as the raw bytes of
+/// the record.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RecordData {
+    /// An IPv4 address
+    A(Ipv4Addr),
+    /// An IPv6 address
+    Aaaa(Ipv6Addr),
+    /// The full domain name pointed to, e.g. `Server._http._tcp.local.`
+    Ptr(String),
+    /// The host and port of a service
+    Srv {
+        /// Priority of the target host, lower values are preferred
+        priority: u16,
+        /// Relative weight of targets with the same priority
+        weight: u16,
+        /// Port of the service on the target host
+        port: u16,
+        /// Full domain name of the target host, e.g. `host.local.`
+        target: String,
+    },
+    /// Key/value pairs in the format used by services
+    Txt(TxtRecord),
+    /// The CPU and operating system of a host
+    Hinfo {
+        /// The CPU type
+        cpu: String,
+        /// The operating system
+        os: String,
+    },
+    /// The raw data of a record of any other type, or a record that couldn't
+    /// be parsed
+    Other(u16, Vec<u8>),
+}
+
+impl RecordData {
+    /// Get the type of the record holding this data.
+    pub fn rrtype(&self) -> RecordType {
+        match self {
+            RecordData::A(_) => RecordType::A,
+            RecordData::Aaaa(_) => RecordType::Aaaa,
+            RecordData::Ptr(_) => RecordType::Ptr,
+            RecordData::Srv { .. } => RecordType::Srv,
+            RecordData::Txt(_) => RecordType::Txt,
+            RecordData::Hinfo { .. } => RecordType::Hinfo,
+            RecordData::Other(t, _) => RecordType::from(*t),
+        }
+    }
+
+    /// Parse the uncompressed wire format data of a record of type `rrtype`
+    pub(crate) fn from_rdata(rrtype: u16, rdata: &[u8]) -> Self {
+        parse_rdata(rrtype, rdata).unwrap_or_else(|| RecordData::Other(rrtype, rdata.to_vec()))
+    }
+
+    /// Encode the data in the uncompressed wire format
+    pub(crate) fn to_rdata(&self) -> Result<Vec<u8>, ZeroconfError> {
+        let mut rdata = Vec::new();
+        match self {
+            RecordData::A(a) => rdata.extend_from_slice(&a.octets()),
+            RecordData::Aaaa(a) => rdata.extend_from_slice(&a.octets()),
+            RecordData::Ptr(name) => write_name(&mut rdata, name)?,
+            RecordData::Srv {
+                priority,
+                weight,
+                port,
+                target,
+            } => {
+                rdata.extend_from_slice(&priority.to_be_bytes());
+                rdata.extend_from_slice(&weight.to_be_bytes());
+                rdata.extend_from_slice(&port.to_be_bytes());
+                write_name(&mut rdata, target)?;
+            }
+            RecordData::Txt(txt) => rdata = txt.to_bytes()?,
+            RecordData::Hinfo { cpu, os } => {
+                write_character_string(&mut rdata, cpu.as_bytes())?;
+                write_character_string(&mut rdata, os.as_bytes())?;
+            }
+            RecordData::Other(_, data) => rdata.extend_from_slice(data),
+        }
+        Ok(rdata)
+    }
+}
+
+// Parse record data, returning `None` if it isn't valid for the type
+fn parse_rdata(rrtype: u16, rdata: &[u8]) -> Option<RecordData> {
+    let data = match RecordType::from(rrtype) {
+        RecordType::A => {
+            let octets: [u8; 4] = rdata.try_into().ok()?;
+            RecordData::A(octets.into())
+        }
+        RecordType::Aaaa => {
+            let octets: [u8; 16] = rdata.try_into().ok()?;
+            RecordData::Aaaa(octets.into())
+        }
+        RecordType::Ptr => {
+            let mut pos = 0;
+            let name = read_name(rdata, &mut pos)?;
+            if pos != rdata.len() {
+                return None;
+            }
+            RecordData::Ptr(name)
+        }
+        RecordType::Srv => {
+            let mut pos = 6;
+            let target = read_name(rdata, &mut pos)?;
+            if pos != rdata.len() {
+                return None;
+            }
+            RecordData::Srv {
+                priority: u16::from_be_bytes([rdata[0], rdata[1]]),
+                weight: u16::from_be_bytes([rdata[2], rdata[3]]),
+                port: u16::from_be_bytes([rdata[4], rdata[5]]),
+                target,
+            }
+        }
+        RecordType::Txt => RecordData::Txt(TxtRecord::from_bytes(rdata).ok()?),
+        RecordType::Hinfo => match character_strings(rdata)?.as_slice() {
+            [cpu, os] => RecordData::Hinfo {
+                cpu: String::from_utf8_lossy(cpu).into_owned(),
+                os: String::from_utf8_lossy(os).into_owned(),
+            },
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(data)
+}
+
+// Read an uncompressed domain name starting at `pos`, escaping each label
+fn read_name(rdata: &[u8], pos: &mut usize) -> Option<String> {
+    let mut name = String::new();
+    loop {
+        let len = *rdata.get(*pos)? as usize;
+        *pos += 1;
+        if len == 0 {
+            break;
+        }
+        let label = rdata.get(*pos..*pos + len)?;
+        name.push_str(&escape_label(&String::from_utf8_lossy(label)));
+        name.push('.');
+        *pos += len;
+    }
+    if name.is_empty() {
+        name.push('.');
+    }
+    Some(name)
+}
+
+// Write an escaped domain name without compression
+fn write_name(rdata: &mut Vec<u8>, name: &str) -> Result<(), ZeroconfError> {
+    let labels = split_labels(name).ok_or(BonjourError::BadParam)?;
+    for label in labels {
+        if label.len() > 63 {
+            return Err(BonjourError::BadParam.into());
+        }
+        rdata.push(label.len() as u8);
+        rdata.extend_from_slice(label.as_bytes());
+    }
+    rdata.push(0);
+    Ok(())
+}
+
+// Write a length-prefixed string as used by TXT and HINFO records
+fn write_character_string(rdata: &mut Vec<u8>, string: &[u8]) -> Result<(), ZeroconfError> {
+    let len: u8 = string
+        .len()
+        .try_into()
+        .map_err(|_| BonjourError::BadParam)?;
+    rdata.push(len);
+    rdata.extend_from_slice(string);
+    Ok(())
+}
+
+// Split data into the length-prefixed strings used by TXT and HINFO records
+fn character_strings(rdata: &[u8]) -> Option<Vec<&[u8]>> {
+    let mut strings = Vec::new();
+    let mut pos = 0;
+    while pos < rdata.len() {
+        let len = rdata[pos] as usize;
+        strings.push(rdata.get(pos + 1..pos + 1 + len)?);
+        pos += 1 + len;
+    }
+    Some(strings)
+}
+
+/// Struct representing a DNS resource record found by a [`RecordQuery`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ResourceRecord {
+    name: String,
+    rrclass: u16,
+    ttl: Option<u32>,
+    data: RecordData,
+}
+
+impl ResourceRecord {
+    /// Create a new `ResourceRecord` for the full domain name `name`, in the
+    /// class `rrclass` (usually [`RecordQuery::CLASS_IN`]).
+    pub fn new(name: &str, rrclass: u16, ttl: Option<u32>, data: RecordData) -> Self {
+        ResourceRecord {
+            name: name.to_string(),
+            rrclass,
+            ttl,
+            data,
+        }
+    }
+
+    /// Get the full domain name of the record
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the type of the record
+    pub fn rrtype(&self) -> RecordType {
+        self.data.rrtype()
+    }
+
+    /// Get the class of the record
+    pub fn rrclass(&self) -> u16 {
+        self.rrclass
+    }
+
+    /// Get the time to live of the record in seconds, if reported by the
+    /// backend
+    pub fn ttl(&self) -> Option<u32> {
+        self.ttl
+    }
+
+    /// Get the data of the record
+    pub fn data(&self) -> &RecordData {
+        &self.data
+    }
+}
+
+impl fmt::Display for ResourceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:?} {:?}", self.name, self.rrtype(), self.data)
+    }
+}
+
+/// `RecordEvent` is produced by [`RecordEvents`] when a record is found or
+/// removed.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RecordEvent {
+    /// A record has been found.
+    Added(ResourceRecord),
+    /// A record previously found is no longer valid.
+    Removed(ResourceRecord),
+}
+
+impl RecordEvent {
+    /// Get the record that was added or removed.
+    pub fn record(&self) -> &ResourceRecord {
+        match self {
+            RecordEvent::Added(r) | RecordEvent::Removed(r) => r,
+        }
+    }
+}
+
+/// `RecordQuery` is used to query for arbitrary DNS records, such as the
+/// `HINFO` record of a host or the `_device-info._tcp` TXT record of a
+/// device, which aren't available by browsing or resolving services.
+///
+/// # Examples
+/// ```
+/// # tokio_test::block_on(async {
+/// use async_zeroconf::{RecordData, RecordEvent, RecordQuery, RecordType};
+///
+/// let mut records = RecordQuery::new("host.local.", RecordType::Hinfo, RecordQuery::CLASS_IN)
+///     .set_timeout(tokio::time::Duration::from_secs(2))
+///     .query()?;
+///
+/// while let Some(Ok(RecordEvent::Added(record))) = records.recv().await {
+///     if let RecordData::Hinfo { cpu, os } = record.data() {
+///         println!("CPU = {}, OS = {}", cpu, os);
+///     }
+/// }
+/// # Ok::<(), async_zeroconf::ZeroconfError>(())
+/// # });
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct RecordQuery {
+    fullname: String,
+    rrtype: RecordType,
+    rrclass: u16,
+    interface: Interface,
+    timeout: Option<Duration>,
+    backend: BackendSelection,
+}
+
+impl RecordQuery {
+    /// The Internet class, used for all multicast DNS records
+    pub const CLASS_IN: u16 = 1;
+
+    /// Create a new `RecordQuery` for records of type `rrtype` and class
+    /// `rrclass` with the full domain name `fullname`.
+    pub fn new(fullname: &str, rrtype: RecordType, rrclass: u16) -> Self {
+        RecordQuery {
+            fullname: fullname.to_string(),
+            rrtype,
+            rrclass,
+            interface: Default::default(),
+            timeout: None,
+            backend: Default::default(),
+        }
+    }
+
+    /// Set the interface to query on rather than all
+    pub fn set_interface(&mut self, interface: Interface) -> &mut Self {
+        self.interface = interface;
+        self
+    }
+
+    /// Set the timeout
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the backend used for the query rather than the default backend
+    pub fn set_backend(&mut self, backend: Arc<dyn Backend>) -> &mut Self {
+        self.backend = BackendSelection::new(backend);
+        self
+    }
+
+    /// Start the query, producing a [`RecordEvent`] each time a record is
+    /// added or removed. The query continues until the timeout or until the
+    /// [`RecordEvents`] is dropped.
+    pub fn query(&self) -> Result<RecordEvents, ZeroconfError> {
+        let (events, task) = self.query_task()?;
+
+        tokio::spawn(task);
+
+        Ok(events)
+    }
+
+    /// Start the query. The returned [`ProcessTask`] future must be awaited
+    /// to process events associated with the query.
+    ///
+    /// # Note
+    /// This method is intended if more control is needed over how the task
+    /// is spawned. [`RecordQuery::query`] will automatically spawn the task.
+    pub fn query_task(&self) -> Result<(RecordEvents, impl ProcessTask), ZeroconfError> {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        let operation = self.backend.get()?.query_record(
+            &self.interface,
+            &self.fullname,
+            self.rrtype,
+            self.rrclass,
+            RecordContext::new(tx),
+        )?;
+
+        let (delegate, task) = ServiceRef::from_operation(
+            operation,
+            OpType::new(&self.fullname, OpKind::QueryRecord),
+            self.timeout,
+        );
+
+        log::debug!("Created RecordEvents for '{}'", self.fullname);
+        Ok((RecordEvents { rx, delegate }, task))
+    }
+}
+
+/// Struct used to get the record events of a [`RecordQuery`], which can be
+/// used as a stream or with [`RecordEvents::recv`].
+#[derive(Debug)]
+pub struct RecordEvents {
+    // Channel to receive events
+    rx: mpsc::UnboundedReceiver<Result<RecordEvent, ZeroconfError>>,
+    // Reference to the operation performing the query
+    delegate: ServiceRef,
+}
+
+impl RecordEvents {
+    /// Receive an event from the query.
+    ///
+    /// A response of `None` indicates that the query has finished, for
+    /// example due to a timeout or error.
+    pub async fn recv(&mut self) -> Option<Result<RecordEvent, ZeroconfError>> {
+        self.rx.recv().await
+    }
+
+    /// Get the [`OpType`] of the operation performing the query.
+    pub fn op_type(&self) -> &OpType {
+        self.delegate.op_type()
+    }
+}
+
+impl Stream for RecordEvents {
+    type Item = Result<RecordEvent, ZeroconfError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<<Self as futures_core::Stream>::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
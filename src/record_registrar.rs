@@ -0,0 +1,136 @@
+use crate::backend::{Backend, BackendSelection};
+use crate::{
+    Interface, OpKind, OpType, ProcessTask, RecordData, RecordQuery, RecordRef, ResourceRecord,
+    ServiceRef, ZeroconfError,
+};
+
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// `RecordRegistrarBuilder` is used to register individual DNS records,
+/// rather than the records of a service. For example, the addresses of a
+/// device that can't use `ZeroConf` itself can be registered, so that
+/// services can be published on its behalf with
+/// [`Service::set_host`][`crate::Service::set_host`].
+///
+/// # Examples
+/// ```
+/// # tokio_test::block_on(async {
+/// let registrar = async_zeroconf::RecordRegistrarBuilder::new().connect()?;
+///
+/// let record_ref = registrar
+///     .register_address("printer.local.", "192.168.0.20".parse().unwrap())
+///     .await?;
+///
+/// let service_ref = async_zeroconf::Service::new("Printer", "_ipp._tcp", 631)
+///     .set_host("printer.local.".to_string())
+///     .publish()
+///     .await?;
+/// # Ok::<(), async_zeroconf::ZeroconfError>(())
+/// # });
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Default)]
+pub struct RecordRegistrarBuilder {
+    interface: Interface,
+    backend: BackendSelection,
+}
+
+impl RecordRegistrarBuilder {
+    /// Create a new `RecordRegistrarBuilder`
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the interface to register records on rather than all
+    pub fn interface(&mut self, interface: Interface) -> &mut Self {
+        self.interface = interface;
+        self
+    }
+
+    /// Set the backend used to register records rather than the default
+    /// backend
+    pub fn backend(&mut self, backend: Arc<dyn Backend>) -> &mut Self {
+        self.backend = BackendSelection::new(backend);
+        self
+    }
+
+    /// Create a [`RecordRegistrar`], which registers records until it is
+    /// dropped.
+    pub fn connect(&self) -> Result<RecordRegistrar, ZeroconfError> {
+        let (registrar, task) = self.connect_task()?;
+
+        tokio::spawn(task);
+
+        Ok(registrar)
+    }
+
+    /// Create a [`RecordRegistrar`], which registers records until it is
+    /// dropped. The returned [`ProcessTask`] future must be awaited to
+    /// process events associated with the registrar.
+    ///
+    /// # Note
+    /// This method is intended if more control is needed over how the task
+    /// is spawned. [`RecordRegistrarBuilder::connect`] will automatically
+    /// spawn the task.
+    pub fn connect_task(&self) -> Result<(RecordRegistrar, impl ProcessTask), ZeroconfError> {
+        let operation = self.backend.get()?.register_records(&self.interface)?;
+
+        let (delegate, task) = ServiceRef::from_operation(
+            operation,
+            OpType::new(&self.interface.to_string(), OpKind::RegisterRecord),
+            None,
+        );
+
+        log::debug!("Created RecordRegistrar");
+        Ok((RecordRegistrar { delegate }, task))
+    }
+}
+
+/// Struct used to register individual DNS records, which should be
+/// constructed with a [`RecordRegistrarBuilder`].
+///
+/// Each record remains registered while the [`RecordRef`] returned for it is
+/// held. All the records are removed when the `RecordRegistrar` is dropped.
+#[derive(Debug)]
+pub struct RecordRegistrar {
+    // Reference to the operation registering records
+    delegate: ServiceRef,
+}
+
+impl RecordRegistrar {
+    /// Register `record`, where a TTL of `None` uses the default.
+    ///
+    /// If `unique` is set the record is unique to this host, such as the
+    /// address of a host, so the name is probed for first and registering
+    /// fails with [`BonjourError::NameConflict`][`crate::BonjourError::NameConflict`]
+    /// if another host has a record with the same name and type. Otherwise
+    /// the record is shared, such as a `PTR` record that any number of hosts
+    /// may publish.
+    pub async fn register(
+        &self,
+        record: &ResourceRecord,
+        unique: bool,
+    ) -> Result<RecordRef, ZeroconfError> {
+        self.delegate.register_record(record, unique).await
+    }
+
+    /// Register the address `address` for the host `hostname` (e.g.
+    /// `"printer.local."`), as a unique `A` or `AAAA` record.
+    pub async fn register_address(
+        &self,
+        hostname: &str,
+        address: IpAddr,
+    ) -> Result<RecordRef, ZeroconfError> {
+        let data = match address {
+            IpAddr::V4(a) => RecordData::A(a),
+            IpAddr::V6(a) => RecordData::Aaaa(a),
+        };
+        let record = ResourceRecord::new(hostname, RecordQuery::CLASS_IN, None, data);
+        self.register(&record, true).await
+    }
+
+    /// Get the [`OpType`] of the operation registering records.
+    pub fn op_type(&self) -> &OpType {
+        self.delegate.op_type()
+    }
+}
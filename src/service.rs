@@ -1,27 +1,19 @@
+use crate::addr_resolver::hostname;
+use crate::backend::{Backend, BackendSelection, PublishContext};
+use crate::service_ref::RegistrationState;
+use crate::supervision::SupervisedOperation;
 use crate::{
-    BonjourError, Interface, OpKind, OpType, ProcessTask, ServiceRef, ServiceRefWrapper, TxtRecord,
-    ZeroconfError,
+    AddrResolver, Interface, OpKind, OpType, ProcessTask, RecordData, RecordQuery, ResourceRecord,
+    ServiceInstanceName, ServiceRef, ServiceType, Supervision, TxtRecord, ZeroconfError,
 };
-use std::{ffi, fmt};
+use std::ffi::CString;
+use std::fmt;
+use std::net::IpAddr;
 use tokio::sync::mpsc;
 
-use bonjour_sys::{DNSServiceErrorType, DNSServiceFlags, DNSServiceRef};
 use std::future::Future;
 use std::sync::Arc;
 
-#[derive(Debug)]
-struct ServicePublishContext {
-    tx: mpsc::UnboundedSender<Result<(), BonjourError>>,
-}
-
-impl ServicePublishContext {
-    fn send(&self, e: Result<(), BonjourError>) {
-        if let Err(e) = self.tx.send(e) {
-            log::warn!("Failed to send status, receiver dropped: {}", e);
-        }
-    }
-}
-
 /// Struct representing a `ZeroConf` service. This should be created with all
 /// the information that should be associated with the service and then the
 /// [`publish`][`Service::publish`] method can be used to register the service.
@@ -64,9 +56,12 @@ pub struct Service {
     domain: Option<String>,
     host: Option<String>,
     txt: TxtRecord,
+    subtypes: Vec<String>,
     browse: bool,
     resolve: bool,
     allow_rename: bool,
+    backend: BackendSelection,
+    supervision: Option<Supervision>,
 }
 
 impl fmt::Display for Service {
@@ -85,42 +80,6 @@ impl fmt::Display for Service {
     }
 }
 
-// Callback passed to DNSServiceRegister
-unsafe extern "C" fn dns_sd_callback(
-    _sd_ref: DNSServiceRef,
-    _flags: DNSServiceFlags,
-    error: DNSServiceErrorType,
-    name: *const libc::c_char,
-    regtype: *const libc::c_char,
-    domain: *const libc::c_char,
-    context: *mut libc::c_void,
-) {
-    let proxy = &*(context as *const ServicePublishContext);
-    if error == 0 {
-        let c_name = ffi::CStr::from_ptr(name);
-        let c_type = ffi::CStr::from_ptr(regtype);
-        let c_domain = ffi::CStr::from_ptr(domain);
-        let name = c_name
-            .to_str()
-            .expect("string originally from rust should be safe");
-        let regtype = c_type
-            .to_str()
-            .expect("string originally from rust should be safe");
-        let domain = c_domain
-            .to_str()
-            .expect("string originally from rust should be safe");
-        log::debug!("Service Callback OK ({}:{}:{})", name, regtype, domain);
-        proxy.send(Ok(()));
-    } else {
-        log::debug!(
-            "Service Callback Error ({}:{})",
-            error,
-            Into::<BonjourError>::into(error)
-        );
-        proxy.send(Err(error.into()));
-    }
-}
-
 impl Service {
     /// Create a new Service, called `name` of type `service_type` that is
     /// listening on port `port`.
@@ -154,36 +113,40 @@ impl Service {
     /// let service = async_zeroconf::Service::new_with_txt("Web Server", "_http._tcp", 80, txt);
     /// ```
     pub fn new_with_txt(name: &str, service_type: &str, port: u16, txt: TxtRecord) -> Self {
+        // Any subtypes included in the type are held separately
+        let (service_type, subtypes) = match service_type.parse::<ServiceType>() {
+            Ok(t) if !t.subtypes().is_empty() => (t.base(), t.subtypes().to_vec()),
+            _ => (service_type.to_string(), Vec::new()),
+        };
+
         Service {
             name: name.to_string(),
-            service_type: service_type.to_string(),
+            service_type,
             port,
             interface: Default::default(),
             domain: None,
             host: None,
             txt,
+            subtypes,
             browse: false,
             resolve: false,
             allow_rename: true,
+            backend: Default::default(),
+            supervision: None,
         }
     }
 
-    fn validate_service_type(&self) -> bool {
-        if self.service_type.contains('.') {
-            let parts: Vec<&str> = self.service_type.split('.').collect();
-            if parts[0].starts_with('_') && (parts[1] == "_udp" || parts[1] == "_tcp") {
-                return true;
-            }
-        }
-        false
-    }
-
     fn validate(&self) -> Result<(), ZeroconfError> {
-        if self.validate_service_type() {
-            self.txt.validate()
-        } else {
-            Err(ZeroconfError::InvalidServiceType(self.service_type.clone()))
+        self.parsed_type()?;
+        // Names are passed to the Bonjour API as C strings, so any containing
+        // NUL are rejected with every backend
+        for s in std::iter::once(&self.name)
+            .chain(&self.domain)
+            .chain(&self.host)
+        {
+            CString::new(s.as_str())?;
         }
+        self.txt.validate()
     }
 
     /// Set an interface to advertise the service on rather than all.
@@ -207,6 +170,47 @@ impl Service {
         self
     }
 
+    /// Get whether this service may be renamed if there is a name collision
+    pub fn allow_rename(&self) -> bool {
+        self.allow_rename
+    }
+
+    /// Set the backend used to publish or resolve this service rather than
+    /// the default backend.
+    ///
+    /// Services found by a [`ServiceBrowser`][`crate::ServiceBrowser`] use the
+    /// same backend as the browser.
+    pub fn set_backend(&mut self, backend: Arc<dyn Backend>) -> &mut Self {
+        self.backend = BackendSelection::new(backend);
+        self
+    }
+
+    /// Register the service again with `supervision` if the daemon stops
+    /// while the service is published.
+    ///
+    /// By default the service is no longer registered if the daemon stops.
+    /// See [`Supervision`] for more details.
+    pub fn supervise(&mut self, supervision: Supervision) -> &mut Self {
+        self.supervision = Some(supervision);
+        self
+    }
+
+    /// Get the supervision used when publishing this service, if any
+    pub fn supervision(&self) -> Option<Supervision> {
+        self.supervision
+    }
+
+    /// Set the backend selection for this service
+    pub(crate) fn select_backend(&mut self, backend: BackendSelection) -> &mut Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Get the backend selection for this service
+    pub(crate) fn backend(&self) -> &BackendSelection {
+        &self.backend
+    }
+
     /// Set the (optional) domain for the service.
     ///
     /// If not specified, the default domain is used.
@@ -250,6 +254,26 @@ impl Service {
         &self.service_type
     }
 
+    /// Get the type of the service including any subtypes as a
+    /// [`ServiceType`], or an error if the type is invalid.
+    ///
+    /// # Examples
+    /// ```
+    /// let mut service = async_zeroconf::Service::new("Server", "_http._tcp", 80);
+    /// service.add_subtype("_printer");
+    /// let service_type = service.parsed_type()?;
+    /// assert_eq!(service_type.name(), "http");
+    /// assert_eq!(service_type.subtypes(), ["_printer"]);
+    /// # Ok::<(), async_zeroconf::ZeroconfError>(())
+    /// ```
+    pub fn parsed_type(&self) -> Result<ServiceType, ZeroconfError> {
+        let mut service_type: ServiceType = self.service_type.parse()?;
+        for subtype in &self.subtypes {
+            service_type.add_subtype(subtype)?;
+        }
+        Ok(service_type)
+    }
+
     /// Get the port of the service
     pub fn port(&self) -> u16 {
         self.port
@@ -267,6 +291,128 @@ impl Service {
         self
     }
 
+    /// Add a TXT flag to the service, which is an entry without a value, as
+    /// described in [`TxtRecord::add_flag`]
+    pub fn add_txt_flag(&mut self, k: String) -> &mut Self {
+        self.txt.add_flag(k);
+        self
+    }
+
+    /// Add a subtype to the service, such as `_printer`, so that it can be
+    /// found by browsing for only services with that subtype.
+    ///
+    /// Subtypes must start with an underscore and be a single DNS label, which
+    /// is checked when the service is published. Subtypes can also be
+    /// included in the type passed to [`Service::new`], e.g.
+    /// `_http._tcp,_printer`.
+    ///
+    /// # Examples
+    /// ```
+    /// let mut service = async_zeroconf::Service::new("Server", "_http._tcp", 80);
+    /// service.add_subtype("_printer");
+    /// assert_eq!(service.registration_type(), "_http._tcp,_printer");
+    /// ```
+    pub fn add_subtype(&mut self, subtype: &str) -> &mut Self {
+        self.subtypes.push(subtype.to_string());
+        self
+    }
+
+    /// Get the subtypes of the service
+    pub fn subtypes(&self) -> &[String] {
+        &self.subtypes
+    }
+
+    /// Get the type used to register the service, which is the service type
+    /// followed by any subtypes separated by commas, e.g.
+    /// `_http._tcp,_printer`.
+    pub fn registration_type(&self) -> String {
+        let mut regtype = self.service_type.trim_end_matches('.').to_string();
+        for subtype in &self.subtypes {
+            regtype.push(',');
+            regtype.push_str(subtype);
+        }
+        regtype
+    }
+
+    /// Replace the TXT record collection of the service
+    pub(crate) fn set_txt(&mut self, txt: TxtRecord) -> &mut Self {
+        self.txt = txt;
+        self
+    }
+
+    /// Look up the addresses of the host of this service, which must have
+    /// been resolved with a [`ServiceResolver`][`crate::ServiceResolver`].
+    ///
+    /// This is equivalent to [`AddrResolver::addresses`] with the default
+    /// [`AddrResolver`], so has no timeout.
+    pub async fn addresses(&self) -> Result<Vec<IpAddr>, ZeroconfError> {
+        AddrResolver::new().addresses(self).await
+    }
+
+    /// Ask the backend to verify the `PTR` and `SRV` records of this service,
+    /// which must have been resolved with a
+    /// [`ServiceResolver`][`crate::ServiceResolver`], so that they are
+    /// removed if the service has gone.
+    ///
+    /// This should be used when connecting to a service fails, as otherwise
+    /// a host that has left the network without removing its services is
+    /// still found by browsers until the records expire. The records are
+    /// removed, and reported as removed to any browsers, if the host doesn't
+    /// answer. A `SRV` record with a priority or weight other than zero is
+    /// not matched.
+    ///
+    /// The backend returns [`BonjourError::Unsupported`][`crate::BonjourError::Unsupported`]
+    /// if it can't reconfirm records.
+    ///
+    /// # Examples
+    /// ```
+    /// # tokio_test::block_on(async {
+    /// let mut browser = async_zeroconf::ServiceBrowserBuilder::new("_http._tcp");
+    /// let mut services = browser
+    ///     .timeout(tokio::time::Duration::from_secs(2))
+    ///     .browse()?;
+    ///
+    /// if let Some(Ok(service)) = services.recv_resolve().await {
+    ///     let connected = match service.addresses().await?.first() {
+    ///         Some(a) => tokio::net::TcpStream::connect((*a, service.port())).await.is_ok(),
+    ///         None => false,
+    ///     };
+    ///     if !connected {
+    ///         service.reconfirm()?;
+    ///     }
+    /// }
+    /// # Ok::<(), async_zeroconf::ZeroconfError>(())
+    /// # });
+    /// ```
+    pub fn reconfirm(&self) -> Result<(), ZeroconfError> {
+        let target = hostname(self)?;
+        let domain = self.domain.as_deref().unwrap_or("local.");
+        let instance = ServiceInstanceName::new(&self.name, &self.service_type, domain);
+
+        let ptr = ResourceRecord::new(
+            &format!("{}.{}.", instance.service_type(), instance.domain()),
+            RecordQuery::CLASS_IN,
+            None,
+            RecordData::Ptr(instance.to_string()),
+        );
+        let srv = ResourceRecord::new(
+            &instance.to_string(),
+            RecordQuery::CLASS_IN,
+            None,
+            RecordData::Srv {
+                priority: 0,
+                weight: 0,
+                port: self.port,
+                target,
+            },
+        );
+
+        log::debug!("Reconfirming {}", instance);
+        let backend = self.backend.get()?;
+        backend.reconfirm_record(&self.interface, &ptr)?;
+        backend.reconfirm_record(&self.interface, &srv)
+    }
+
     /// Get the browse flag
     pub(crate) fn browse(&self) -> bool {
         self.browse
@@ -349,28 +495,39 @@ impl Service {
         self.validate()?;
 
         let (tx, mut rx) = mpsc::unbounded_channel();
+        let state = Arc::new(RegistrationState::default());
+
+        let backend = self.backend.get()?;
+        let (operation, supervision) = match self.supervision {
+            Some(supervision) => {
+                // Register again with the TXT record of the last update
+                let service = self.clone();
+                let state = state.clone();
+                let start = move |txt: Option<TxtRecord>| {
+                    let context = PublishContext::new(tx.clone(), state.clone());
+                    match txt {
+                        Some(txt) => backend.register(service.clone().set_txt(txt), context),
+                        None => backend.register(&service, context),
+                    }
+                };
+                let (operation, supervision) = SupervisedOperation::start(start, supervision)?;
+                (operation, Some(supervision))
+            }
+            None => (
+                backend.register(self, PublishContext::new(tx, state.clone()))?,
+                None,
+            ),
+        };
 
-        let callback_context = ServicePublishContext { tx };
-        let context = Arc::new(callback_context);
-        let context_ptr =
-            Arc::as_ptr(&context) as *mut Arc<ServicePublishContext> as *mut libc::c_void;
-
-        let service_ref = crate::c_intf::service_register(
-            (&self.name, &self.service_type, self.port),
-            &self.interface,
-            (self.domain.as_deref(), self.host.as_deref()),
-            &self.txt,
-            Some(dns_sd_callback),
-            self.allow_rename,
-            context_ptr,
-        )?;
-
-        let (r, task) = ServiceRefWrapper::from_service(
-            service_ref,
+        let (mut r, task) = ServiceRef::from_operation(
+            operation,
             OpType::new(&self.service_type, OpKind::Publish),
-            Some(Box::new(context)),
             None,
-        )?;
+        );
+        r.set_registration(state);
+        if let Some(supervision) = supervision {
+            r.set_supervision(supervision);
+        }
 
         let fut = async move {
             match rx.recv().await {
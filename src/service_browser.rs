@@ -1,19 +1,18 @@
+use crate::backend::{Backend, BackendSelection, BrowseContext};
+use crate::supervision::SupervisedOperation;
 use crate::{
-    BonjourError, Interface, OpKind, OpType, ProcessTask, Service, ServiceRef, ServiceRefWrapper,
-    ServiceResolver, ZeroconfError,
+    Interface, OpKind, OpType, ProcessTask, ReconnectEvents, Service, ServiceRef, ServiceResolver,
+    ServiceType, Supervision, ZeroconfError,
 };
 
 use core::pin::Pin;
 use core::task::{Context, Poll};
 use futures::stream::StreamExt;
 use futures_core::Stream;
-use std::ffi;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc;
 
-use bonjour_sys::{DNSServiceErrorType, DNSServiceFlags, DNSServiceRef};
-
 /// `ServiceBrowserBuilder` is used to browse for services. Once all the
 /// required information is added to the `ServiceBrowserBuilder` the
 /// [`browse`][`ServiceBrowserBuilder::browse`] method will produce a
@@ -47,17 +46,43 @@ use bonjour_sys::{DNSServiceErrorType, DNSServiceFlags, DNSServiceRef};
 pub struct ServiceBrowserBuilder {
     interface: Interface,
     service_type: String,
+    subtype: Option<String>,
     domain: Option<String>,
     timeout: Option<Duration>,
     close_on_end: bool,
+    backend: BackendSelection,
+    supervision: Option<Supervision>,
+}
+
+/// `BrowseEvent` is produced by [`ServiceBrowser::recv_event`] or the stream
+/// returned by [`ServiceBrowser::events`] when a service is found or removed.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum BrowseEvent {
+    /// A service has been found by the browser.
+    Added(Service),
+    /// A service previously found by the browser has been removed.
+    Removed(Service),
+}
+
+impl BrowseEvent {
+    /// Get the service that was added or removed.
+    pub fn service(&self) -> &Service {
+        match self {
+            BrowseEvent::Added(s) | BrowseEvent::Removed(s) => s,
+        }
+    }
 }
 
 /// Struct used to get the results of a service browser which should be
 /// constructed with a [`ServiceBrowserBuilder`].
+///
+/// Used as a stream, or with [`ServiceBrowser::recv`], only services that are
+/// found are produced. [`ServiceBrowser::events`] and
+/// [`ServiceBrowser::recv_event`] also report services that are removed.
 #[derive(Debug)]
 pub struct ServiceBrowser {
-    // Channel to receive found services
-    rx: mpsc::UnboundedReceiver<(Result<Service, ZeroconfError>, bool)>,
+    // Channel to receive found and removed services
+    rx: mpsc::UnboundedReceiver<(Result<BrowseEvent, ZeroconfError>, bool)>,
     // Reference to the socket used to process events
     delegate: ServiceRef,
     // Close if no more events
@@ -71,14 +96,23 @@ impl Stream for ServiceBrowser {
         mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Option<<Self as futures_core::Stream>::Item>> {
-        self.rx.poll_recv(cx).map(|p| {
-            p.map(|s| {
-                if s.1 {
-                    self.close()
-                };
-                s.0
-            })
-        })
+        loop {
+            let event = match self.rx.poll_recv(cx) {
+                Poll::Ready(Some(s)) => {
+                    if s.1 {
+                        self.close()
+                    };
+                    s.0
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+            match event {
+                Ok(BrowseEvent::Added(s)) => return Poll::Ready(Some(Ok(s))),
+                Ok(BrowseEvent::Removed(_)) => (),
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+        }
     }
 }
 
@@ -111,6 +145,41 @@ impl ServiceBrowser {
     /// # });
     /// ```
     pub async fn recv(&mut self) -> Option<Result<Service, ZeroconfError>> {
+        loop {
+            match self.recv_event().await? {
+                Ok(BrowseEvent::Added(s)) => return Some(Ok(s)),
+                Ok(BrowseEvent::Removed(_)) => (),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+
+    /// Receive an event from the browser, reporting services that are found
+    /// or removed.
+    ///
+    /// A response of `None` indicates that the browse operation has
+    /// finished, for example due to a timeout or error.
+    ///
+    /// # Examples
+    /// ```
+    /// # tokio_test::block_on(async {
+    /// use async_zeroconf::BrowseEvent;
+    ///
+    /// let mut browser = async_zeroconf::ServiceBrowserBuilder::new("_http._tcp");
+    /// let mut services = browser
+    ///     .timeout(tokio::time::Duration::from_secs(2))
+    ///     .browse()?;
+    ///
+    /// while let Some(Ok(event)) = services.recv_event().await {
+    ///     match event {
+    ///         BrowseEvent::Added(s) => println!("Added = {}", s),
+    ///         BrowseEvent::Removed(s) => println!("Removed = {}", s),
+    ///     }
+    /// }
+    /// # Ok::<(), async_zeroconf::ZeroconfError>(())
+    /// # });
+    /// ```
+    pub async fn recv_event(&mut self) -> Option<Result<BrowseEvent, ZeroconfError>> {
         self.rx.recv().await.map(|s| {
             if s.1 {
                 self.close()
@@ -119,6 +188,35 @@ impl ServiceBrowser {
         })
     }
 
+    /// Return a stream of the events from the browser, reporting services
+    /// that are found or removed. The [`ServiceBrowser`] is consumed to
+    /// produce the new stream.
+    ///
+    /// The values produced by the stream are equivalent to those produced by
+    /// [`recv_event`][`ServiceBrowser::recv_event`].
+    ///
+    /// # Examples
+    /// ```
+    /// use tokio_stream::StreamExt;
+    /// # tokio_test::block_on(async {
+    /// let mut browser = async_zeroconf::ServiceBrowserBuilder::new("_http._tcp");
+    /// let mut services = browser
+    ///     .timeout(tokio::time::Duration::from_secs(2))
+    ///     .browse()?;
+    ///
+    /// let mut stream = services.events();
+    /// while let Some(Ok(event)) = stream.next().await {
+    ///     println!("Event = {:?}", event);
+    /// }
+    /// # Ok::<(), async_zeroconf::ZeroconfError>(())
+    /// # });
+    /// ```
+    pub fn events(self) -> impl Stream<Item = Result<BrowseEvent, ZeroconfError>> + Unpin {
+        Box::pin(futures::stream::unfold(self, |mut browser| async move {
+            browser.recv_event().await.map(|e| (e, browser))
+        }))
+    }
+
     /// Receive a service from the browser, resolving it before returning it
     ///
     /// A response of `None` indicates that the browse operation has
@@ -147,6 +245,16 @@ impl ServiceBrowser {
         }
     }
 
+    /// Receive the [`ReconnectEvent`][`crate::ReconnectEvent`]s of the
+    /// browser after this method is called, if it was created with
+    /// [`ServiceBrowserBuilder::supervise`].
+    ///
+    /// Services found before the daemon stopped are not reported as removed,
+    /// and are found again once browsing starts again.
+    pub fn reconnects(&self) -> ReconnectEvents {
+        self.delegate.reconnects()
+    }
+
     /// Return a stream that includes the resolve operation before returning
     /// results. The [`ServiceBrowser`] is consumed to produce the new stream.
     ///
@@ -179,101 +287,22 @@ impl ServiceBrowser {
     }
 }
 
-#[derive(Debug)]
-struct ServiceBrowserContext {
-    tx: mpsc::UnboundedSender<(Result<Service, ZeroconfError>, bool)>,
-}
-
-impl ServiceBrowserContext {
-    fn send(&self, result: Result<Service, ZeroconfError>, last: bool) {
-        if let Err(e) = self.tx.send((result, last)) {
-            log::warn!("Failed to send Service, receiver dropped: {}", e);
-        }
-    }
-}
-
-impl Drop for ServiceBrowserContext {
-    fn drop(&mut self) {
-        log::trace!("Dropping ServiceBrowserContext");
-    }
-}
-
-unsafe fn browse_callback_inner(
-    intf_index: u32,
-    name: *const libc::c_char,
-    regtype: *const libc::c_char,
-    domain: *const libc::c_char,
-) -> Result<Service, ZeroconfError> {
-    let c_name = ffi::CStr::from_ptr(name);
-    let c_type = ffi::CStr::from_ptr(regtype);
-    let c_domain = ffi::CStr::from_ptr(domain);
-    let name = c_name.to_str()?;
-    let regtype = c_type.to_str()?;
-    let domain = c_domain.to_str()?;
-
-    log::debug!(
-        "ServiceBrowse Callback OK ({}:{}:{})",
-        name,
-        regtype,
-        domain
-    );
-    let mut service = Service::new(name, regtype, 0);
-    service
-        .set_interface(Interface::Interface(intf_index))
-        .set_domain(domain.to_string())
-        .set_browse();
-    Ok(service)
-}
-
-// Callback passed to DNSServiceBrowse
-unsafe extern "C" fn browse_callback(
-    _sd_ref: DNSServiceRef,
-    flags: DNSServiceFlags,
-    intf_index: u32,
-    error: DNSServiceErrorType,
-    name: *const libc::c_char,
-    regtype: *const libc::c_char,
-    domain: *const libc::c_char,
-    context: *mut libc::c_void,
-) {
-    let proxy = &*(context as *const ServiceBrowserContext);
-    if error == 0 {
-        let more = (flags & 0x1) == 0x1;
-        let add = (flags & 0x2) == 0x2;
-
-        if add {
-            let service = browse_callback_inner(intf_index, name, regtype, domain);
-            if !more {
-                log::trace!("End of services (for now)");
-            }
-
-            proxy.send(service, !more);
-        } else {
-            let c_name = ffi::CStr::from_ptr(name);
-            if let Ok(s) = c_name.to_str() {
-                log::debug!("ServiceBrowse Remove {}", s);
-            }
-        }
-    } else {
-        proxy.send(Err(error.into()), false);
-
-        log::error!(
-            "ServiceBrowse Callback Error ({}:{})",
-            error,
-            Into::<BonjourError>::into(error)
-        )
-    }
-}
-
 impl ServiceBrowserBuilder {
-    /// Create a new `ServiceBrowserBuilder` for the specified service type
+    /// Create a new `ServiceBrowserBuilder` for the specified service type,
+    /// which is parsed as a [`ServiceType`] when browsing starts.
+    ///
+    /// Unlike when publishing, the service name doesn't have to follow
+    /// RFC 6335, so existing types such as `_nvstream_dbd._tcp` can be found.
     pub fn new(service_type: &str) -> Self {
         ServiceBrowserBuilder {
             interface: Default::default(),
             service_type: service_type.to_string(),
+            subtype: None,
             domain: None,
             timeout: None,
             close_on_end: false,
+            backend: Default::default(),
+            supervision: None,
         }
     }
 
@@ -300,12 +329,67 @@ impl ServiceBrowserBuilder {
         self
     }
 
+    /// Only find services registered with the subtype `subtype`, such as
+    /// `_printer`, rather than all services of the type.
+    ///
+    /// # Examples
+    /// ```
+    /// # tokio_test::block_on(async {
+    /// let mut browser = async_zeroconf::ServiceBrowserBuilder::new("_http._tcp");
+    /// let mut services = browser
+    ///     .subtype("_printer")
+    ///     .timeout(tokio::time::Duration::from_secs(2))
+    ///     .browse()?;
+    ///
+    /// while let Some(Ok(v)) = services.recv().await {
+    ///     println!("Printer = {:?}", v);
+    /// }
+    /// # Ok::<(), async_zeroconf::ZeroconfError>(())
+    /// # });
+    /// ```
+    pub fn subtype(&mut self, subtype: &str) -> &mut Self {
+        self.subtype = Some(subtype.to_string());
+        self
+    }
+
+    // The name to browse for, using the `_sub` form if there is a subtype
+    fn query(&self) -> Result<String, ZeroconfError> {
+        let mut service_type = ServiceType::parse_lenient(&self.service_type)?;
+        if let Some(subtype) = &self.subtype {
+            service_type.add_subtype(subtype)?;
+        }
+        service_type.browse_query()
+    }
+
     /// Set the domain for service discovery rather than all
     pub fn domain(&mut self, domain: String) -> &mut Self {
         self.domain = Some(domain);
         self
     }
 
+    /// Set the backend used for service discovery rather than the default
+    /// backend
+    pub fn backend(&mut self, backend: Arc<dyn Backend>) -> &mut Self {
+        self.backend = BackendSelection::new(backend);
+        self
+    }
+
+    /// Start browsing again with `supervision` if the daemon stops while
+    /// browsing.
+    ///
+    /// By default the browser ends if the daemon stops. See [`Supervision`]
+    /// for more details.
+    pub fn supervise(&mut self, supervision: Supervision) -> &mut Self {
+        self.supervision = Some(supervision);
+        self
+    }
+
+    // Set the backend selection used for service discovery
+    pub(crate) fn select_backend(&mut self, backend: BackendSelection) -> &mut Self {
+        self.backend = backend;
+        self
+    }
+
     /// Start the browsing operation, which will continue until the specified
     /// timeout or until the [`ServiceBrowser`] is dropped.
     ///
@@ -358,28 +442,44 @@ impl ServiceBrowserBuilder {
     /// # });
     /// ```
     pub fn browse_task(&self) -> Result<(ServiceBrowser, impl ProcessTask), ZeroconfError> {
+        let query = self.query()?;
         let (tx, rx) = mpsc::unbounded_channel();
 
-        let callback_context = ServiceBrowserContext { tx };
-
-        let context = Arc::new(callback_context);
-        let context_ptr =
-            Arc::as_ptr(&context) as *mut Arc<ServiceBrowserContext> as *mut libc::c_void;
-
-        let service_handle = crate::c_intf::service_browse(
-            &self.interface,
-            &self.service_type,
-            self.domain.as_deref(),
-            Some(browse_callback),
-            context_ptr,
-        )?;
+        let backend = self.backend.get()?;
+        let (operation, supervision) = match self.supervision {
+            Some(supervision) => {
+                let (interface, domain) = (self.interface, self.domain.clone());
+                let (query, selection) = (query.clone(), self.backend.clone());
+                let start = move |_| {
+                    backend.browse(
+                        &interface,
+                        &query,
+                        domain.as_deref(),
+                        BrowseContext::new(tx.clone(), selection.clone()),
+                    )
+                };
+                let (operation, supervision) = SupervisedOperation::start(start, supervision)?;
+                (operation, Some(supervision))
+            }
+            None => (
+                backend.browse(
+                    &self.interface,
+                    &query,
+                    self.domain.as_deref(),
+                    BrowseContext::new(tx, self.backend.clone()),
+                )?,
+                None,
+            ),
+        };
 
-        let (service_ref, task) = ServiceRefWrapper::from_service(
-            service_handle,
-            OpType::new(&self.service_type, OpKind::Browse),
-            Some(Box::new(context)),
+        let (mut service_ref, task) = ServiceRef::from_operation(
+            operation,
+            OpType::new(&query, OpKind::Browse),
             self.timeout,
-        )?;
+        );
+        if let Some(supervision) = supervision {
+            service_ref.set_supervision(supervision);
+        }
 
         log::debug!("Created ServiceBrowser");
         let browser = ServiceBrowser {
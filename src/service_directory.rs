@@ -0,0 +1,395 @@
+use crate::sync::lock;
+use crate::{BrowseEvent, Interface, Service, ServiceBrowser, ServiceResolver, ZeroconfError};
+
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures_core::Stream;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+
+// Timeout used to resolve services if no resolver is specified
+const DEFAULT_RESOLVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// `ServiceKey` identifies a service in a [`ServiceDirectory`] by its name,
+/// type and domain, independent of the interfaces it was found on.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ServiceKey {
+    name: String,
+    service_type: String,
+    domain: Option<String>,
+}
+
+impl ServiceKey {
+    /// Create the `ServiceKey` identifying a [`Service`].
+    pub fn new(service: &Service) -> Self {
+        ServiceKey {
+            name: service.name().to_string(),
+            service_type: service.service_type().to_string(),
+            domain: service.domain().clone(),
+        }
+    }
+
+    /// Get the name of the service
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the type of the service
+    pub fn service_type(&self) -> &str {
+        &self.service_type
+    }
+
+    /// Get the domain of the service
+    pub fn domain(&self) -> &Option<String> {
+        &self.domain
+    }
+}
+
+/// `DirectoryEntry` holds the current information about a service in a
+/// [`ServiceDirectory`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DirectoryEntry {
+    key: ServiceKey,
+    service: Service,
+    interfaces: Vec<Interface>,
+    resolved: Option<Service>,
+}
+
+impl DirectoryEntry {
+    /// Get the [`ServiceKey`] identifying the service
+    pub fn key(&self) -> &ServiceKey {
+        &self.key
+    }
+
+    /// Get the service as found by the browser on one of its interfaces,
+    /// which can be resolved with a [`ServiceResolver`].
+    pub fn service(&self) -> &Service {
+        &self.service
+    }
+
+    /// Get the interfaces the service has been found on
+    pub fn interfaces(&self) -> &[Interface] {
+        &self.interfaces
+    }
+
+    /// Get the resolved service, if it has been resolved
+    pub fn resolved(&self) -> Option<&Service> {
+        self.resolved.as_ref()
+    }
+}
+
+/// `DirectoryEvent` is produced by [`DirectoryEvents`] when the services in a
+/// [`ServiceDirectory`] change.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DirectoryEvent {
+    /// A service has been added to the directory.
+    Added(DirectoryEntry),
+    /// A service in the directory has been resolved or its interfaces have
+    /// changed.
+    Updated(DirectoryEntry),
+    /// A service has been removed from all interfaces, so is no longer in
+    /// the directory.
+    Removed(DirectoryEntry),
+}
+
+impl DirectoryEvent {
+    /// Get the entry that was added, updated or removed.
+    pub fn entry(&self) -> &DirectoryEntry {
+        match self {
+            DirectoryEvent::Added(e) | DirectoryEvent::Updated(e) | DirectoryEvent::Removed(e) => e,
+        }
+    }
+}
+
+/// The set of services in a [`ServiceDirectory`].
+pub type DirectoryServices = HashMap<ServiceKey, DirectoryEntry>;
+
+// Senders for each subscriber, or `None` once the directory has finished
+type Subscribers = Arc<Mutex<Option<Vec<mpsc::UnboundedSender<DirectoryEvent>>>>>;
+
+// Result of resolving a service on an interface
+type Resolution = (ServiceKey, Interface, Result<Service, ZeroconfError>);
+
+/// `ServiceDirectory` maintains the current set of services found by a
+/// [`ServiceBrowser`].
+///
+/// A service found on several interfaces has a single entry, which is only
+/// removed once the service has been removed from every interface. Each
+/// service is resolved when it is added to the directory.
+///
+/// The directory is updated until the browser finishes, for example due to
+/// a timeout, or until the `ServiceDirectory` is dropped.
+///
+/// # Examples
+/// ```
+/// # tokio_test::block_on(async {
+/// let mut browser = async_zeroconf::ServiceBrowserBuilder::new("_http._tcp");
+/// let services = browser.browse()?;
+///
+/// let directory = async_zeroconf::ServiceDirectory::new(services);
+/// tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+///
+/// for entry in directory.snapshot().values() {
+///     println!("Service = {}", entry.service());
+/// }
+/// # Ok::<(), async_zeroconf::ZeroconfError>(())
+/// # });
+/// ```
+#[derive(Debug)]
+pub struct ServiceDirectory {
+    services: watch::Receiver<DirectoryServices>,
+    subscribers: Subscribers,
+    task: JoinHandle<()>,
+}
+
+impl ServiceDirectory {
+    /// Create a new `ServiceDirectory` from the services found by `browser`,
+    /// resolving them with a timeout of 10 seconds.
+    pub fn new(browser: ServiceBrowser) -> Self {
+        let resolver = ServiceResolver::new_with_timeout(DEFAULT_RESOLVE_TIMEOUT);
+        ServiceDirectory::new_with_resolver(browser, resolver)
+    }
+
+    /// Create a new `ServiceDirectory` from the services found by `browser`,
+    /// resolving them with `resolver`.
+    pub fn new_with_resolver(browser: ServiceBrowser, resolver: ServiceResolver) -> Self {
+        let (tx, rx) = watch::channel(HashMap::new());
+        let subscribers: Subscribers = Arc::new(Mutex::new(Some(Vec::new())));
+
+        let state = DirectoryState {
+            services: HashMap::new(),
+            tx,
+            subscribers: subscribers.clone(),
+            resolver: Arc::new(resolver),
+            resolve_tx: None,
+        };
+        let task = tokio::spawn(state.run(browser));
+
+        log::debug!("Created ServiceDirectory");
+        ServiceDirectory {
+            services: rx,
+            subscribers,
+            task,
+        }
+    }
+
+    /// Get a copy of the current set of services.
+    pub fn snapshot(&self) -> DirectoryServices {
+        self.services.borrow().clone()
+    }
+
+    /// Get the current entry for a service.
+    pub fn get(&self, key: &ServiceKey) -> Option<DirectoryEntry> {
+        self.services.borrow().get(key).cloned()
+    }
+
+    /// Get a [`watch::Receiver`] of the set of services, which is notified
+    /// each time the set changes.
+    pub fn watch(&self) -> watch::Receiver<DirectoryServices> {
+        self.services.clone()
+    }
+
+    /// Subscribe to changes of the set of services. Only changes after the
+    /// subscription are produced, so [`ServiceDirectory::snapshot`] can be
+    /// used to get the existing services.
+    ///
+    /// # Examples
+    /// ```
+    /// # tokio_test::block_on(async {
+    /// let mut browser = async_zeroconf::ServiceBrowserBuilder::new("_http._tcp");
+    /// let services = browser
+    ///     .timeout(tokio::time::Duration::from_secs(2))
+    ///     .browse()?;
+    ///
+    /// let directory = async_zeroconf::ServiceDirectory::new(services);
+    /// let mut events = directory.events();
+    /// while let Some(event) = events.recv().await {
+    ///     println!("Event = {:?}", event);
+    /// }
+    /// # Ok::<(), async_zeroconf::ZeroconfError>(())
+    /// # });
+    /// ```
+    pub fn events(&self) -> DirectoryEvents {
+        let (tx, rx) = mpsc::unbounded_channel();
+        if let Some(subscribers) = lock(&self.subscribers).as_mut() {
+            subscribers.push(tx);
+        }
+        DirectoryEvents { rx }
+    }
+}
+
+impl Drop for ServiceDirectory {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Struct used to get the changes to a [`ServiceDirectory`], which can be
+/// used as a stream or with [`DirectoryEvents::recv`].
+#[derive(Debug)]
+pub struct DirectoryEvents {
+    rx: mpsc::UnboundedReceiver<DirectoryEvent>,
+}
+
+impl DirectoryEvents {
+    /// Receive a change to the directory.
+    ///
+    /// A response of `None` indicates that the directory will not change
+    /// further, as the browser has finished or the directory was dropped.
+    pub async fn recv(&mut self) -> Option<DirectoryEvent> {
+        self.rx.recv().await
+    }
+}
+
+impl Stream for DirectoryEvents {
+    type Item = DirectoryEvent;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<<Self as futures_core::Stream>::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+// State of the task updating a directory
+struct DirectoryState {
+    services: DirectoryServices,
+    tx: watch::Sender<DirectoryServices>,
+    subscribers: Subscribers,
+    resolver: Arc<ServiceResolver>,
+    resolve_tx: Option<mpsc::UnboundedSender<Resolution>>,
+}
+
+impl DirectoryState {
+    async fn run(mut self, mut browser: ServiceBrowser) {
+        let (resolve_tx, mut resolve_rx) = mpsc::unbounded_channel();
+        self.resolve_tx = Some(resolve_tx);
+
+        // Once the browser finishes, wait for any outstanding resolves
+        loop {
+            tokio::select! {
+                event = browser.recv_event(), if self.resolve_tx.is_some() => match event {
+                    Some(Ok(BrowseEvent::Added(service))) => self.added(service),
+                    Some(Ok(BrowseEvent::Removed(service))) => self.removed(service),
+                    Some(Err(e)) => log::warn!("ServiceDirectory browse error: {}", e),
+                    None => self.resolve_tx = None,
+                },
+                Some((key, interface, result)) = resolve_rx.recv() => {
+                    self.resolved(key, interface, result)
+                }
+                else => break,
+            }
+        }
+
+        log::debug!("ServiceDirectory finished");
+        lock(&self.subscribers).take();
+    }
+
+    fn added(&mut self, service: Service) {
+        let key = ServiceKey::new(&service);
+        let interface = *service.interface();
+
+        let event = match self.services.get_mut(&key) {
+            Some(entry) if entry.interfaces.contains(&interface) => return,
+            Some(entry) => {
+                entry.interfaces.push(interface);
+                DirectoryEvent::Updated(entry.clone())
+            }
+            None => {
+                self.resolve(&service);
+                let entry = DirectoryEntry {
+                    key: key.clone(),
+                    service,
+                    interfaces: vec![interface],
+                    resolved: None,
+                };
+                self.services.insert(key, entry.clone());
+                DirectoryEvent::Added(entry)
+            }
+        };
+        self.notify(event);
+    }
+
+    fn removed(&mut self, service: Service) {
+        let key = ServiceKey::new(&service);
+        let interface = *service.interface();
+
+        let entry = match self.services.get_mut(&key) {
+            Some(entry) if entry.interfaces.contains(&interface) => entry,
+            _ => return,
+        };
+        entry.interfaces.retain(|i| *i != interface);
+
+        let event = if entry.interfaces.is_empty() {
+            match self.services.remove(&key) {
+                Some(entry) => DirectoryEvent::Removed(entry),
+                None => return,
+            }
+        } else if *entry.service.interface() == interface {
+            // Switch to one of the remaining interfaces and resolve again
+            entry.service.set_interface(entry.interfaces[0]);
+            entry.resolved = None;
+            let event = DirectoryEvent::Updated(entry.clone());
+            self.resolve(&event.entry().service);
+            event
+        } else {
+            DirectoryEvent::Updated(entry.clone())
+        };
+        self.notify(event);
+    }
+
+    fn resolved(
+        &mut self,
+        key: ServiceKey,
+        interface: Interface,
+        result: Result<Service, ZeroconfError>,
+    ) {
+        let resolved = match result {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                log::warn!("ServiceDirectory failed to resolve '{}': {}", key.name, e);
+                return;
+            }
+        };
+
+        // Ignore results for services that have since been removed
+        let event = match self.services.get_mut(&key) {
+            Some(entry) if *entry.service.interface() == interface => {
+                entry.resolved = Some(resolved);
+                DirectoryEvent::Updated(entry.clone())
+            }
+            _ => return,
+        };
+        self.notify(event);
+    }
+
+    // Resolve a service in a separate task so browse events aren't delayed
+    fn resolve(&self, service: &Service) {
+        if let Some(tx) = &self.resolve_tx {
+            let tx = tx.clone();
+            let resolver = self.resolver.clone();
+            let service = service.clone();
+            tokio::spawn(async move {
+                let result = resolver.resolve(&service).await;
+                let resolution = (ServiceKey::new(&service), *service.interface(), result);
+                if tx.send(resolution).is_err() {
+                    log::debug!("ServiceDirectory dropped before resolve finished");
+                }
+            });
+        }
+    }
+
+    // Update the watched set of services then notify subscribers
+    fn notify(&self, event: DirectoryEvent) {
+        if self.tx.send(self.services.clone()).is_err() {
+            log::debug!("ServiceDirectory dropped before update");
+        }
+        if let Some(subscribers) = lock(&self.subscribers).as_mut() {
+            subscribers.retain(|s| s.send(event.clone()).is_ok());
+        }
+    }
+}
@@ -1,17 +1,18 @@
-// Private helper structures to wrap the service reference
+// Reference to an operation, keeping it alive until dropped
 
-use crate::{BonjourError, ZeroconfError};
+use crate::backend::BackendOperation;
+use crate::supervision::SupervisionState;
+use crate::sync::lock;
+use crate::{BonjourError, ReconnectEvents, RecordType, ResourceRecord, TxtRecord, ZeroconfError};
 
-use bonjour_sys::{
-    DNSServiceProcessResult, DNSServiceRef, DNSServiceRefDeallocate, DNSServiceRefSockFD,
-};
+use core::pin::Pin;
+use core::task::{Context, Poll};
 use futures::Future;
-use std::any::Any;
+use futures_core::Stream;
 use std::fmt::Display;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
 use std::time::Duration;
-use tokio::io::unix::AsyncFd;
-use tokio::sync::oneshot;
+use tokio::sync::{mpsc, oneshot};
 
 /// `OpType` is used to indicate the service type and the kind of operation
 /// associated with a [`ServiceRef`]. Primarily intended for debug.
@@ -42,6 +43,11 @@ impl OpType {
     }
 
     /// The associated service type (e.g. `"_http._tcp"`).
+    ///
+    /// Operations that aren't for a service type hold the name they are for
+    /// instead, such as the hostname when looking up addresses or the full
+    /// name when querying for records, so this isn't a
+    /// [`ServiceType`][crate::ServiceType].
     pub fn service_type(&self) -> &str {
         &self.service_type
     }
@@ -69,6 +75,129 @@ pub enum OpKind {
     Browse,
     /// An operation to resolve a service.
     Resolve,
+    /// An operation to look up the addresses of a host.
+    GetAddrInfo,
+    /// An operation to find the domains to browse or register in.
+    EnumerateDomains,
+    /// An operation to query for DNS records.
+    QueryRecord,
+    /// An operation to register individual DNS records.
+    RegisterRecord,
+}
+
+/// `RegisteredService` holds the name, type and domain that a service was
+/// registered with, which may differ from those requested if the service was
+/// renamed due to a conflict or the default domain was used.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct RegisteredService {
+    name: String,
+    service_type: String,
+    domain: String,
+}
+
+impl RegisteredService {
+    pub(crate) fn new(name: &str, service_type: &str, domain: &str) -> Self {
+        RegisteredService {
+            name: name.to_string(),
+            service_type: service_type.to_string(),
+            domain: domain.to_string(),
+        }
+    }
+
+    /// The name the service was registered with (e.g. `"Server (2)"`).
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The type the service was registered with (e.g. `"_http._tcp."`).
+    pub fn service_type(&self) -> &str {
+        &self.service_type
+    }
+
+    /// The domain the service was registered in (e.g. `"local."`).
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+}
+
+/// `RegistrationEvent` is produced by [`RegistrationEvents`] when the
+/// registration of a published service changes.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RegistrationEvent {
+    /// The service has been registered, or registered again with the same
+    /// name.
+    Registered(RegisteredService),
+    /// The service has been registered with a different name, for example
+    /// due to a conflict after a network change.
+    Renamed(RegisteredService),
+    /// The name of the service conflicts with another service and the
+    /// service could not be renamed, so is no longer registered.
+    Conflict,
+    /// An error occurred, so the service may no longer be registered.
+    Error(BonjourError),
+}
+
+impl RegistrationEvent {
+    // Convert to the result produced by `Renames`
+    fn into_result(self) -> Result<RegisteredService, ZeroconfError> {
+        match self {
+            RegistrationEvent::Registered(r) | RegistrationEvent::Renamed(r) => Ok(r),
+            RegistrationEvent::Conflict => Err(BonjourError::NameConflict.into()),
+            RegistrationEvent::Error(e) => Err(e.into()),
+        }
+    }
+}
+
+type RegistrationResult = Result<RegisteredService, BonjourError>;
+
+// State of a registration shared between the backend and the `ServiceRef`
+#[derive(Debug, Default)]
+pub(crate) struct RegistrationState {
+    inner: Mutex<RegistrationInner>,
+}
+
+#[derive(Debug, Default)]
+struct RegistrationInner {
+    current: Option<RegisteredService>,
+    subscribers: Vec<mpsc::UnboundedSender<RegistrationEvent>>,
+}
+
+impl RegistrationState {
+    // Record the result of the registration and notify any subscribers
+    pub(crate) fn update(&self, result: RegistrationResult) {
+        let mut inner = self.lock();
+        let event = match result {
+            Ok(registered) => {
+                let renamed = match &inner.current {
+                    Some(current) => current.name != registered.name,
+                    None => false,
+                };
+                inner.current = Some(registered.clone());
+                if renamed {
+                    RegistrationEvent::Renamed(registered)
+                } else {
+                    RegistrationEvent::Registered(registered)
+                }
+            }
+            Err(BonjourError::NameConflict) => RegistrationEvent::Conflict,
+            Err(e) => RegistrationEvent::Error(e),
+        };
+        inner.subscribers.retain(|s| s.send(event.clone()).is_ok());
+    }
+
+    fn current(&self) -> Option<RegisteredService> {
+        self.lock().current.clone()
+    }
+
+    pub(crate) fn subscribe(&self) -> mpsc::UnboundedReceiver<RegistrationEvent> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.lock().subscribers.push(tx);
+        rx
+    }
+
+    fn lock(&self) -> MutexGuard<'_, RegistrationInner> {
+        lock(&self.inner)
+    }
 }
 
 /// Struct to hold a published service, which keeps the service alive while a
@@ -85,6 +214,12 @@ pub enum OpKind {
 pub struct ServiceRef {
     shutdown_tx: Option<oneshot::Sender<()>>,
     op_type: OpType,
+    // The operation is owned by the process task, so it ends on a timeout
+    operation: Weak<dyn BackendOperation>,
+    // State of the registration if this is a published service
+    registration: Option<Arc<RegistrationState>>,
+    // State of the supervision if this is a supervised operation
+    supervision: Option<Arc<SupervisionState>>,
 }
 
 impl ServiceRef {
@@ -93,6 +228,222 @@ impl ServiceRef {
     pub fn op_type(&self) -> &OpType {
         &self.op_type
     }
+
+    /// Get the name, type and domain the service is currently registered
+    /// with, or `None` if this is not a published service or it is not
+    /// registered yet.
+    pub fn registration(&self) -> Option<RegisteredService> {
+        self.registration.as_ref().and_then(|r| r.current())
+    }
+
+    /// Get the name the service is currently registered with, which differs
+    /// from the name of the [`Service`][`crate::Service`] if it was renamed
+    /// due to a conflict.
+    ///
+    /// # Examples
+    /// ```
+    /// # tokio_test::block_on(async {
+    /// let service = async_zeroconf::Service::new("Server", "_http._tcp", 80);
+    /// let service_ref = service.publish().await?;
+    ///
+    /// println!("Registered as {:?}", service_ref.registered_name());
+    /// # Ok::<(), async_zeroconf::ZeroconfError>(())
+    /// # });
+    /// ```
+    pub fn registered_name(&self) -> Option<String> {
+        self.registration().map(|r| r.name)
+    }
+
+    /// Get the domain the service is currently registered in.
+    pub fn registered_domain(&self) -> Option<String> {
+        self.registration().map(|r| r.domain)
+    }
+
+    /// Receive the changes to the registration of a published service after
+    /// this method is called. Each time the service is renamed, for example
+    /// due to a conflict after a network change, the new registration is
+    /// produced. Errors such as a [`BonjourError::NameConflict`] for a
+    /// service that can't be renamed are also produced.
+    ///
+    /// The stream ends when the service is no longer published, and is
+    /// always empty if this is not a published service.
+    ///
+    /// # Examples
+    /// ```
+    /// # tokio_test::block_on(async {
+    /// let service = async_zeroconf::Service::new("Server", "_http._tcp", 80);
+    /// let service_ref = service.publish().await?;
+    ///
+    /// let mut renames = service_ref.renames();
+    /// tokio::spawn(async move {
+    ///     while let Some(r) = renames.recv().await {
+    ///         println!("Registration changed = {:?}", r);
+    ///     }
+    /// });
+    /// # Ok::<(), async_zeroconf::ZeroconfError>(())
+    /// # });
+    /// ```
+    pub fn renames(&self) -> Renames {
+        Renames {
+            rx: self.subscribe(),
+        }
+    }
+
+    /// Receive the events for a published service after this method is
+    /// called, so that a service which is no longer registered can be
+    /// published again.
+    ///
+    /// The stream ends when the service is no longer published, and is
+    /// always empty if this is not a published service.
+    ///
+    /// # Examples
+    /// ```
+    /// # tokio_test::block_on(async {
+    /// use async_zeroconf::RegistrationEvent;
+    ///
+    /// let service = async_zeroconf::Service::new("Server", "_http._tcp", 80);
+    /// let service_ref = service.publish().await?;
+    ///
+    /// let mut events = service_ref.events();
+    /// tokio::spawn(async move {
+    ///     while let Some(event) = events.recv().await {
+    ///         match event {
+    ///             RegistrationEvent::Registered(r) | RegistrationEvent::Renamed(r) => {
+    ///                 println!("Registered as {}", r.name())
+    ///             }
+    ///             RegistrationEvent::Conflict => println!("Name conflict"),
+    ///             RegistrationEvent::Error(e) => println!("Error = {}", e),
+    ///         }
+    ///     }
+    /// });
+    /// # Ok::<(), async_zeroconf::ZeroconfError>(())
+    /// # });
+    /// ```
+    pub fn events(&self) -> RegistrationEvents {
+        RegistrationEvents {
+            rx: self.subscribe(),
+        }
+    }
+
+    fn subscribe(&self) -> mpsc::UnboundedReceiver<RegistrationEvent> {
+        match &self.registration {
+            Some(r) => r.subscribe(),
+            None => mpsc::unbounded_channel().1,
+        }
+    }
+
+    // Set the registration state for a published service
+    pub(crate) fn set_registration(&mut self, registration: Arc<RegistrationState>) {
+        self.registration = Some(registration);
+    }
+
+    /// Receive the [`ReconnectEvent`][`crate::ReconnectEvent`]s of a
+    /// supervised operation after this method is called, which report when
+    /// the daemon stops and when the operation is started again.
+    ///
+    /// The stream ends when the operation ends, and is always empty if the
+    /// operation is not supervised. See
+    /// [`Supervision`][`crate::Supervision`] for how to supervise an
+    /// operation.
+    pub fn reconnects(&self) -> ReconnectEvents {
+        let rx = match &self.supervision {
+            Some(s) => s.subscribe(),
+            None => mpsc::unbounded_channel().1,
+        };
+        ReconnectEvents::new(rx)
+    }
+
+    // Set the supervision state for a supervised operation
+    pub(crate) fn set_supervision(&mut self, supervision: Arc<SupervisionState>) {
+        self.supervision = Some(supervision);
+    }
+
+    /// Replace the TXT record of the published service, without removing
+    /// and registering the service again.
+    ///
+    /// The TXT record is checked with [`TxtRecord::validate`] before it is
+    /// used. Only a `ServiceRef` from publishing a service can be updated,
+    /// other operations return
+    /// [`BonjourError::Unsupported`][`crate::BonjourError::Unsupported`].
+    ///
+    /// # Examples
+    /// ```
+    /// # tokio_test::block_on(async {
+    /// let service = async_zeroconf::Service::new("Server", "_http._tcp", 80);
+    /// let service_ref = service.publish().await?;
+    ///
+    /// let mut txt = async_zeroconf::TxtRecord::new();
+    /// txt.add("status".to_string(), "ready".to_string());
+    /// service_ref.update_txt(txt).await?;
+    /// # Ok::<(), async_zeroconf::ZeroconfError>(())
+    /// # });
+    /// ```
+    pub async fn update_txt(&self, txt: TxtRecord) -> Result<(), ZeroconfError> {
+        txt.validate()?;
+        let operation = self.operation.upgrade().ok_or(ZeroconfError::Dropped)?;
+        log::debug!("Updating TXT record ({})", self.op_type);
+        operation.update_txt(txt).await
+    }
+
+    /// Add a record of type `rrtype` with the data `rdata` to the published
+    /// service, in addition to the SRV and TXT records, for example to
+    /// publish a second TXT record or a NULL record. The record has the same
+    /// name as the service and is removed when the returned [`RecordRef`] is
+    /// dropped, or when the service is no longer published.
+    ///
+    /// `rdata` is in the wire format of the record type and `ttl` is in
+    /// seconds, where a TTL of zero uses the default. Only a `ServiceRef`
+    /// from publishing a service can have records added, other operations
+    /// return [`BonjourError::Unsupported`][`crate::BonjourError::Unsupported`].
+    ///
+    /// # Examples
+    /// ```
+    /// # tokio_test::block_on(async {
+    /// use async_zeroconf::RecordType;
+    ///
+    /// let service = async_zeroconf::Service::new("Server", "_http._tcp", 80);
+    /// let service_ref = service.publish().await?;
+    ///
+    /// // Add an empty NULL record
+    /// let record_ref = service_ref.add_record(RecordType::Other(10), &[], 0).await?;
+    /// # Ok::<(), async_zeroconf::ZeroconfError>(())
+    /// # });
+    /// ```
+    pub async fn add_record(
+        &self,
+        rrtype: RecordType,
+        rdata: &[u8],
+        ttl: u32,
+    ) -> Result<RecordRef, ZeroconfError> {
+        if rdata.len() > u16::MAX.into() {
+            return Err(BonjourError::BadParam.into());
+        }
+        let operation = self.operation.upgrade().ok_or(ZeroconfError::Dropped)?;
+        log::debug!("Adding {:?} record ({})", rrtype, self.op_type);
+        let id = operation.add_record(rrtype, rdata.to_vec(), ttl).await?;
+        Ok(self.record_ref(id, rrtype))
+    }
+
+    // Register a record with an operation registering individual records
+    pub(crate) async fn register_record(
+        &self,
+        record: &ResourceRecord,
+        unique: bool,
+    ) -> Result<RecordRef, ZeroconfError> {
+        let operation = self.operation.upgrade().ok_or(ZeroconfError::Dropped)?;
+        log::debug!("Registering record {} ({})", record, self.op_type);
+        let id = operation.register_record(record.clone(), unique).await?;
+        Ok(self.record_ref(id, record.rrtype()))
+    }
+
+    fn record_ref(&self, id: u64, rrtype: RecordType) -> RecordRef {
+        RecordRef {
+            operation: self.operation.clone(),
+            id,
+            rrtype,
+            op_type: self.op_type.clone(),
+        }
+    }
 }
 
 impl Drop for ServiceRef {
@@ -110,18 +461,93 @@ impl Drop for ServiceRef {
     }
 }
 
-// Internal type to hold the file descriptor for the socket associated with the
-// service.
+/// Struct to hold a record added to a published service with
+/// [`ServiceRef::add_record`], or registered with a
+/// [`RecordRegistrar`][`crate::RecordRegistrar`], which keeps the record
+/// published while it is held. When dropped the record is removed.
 #[derive(Debug)]
-pub(crate) struct ServiceFileDescriptor {
-    pub fd: i32,
+#[must_use]
+pub struct RecordRef {
+    operation: Weak<dyn BackendOperation>,
+    id: u64,
+    rrtype: RecordType,
+    op_type: OpType,
+}
+
+impl RecordRef {
+    /// Get the type of the record.
+    pub fn rrtype(&self) -> RecordType {
+        self.rrtype
+    }
+}
+
+impl Drop for RecordRef {
+    fn drop(&mut self) {
+        log::debug!("Dropping RecordRef ({})", self.op_type);
+        // The record has already gone if the service is no longer published
+        if let Some(operation) = self.operation.upgrade() {
+            if let Err(e) = operation.remove_record(self.id) {
+                log::warn!("Failed to remove record: {}", e);
+            }
+        }
+    }
+}
+
+/// Struct used to get the changes to the registration of a published service,
+/// produced by [`ServiceRef::renames`], which can be used as a stream or with
+/// [`Renames::recv`].
+#[derive(Debug)]
+pub struct Renames {
+    rx: mpsc::UnboundedReceiver<RegistrationEvent>,
+}
+
+impl Renames {
+    /// Receive the next change to the registration.
+    ///
+    /// A response of `None` indicates that the service is no longer
+    /// published.
+    pub async fn recv(&mut self) -> Option<Result<RegisteredService, ZeroconfError>> {
+        self.rx.recv().await.map(|e| e.into_result())
+    }
+}
+
+impl Stream for Renames {
+    type Item = Result<RegisteredService, ZeroconfError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<<Self as futures_core::Stream>::Item>> {
+        self.rx.poll_recv(cx).map(|e| e.map(|e| e.into_result()))
+    }
+}
+
+/// Struct used to get the [`RegistrationEvent`]s of a published service,
+/// produced by [`ServiceRef::events`], which can be used as a stream or with
+/// [`RegistrationEvents::recv`].
+#[derive(Debug)]
+pub struct RegistrationEvents {
+    rx: mpsc::UnboundedReceiver<RegistrationEvent>,
+}
+
+impl RegistrationEvents {
+    /// Receive the next event for the service.
+    ///
+    /// A response of `None` indicates that the service is no longer
+    /// published.
+    pub async fn recv(&mut self) -> Option<RegistrationEvent> {
+        self.rx.recv().await
+    }
 }
 
-// Allow ServiceFileDescriptor to be convered to a AsyncFd by implementing the
-// AsRawFd trait.
-impl std::os::unix::prelude::AsRawFd for ServiceFileDescriptor {
-    fn as_raw_fd(&self) -> i32 {
-        self.fd
+impl Stream for RegistrationEvents {
+    type Item = RegistrationEvent;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<<Self as futures_core::Stream>::Item>> {
+        self.rx.poll_recv(cx)
     }
 }
 
@@ -132,169 +558,92 @@ pub trait ProcessTask: Future<Output = ()> + Send + Sync {}
 
 impl<T> ProcessTask for T where T: Future<Output = ()> + Send + Sync {}
 
-#[derive(Debug)]
-pub(crate) struct ServiceRefWrapper {
-    // Pointer to reference returned by C API
-    pub inner: DNSServiceRef,
-    // Mutex to protect service reference
-    pub lock: Mutex<()>,
-    // Async file descriptor to detect new events asynchronously
-    pub fd: AsyncFd<ServiceFileDescriptor>,
-    // Hold a reference to an (optional) context used for C API callbacks
-    context: Option<Box<dyn Any + Send>>,
-    // Operation type that created this reference
-    op_type: OpType,
-}
-
-impl ServiceRefWrapper {
-    pub fn from_service(
-        service_ref: DNSServiceRef,
+impl ServiceRef {
+    /// Create a `ServiceRef` for an operation started by a backend, along with
+    /// the task that processes events for the operation until the reference is
+    /// dropped or the timeout expires.
+    pub(crate) fn from_operation(
+        operation: Box<dyn BackendOperation>,
         op_type: OpType,
-        context: Option<Box<dyn Any + Send>>,
         timeout: Option<Duration>,
-    ) -> Result<(ServiceRef, impl ProcessTask), std::io::Error> {
-        log::trace!("Call DNSServiceRefSockFD");
-        let fd = unsafe { DNSServiceRefSockFD(service_ref) };
-        log::trace!("  FD:{}", fd);
-
+    ) -> (ServiceRef, impl ProcessTask) {
         log::debug!("Creating ServiceRef ({})", op_type);
 
-        match AsyncFd::new(ServiceFileDescriptor { fd }) {
-            Ok(async_fd) => {
-                // Create channel for shutdown
-                let (tx, rx) = oneshot::channel::<()>();
-
-                // Create the wrapper for processing events
-                let wrapper = ServiceRefWrapper {
-                    inner: service_ref,
-                    lock: Mutex::new(()),
-                    fd: async_fd,
-                    context,
-                    op_type: op_type.clone(),
-                };
+        // Create channel for shutdown
+        let (tx, rx) = oneshot::channel::<()>();
 
-                // Spawn the task that will process events
-                let task = async move {
-                    match ServiceRefWrapper::process(rx, wrapper, timeout).await {
-                        Ok(_) => (),
-                        Err(e) => log::error!("Error on processing: {}", e),
-                    }
-                };
+        let operation: Arc<dyn BackendOperation> = Arc::from(operation);
+        let weak = Arc::downgrade(&operation);
 
-                // Create the reference that will hold the service active
-                let s_ref = ServiceRef {
-                    shutdown_tx: Some(tx),
-                    op_type,
-                };
-
-                Ok((s_ref, task))
+        // Spawn the task that will process events
+        let task = async move {
+            match process(rx, operation, timeout).await {
+                Ok(_) => (),
+                Err(e) => log::error!("Error on processing: {}", e),
             }
-            Err(e) => Err(e),
-        }
-    }
-
-    /// A future to wait for any pending events related to the service,
-    /// handling them and then completing the future.
-    async fn process_events(service_ref: &ServiceRefWrapper) -> Result<bool, ZeroconfError> {
-        // Wait on indication that file descriptor is readable
-        let mut fd = service_ref.fd.readable().await?;
-
-        log::trace!("Call DNSServiceProcessResult");
+        };
 
-        // Process any pending events
-        let process_err = {
-            let mut _guard = service_ref.lock.lock()?;
-            unsafe { DNSServiceProcessResult(service_ref.inner) }
+        // Create the reference that will hold the service active
+        let s_ref = ServiceRef {
+            shutdown_tx: Some(tx),
+            op_type,
+            operation: weak,
+            registration: None,
+            supervision: None,
         };
-        // Clear ready flag for socket to wait for next event
-        // As there is no await point or polling between processing above and
-        // clearing the flag, there should be no opportunity to 'miss' an event
-        // between the DNSServiceProcessResult and clear_ready().
-        fd.clear_ready();
-        if process_err != 0 {
-            return Err(Into::<BonjourError>::into(process_err).into());
-        }
 
-        Ok(true)
+        (s_ref, task)
     }
+}
 
-    /// Processing wrapper to keep processing events as available
-    async fn process(
-        mut rx: oneshot::Receiver<()>,
-        service_ref: ServiceRefWrapper,
-        timeout: Option<Duration>,
-    ) -> Result<(), ZeroconfError> {
-        let (tx_time, mut rx_time) = oneshot::channel();
-
-        if let Some(t) = timeout {
-            tokio::spawn(async move {
-                tokio::time::sleep(t).await;
-                match tx_time.send(()) {
-                    Ok(_) => {
-                        log::debug!("Sending timeout");
-                    }
-                    Err(_) => {
-                        log::trace!("Sending timeout failed - processing ended due to shutdown");
-                    }
-                }
-            });
-        }
-
-        loop {
-            tokio::select! {
-                // Shutdown event
-                _ = &mut rx => {
-                    log::debug!("Process task got shutdown");
-                    return Ok(());
-                }
-                // Timeout future
-                _ = &mut rx_time => {
-                    log::debug!("Process task got timeout");
-                    return Ok(());
+/// Processing wrapper to keep processing events as available
+async fn process(
+    mut rx: oneshot::Receiver<()>,
+    operation: Arc<dyn BackendOperation>,
+    timeout: Option<Duration>,
+) -> Result<(), ZeroconfError> {
+    let (tx_time, mut rx_time) = oneshot::channel();
+
+    if let Some(t) = timeout {
+        tokio::spawn(async move {
+            tokio::time::sleep(t).await;
+            match tx_time.send(()) {
+                Ok(_) => {
+                    log::debug!("Sending timeout");
                 }
-                // Event processing
-                e = Self::process_events(&service_ref) => {
-                    match e {
-                        Ok(b) => {
-                            if b {
-                                log::trace!("Events processed");
-                            } else {
-                                log::trace!("Got null pointer due to shutdown");
-                                return Ok(());
-                            }
-                        },
-                        Err(e) => return Err(e)
-                    }
+                Err(_) => {
+                    log::trace!("Sending timeout failed - processing ended due to shutdown");
                 }
             }
-        }
+        });
     }
-}
-
-// Implement Send as reference is thread-safe
-unsafe impl Send for ServiceRefWrapper {}
-// Implement Sync as reference is protected by mutex
-unsafe impl Sync for ServiceRefWrapper {}
 
-impl Drop for ServiceRefWrapper {
-    fn drop(&mut self) {
-        log::debug!(
-            "Dropping and deallocating service reference ({})",
-            self.op_type
-        );
-        {
-            match self.lock.lock() {
-                Ok(_guard) => {
-                    unsafe { DNSServiceRefDeallocate(self.inner) };
-                }
-                Err(_) => {
-                    log::warn!("Service reference mutex was poisoned");
-                    unsafe { DNSServiceRefDeallocate(self.inner) };
+    loop {
+        tokio::select! {
+            // Shutdown event
+            _ = &mut rx => {
+                log::debug!("Process task got shutdown");
+                return Ok(());
+            }
+            // Timeout future
+            _ = &mut rx_time => {
+                log::debug!("Process task got timeout");
+                return Ok(());
+            }
+            // Event processing
+            e = operation.process() => {
+                match e {
+                    Ok(b) => {
+                        if b {
+                            log::trace!("Events processed");
+                        } else {
+                            log::trace!("Operation has no more events");
+                            return Ok(());
+                        }
+                    },
+                    Err(e) => return Err(e)
                 }
             }
         }
-        if self.context.is_some() {
-            log::debug!("Context to be dropped ({})", self.op_type);
-        }
     }
 }
@@ -1,20 +1,16 @@
+use crate::backend::{Backend, BackendSelection, ResolveContext, ResolverInformation};
 use crate::{
-    BonjourError, Interface, OpKind, OpType, ProcessTask, Service, ServiceRef, ServiceRefWrapper,
-    TxtRecord, ZeroconfError,
+    AddrResolver, Interface, OpKind, OpType, ProcessTask, Service, ServiceInstanceName, ServiceRef,
+    ZeroconfError,
 };
 
 use futures::Future;
 use futures::FutureExt;
-use std::ffi;
-use std::ptr;
+use std::net::{IpAddr, SocketAddr, SocketAddrV6};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc;
 
-use bonjour_sys::{
-    DNSServiceErrorType, DNSServiceFlags, DNSServiceRef, TXTRecordGetCount, TXTRecordGetItemAtIndex,
-};
-
 /// `ServiceResolver` is used resolve a service obtained from a
 /// [`ServiceBrowser`][`crate::ServiceBrowser`]. Browsing does not obtain all
 /// information about a service, for example it doesn't include port
@@ -43,6 +39,7 @@ use bonjour_sys::{
 pub struct ServiceResolver {
     timeout: Option<Duration>,
     checked: bool,
+    backend: Option<BackendSelection>,
 }
 
 impl Default for ServiceResolver {
@@ -59,6 +56,7 @@ impl ServiceResolver {
         ServiceResolver {
             timeout: None,
             checked: true,
+            backend: None,
         }
     }
 
@@ -67,6 +65,7 @@ impl ServiceResolver {
         ServiceResolver {
             timeout: Some(timeout),
             checked: true,
+            backend: None,
         }
     }
 
@@ -76,6 +75,13 @@ impl ServiceResolver {
         self
     }
 
+    /// Set the backend used to resolve services rather than the backend
+    /// associated with each service
+    pub fn set_backend(&mut self, backend: Arc<dyn Backend>) -> &mut Self {
+        self.backend = Some(BackendSelection::new(backend));
+        self
+    }
+
     /// Static method to resolve the specified [`Service`], the service must have
     /// been produced from a [`ServiceBrowser`][`crate::ServiceBrowser`] to ensure
     /// that the required information for the resolve operation is available.
@@ -126,6 +132,56 @@ impl ServiceResolver {
         resolver.get(service).await
     }
 
+    /// Resolve the specified [`Service`] using this `ServiceResolver` and look
+    /// up the addresses of its host, producing the socket addresses that can
+    /// be used to connect to the service.
+    ///
+    /// IPv6 link-local addresses include the index of the interface the
+    /// service was found on as the scope ID. The timeout of this
+    /// `ServiceResolver` applies separately to resolving the service and
+    /// looking up the addresses.
+    ///
+    /// # Examples
+    /// ```
+    /// # tokio_test::block_on(async {
+    /// let mut browser = async_zeroconf::ServiceBrowserBuilder::new("_http._tcp");
+    /// let mut services = browser
+    ///     .timeout(tokio::time::Duration::from_secs(2))
+    ///     .browse()?;
+    /// let resolver = async_zeroconf::ServiceResolver::new_with_timeout(
+    ///     tokio::time::Duration::from_secs(2)
+    /// );
+    ///
+    /// while let Some(Ok(service)) = services.recv().await {
+    ///     let addrs = resolver.resolve_socket_addrs(&service).await?;
+    ///     if let Some(addr) = addrs.first() {
+    ///         let stream = tokio::net::TcpStream::connect(addr).await;
+    ///     }
+    /// }
+    /// # Ok::<(), async_zeroconf::ZeroconfError>(())
+    /// # });
+    /// ```
+    pub async fn resolve_socket_addrs(
+        &self,
+        service: &Service,
+    ) -> Result<Vec<SocketAddr>, ZeroconfError> {
+        let resolved = self.resolve(service).await?;
+
+        let mut addr_resolver = match self.timeout {
+            Some(t) => AddrResolver::new_with_timeout(t),
+            None => AddrResolver::new(),
+        };
+        if let Some(backend) = &self.backend {
+            addr_resolver.select_backend(backend.clone());
+        }
+        let addresses = addr_resolver.addresses(&resolved).await?;
+
+        Ok(addresses
+            .into_iter()
+            .map(|a| socket_addr(a, resolved.port(), resolved.interface()))
+            .collect())
+    }
+
     /// Resolve the specified [`Service`] using this `ServiceResolver`. The
     /// returned [`ProcessTask`] future must be awaited to process events
     /// associated with the browser.
@@ -195,32 +251,14 @@ impl ServiceResolver {
     ) -> Result<(ServiceResolverResult, impl ProcessTask), ZeroconfError> {
         let (tx, rx) = mpsc::unbounded_channel();
 
-        let callback_context = ServiceResolverContext { tx };
-
-        let context = Arc::new(callback_context);
-        let context_ptr =
-            Arc::as_ptr(&context) as *mut Arc<ServiceResolverContext> as *mut libc::c_void;
-
-        let domain = &service
-            .domain()
-            .as_ref()
-            .ok_or_else(|| ZeroconfError::NotFromBrowser(service.clone()))?;
+        let backend = self.backend.as_ref().unwrap_or_else(|| service.backend());
+        let operation = backend.get()?.resolve(service, ResolveContext::new(tx))?;
 
-        let service_handle = crate::c_intf::service_resolve(
-            service.name(),
-            &service.interface(),
-            service.service_type(),
-            domain,
-            Some(resolve_callback),
-            context_ptr,
-        )?;
-
-        let (delegate, task) = ServiceRefWrapper::from_service(
-            service_handle,
+        let (delegate, task) = ServiceRef::from_operation(
+            operation,
             OpType::new(service.service_type(), OpKind::Resolve),
-            Some(Box::new(context)),
             self.timeout,
-        )?;
+        );
 
         let result = ServiceResolverResult { rx, delegate };
 
@@ -228,13 +266,15 @@ impl ServiceResolver {
     }
 }
 
-#[derive(Debug)]
-struct ResolverInformation {
-    interface: Interface,
-    fullname: String,
-    hosttarget: String,
-    port: u16,
-    txt_record: TxtRecord,
+// Combine an address and port, adding the interface as the scope ID of IPv6
+// link-local addresses as they can't be used without one
+fn socket_addr(address: IpAddr, port: u16, interface: &Interface) -> SocketAddr {
+    match (address, interface) {
+        (IpAddr::V6(a), Interface::Interface(index)) if (a.segments()[0] & 0xffc0) == 0xfe80 => {
+            SocketAddrV6::new(a, port, 0, *index).into()
+        }
+        (a, _) => SocketAddr::new(a, port),
+    }
 }
 
 impl ResolverInformation {
@@ -252,21 +292,44 @@ impl ResolverInformation {
             self.txt_record,
         );
 
-        s.set_interface(self.interface).set_resolve();
+        s.set_interface(self.interface)
+            .set_resolve()
+            .select_backend(service.backend().clone());
 
-        match service.domain() {
-            Some(d) => {
-                let host = self.hosttarget.replace(d, "");
-                s.set_host(host[0..host.len() - 1].to_string())
-                    .set_domain(d.to_string())
+        // Use the domain the service was resolved in, if it can be found
+        let domain = match self.fullname.parse::<ServiceInstanceName>() {
+            Ok(fullname) => Some(format!("{}.", fullname.domain())),
+            Err(e) => {
+                log::warn!("Failed to parse resolved name: {}", e);
+                service.domain().clone()
             }
-            _ => &s,
+        };
+
+        match domain {
+            Some(d) => s
+                .set_host(host_in_domain(&self.hosttarget, &d))
+                .set_domain(d),
+            None => s.set_host(self.hosttarget),
         };
 
         s
     }
 }
 
+// Get the host name relative to `domain`, or the full name with a trailing dot
+// if the host is not in the domain
+fn host_in_domain(hosttarget: &str, domain: &str) -> String {
+    let host = hosttarget.trim_end_matches('.');
+    let suffix = format!(".{}", domain.trim_end_matches('.'));
+    let split = host.len().saturating_sub(suffix.len());
+    match (host.get(..split), host.get(split..)) {
+        (Some(name), Some(end)) if !name.is_empty() && end.eq_ignore_ascii_case(&suffix) => {
+            name.to_string()
+        }
+        _ => format!("{}.", host),
+    }
+}
+
 #[derive(Debug)]
 struct ServiceResolverResult {
     rx: mpsc::UnboundedReceiver<Result<ResolverInformation, ZeroconfError>>,
@@ -285,120 +348,3 @@ impl ServiceResolverResult {
             .await
     }
 }
-
-#[derive(Debug)]
-struct ServiceResolverContext {
-    tx: mpsc::UnboundedSender<Result<ResolverInformation, ZeroconfError>>,
-}
-
-impl ServiceResolverContext {
-    fn send(&self, info: Result<ResolverInformation, ZeroconfError>) {
-        if self.tx.send(info).is_err() {
-            log::warn!("Failed to send resolved information, receiver dropped");
-        }
-    }
-}
-
-unsafe fn resolve_callback_inner(
-    intf_index: u32,
-    fullname: *const libc::c_char,
-    hosttarget: *const libc::c_char,
-    port: u16,
-    txt_len: u16,
-    txt_record: *const libc::c_uchar,
-) -> Result<ResolverInformation, ZeroconfError> {
-    let c_fullname = ffi::CStr::from_ptr(fullname);
-    let c_hosttarget = ffi::CStr::from_ptr(hosttarget);
-    let fullname = c_fullname.to_str()?;
-    let hosttarget = c_hosttarget.to_str()?;
-    let port = port.to_be();
-
-    log::debug!(
-        "ServiceResolve Callback OK ({}:{}:{})",
-        fullname,
-        hosttarget,
-        port
-    );
-
-    let txt_count = TXTRecordGetCount(txt_len, txt_record as *const libc::c_void);
-    let mut txt = TxtRecord::new();
-    for i in 0..txt_count {
-        let keysize: u16 = 256;
-        let mut valsize = 0;
-        let mut valptr: *const libc::c_void = ptr::null_mut();
-        let mut keybuf = vec![0; (keysize + 1).into()];
-        let keyptr = keybuf.as_mut_ptr() as *mut libc::c_char;
-        let err = TXTRecordGetItemAtIndex(
-            txt_len,
-            txt_record as *const libc::c_void,
-            i,
-            keysize,
-            keyptr,
-            &mut valsize,
-            &mut valptr,
-        );
-
-        if err == 0 {
-            let keylen = keybuf.iter().position(|&c| c == 0).expect(
-                "No error reported by TXTRecordGetItemAtIndex but no null byte in key string",
-            );
-            keybuf.truncate(keylen);
-
-            let key = String::from_utf8_lossy(&keybuf);
-            let val_slice =
-                std::slice::from_raw_parts(valptr as *const libc::c_uchar, valsize.into());
-
-            txt.add_vec(key.into_owned(), val_slice.to_owned());
-        } else {
-            log::error!(
-                "TXTRecordGetItemAtIndex Callback Error ({}:{})",
-                err,
-                Into::<BonjourError>::into(err)
-            );
-            return Err(err.into());
-        }
-    }
-
-    let info = ResolverInformation {
-        interface: Interface::Interface(intf_index),
-        fullname: fullname.to_string(),
-        hosttarget: hosttarget.to_string(),
-        port,
-        txt_record: txt,
-    };
-
-    Ok(info)
-}
-
-// Callback passed to DNSServiceResolve
-unsafe extern "C" fn resolve_callback(
-    _sd_ref: DNSServiceRef,
-    flags: DNSServiceFlags,
-    intf_index: u32,
-    error: DNSServiceErrorType,
-    fullname: *const libc::c_char,
-    hosttarget: *const libc::c_char,
-    port: u16,
-    txt_len: u16,
-    txt_record: *const libc::c_uchar,
-    context: *mut libc::c_void,
-) {
-    let proxy = &*(context as *const ServiceResolverContext);
-    if error == 0 {
-        let more = (flags & 0x1) == 0x1;
-        if more {
-            log::warn!("Unexpected DNSServiceFlagsMoreComing set on resolve")
-        }
-
-        proxy.send(resolve_callback_inner(
-            intf_index, fullname, hosttarget, port, txt_len, txt_record,
-        ));
-    } else {
-        proxy.send(Err(error.into()));
-        log::error!(
-            "ServiceResolve Callback Error ({}:{})",
-            error,
-            Into::<BonjourError>::into(error)
-        )
-    }
-}
@@ -0,0 +1,220 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::ZeroconfError;
+
+/// Enum to hold the transport protocol of a [`ServiceType`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ServiceProtocol {
+    /// The service uses TCP (`_tcp`)
+    Tcp,
+    /// The service uses any protocol other than TCP (`_udp`)
+    Udp,
+}
+
+impl fmt::Display for ServiceProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ServiceProtocol::Tcp => write!(f, "_tcp"),
+            ServiceProtocol::Udp => write!(f, "_udp"),
+        }
+    }
+}
+
+/// Struct representing the type of a `ZeroConf` service, made up of the
+/// service name, the transport protocol and any subtypes, e.g.
+/// `_http._tcp,_printer`.
+///
+/// The service name must follow the rules of [RFC 6335][rfc], so is at most
+/// 15 characters of letters, digits and hyphens. Subtypes must start with an
+/// underscore and be a single DNS label.
+///
+/// # Examples
+/// ```
+/// use async_zeroconf::{ServiceProtocol, ServiceType};
+///
+/// let service_type: ServiceType = "_http._tcp,_printer".parse()?;
+/// assert_eq!(service_type.name(), "http");
+/// assert_eq!(service_type.protocol(), ServiceProtocol::Tcp);
+/// assert_eq!(service_type.subtypes(), ["_printer"]);
+///
+/// // The browse form of a subtype is also accepted
+/// let browse_type: ServiceType = "_printer._sub._http._tcp".parse()?;
+/// assert_eq!(browse_type, service_type);
+/// assert_eq!(browse_type.to_string(), "_http._tcp,_printer");
+///
+/// assert!("_a._tcp.garbage".parse::<ServiceType>().is_err());
+/// # Ok::<(), async_zeroconf::ZeroconfError>(())
+/// ```
+/// [rfc]: https://www.rfc-editor.org/rfc/rfc6335#section-5.1
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ServiceType {
+    name: String,
+    protocol: ServiceProtocol,
+    subtypes: Vec<String>,
+}
+
+impl ServiceType {
+    /// Create a new `ServiceType` for the service name `name`, such as `http`,
+    /// using the transport protocol `protocol`.
+    pub fn new(name: &str, protocol: ServiceProtocol) -> Result<Self, ZeroconfError> {
+        if validate_name(name) {
+            Ok(ServiceType {
+                name: name.to_string(),
+                protocol,
+                subtypes: Vec::new(),
+            })
+        } else {
+            Err(ZeroconfError::InvalidServiceType(name.to_string()))
+        }
+    }
+
+    /// Add a subtype, such as `_printer`, to the service type.
+    pub fn add_subtype(&mut self, subtype: &str) -> Result<&mut Self, ZeroconfError> {
+        if validate_subtype(subtype) {
+            self.subtypes.push(subtype.to_string());
+            Ok(self)
+        } else {
+            Err(ZeroconfError::InvalidSubtype(subtype.to_string()))
+        }
+    }
+
+    /// Get the service name, without the leading underscore
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the transport protocol
+    pub fn protocol(&self) -> ServiceProtocol {
+        self.protocol
+    }
+
+    /// Get the subtypes
+    pub fn subtypes(&self) -> &[String] {
+        &self.subtypes
+    }
+
+    // Parse a service type without applying the RFC 6335 rules to the service
+    // name, so that types already in use that don't follow them, such as
+    // `_nvstream_dbd._tcp`, can still be browsed for
+    pub(crate) fn parse_lenient(s: &str) -> Result<Self, ZeroconfError> {
+        parse(s, false)
+    }
+
+    // The service type without any subtypes, e.g. `_http._tcp`
+    pub(crate) fn base(&self) -> String {
+        format!("_{}.{}", self.name, self.protocol)
+    }
+
+    // The name used to browse for this type, only a single subtype can be
+    // browsed for
+    pub(crate) fn browse_query(&self) -> Result<String, ZeroconfError> {
+        match self.subtypes.as_slice() {
+            [] => Ok(self.base()),
+            [subtype] => Ok(format!("{}._sub.{}", subtype, self.base())),
+            _ => Err(ZeroconfError::InvalidServiceType(self.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for ServiceType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.base())?;
+        for subtype in &self.subtypes {
+            write!(f, ",{}", subtype)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ServiceType {
+    type Err = ZeroconfError;
+
+    /// Parse a service type of the form `_http._tcp`, with an optional
+    /// trailing dot, followed by any subtypes separated by commas. A subtype
+    /// may also be given in the form `_printer._sub._http._tcp`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s, true)
+    }
+}
+
+// Parse a service type, only checking that the service name follows RFC 6335
+// if `strict` is set
+fn parse(s: &str, strict: bool) -> Result<ServiceType, ZeroconfError> {
+    let invalid = || ZeroconfError::InvalidServiceType(s.to_string());
+
+    let mut parts = s.split(',');
+    let (subtype, base) = split_subtype(parts.next().unwrap_or_default());
+    let base = base.strip_suffix('.').unwrap_or(base);
+
+    let mut labels = base.split('.');
+    let (name, protocol) = match (labels.next(), labels.next(), labels.next()) {
+        (Some(name), Some(protocol), None) => (name, protocol),
+        _ => return Err(invalid()),
+    };
+    let name = name.strip_prefix('_').ok_or_else(invalid)?;
+    let protocol = if protocol.eq_ignore_ascii_case("_tcp") {
+        ServiceProtocol::Tcp
+    } else if protocol.eq_ignore_ascii_case("_udp") {
+        ServiceProtocol::Udp
+    } else {
+        return Err(invalid());
+    };
+
+    let mut service_type = if strict {
+        ServiceType::new(name, protocol).map_err(|_| invalid())?
+    } else if validate_label(name) {
+        ServiceType {
+            name: name.to_string(),
+            protocol,
+            subtypes: Vec::new(),
+        }
+    } else {
+        return Err(invalid());
+    };
+    for subtype in subtype.into_iter().chain(parts) {
+        service_type.add_subtype(subtype)?;
+    }
+    Ok(service_type)
+}
+
+// Check that a service name follows RFC 6335, at most 15 letters, digits and
+// hyphens, with at least one letter and no leading, trailing or consecutive
+// hyphens
+fn validate_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 15
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        && name.chars().any(|c| c.is_ascii_alphabetic())
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+        && !name.contains("--")
+}
+
+// Check that a service name fits in a single label with its leading underscore,
+// without the other rules of RFC 6335
+fn validate_label(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() < 63
+        && !name.contains(|c: char| c == ',' || c == '\\' || c.is_control())
+}
+
+// Check that a subtype is a single label starting with an underscore
+pub(crate) fn validate_subtype(subtype: &str) -> bool {
+    subtype.starts_with('_')
+        && subtype.len() > 1
+        && subtype.len() <= 63
+        && !subtype.contains(|c: char| c == '.' || c == ',' || c == '\\' || c.is_control())
+}
+
+// Split a browse query of the form `_printer._sub._http._tcp` into the
+// subtype and the service type
+pub(crate) fn split_subtype(query: &str) -> (Option<&str>, &str) {
+    let mut parts = query.splitn(3, '.');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(subtype), Some(sub), Some(service_type)) if sub.eq_ignore_ascii_case("_sub") => {
+            (Some(subtype), service_type)
+        }
+        _ => (None, query),
+    }
+}
@@ -0,0 +1,335 @@
+use crate::backend::{Backend, BackendSelection, BrowseContext};
+use crate::{
+    BrowseEvent, Interface, OpKind, OpType, ProcessTask, Service, ServiceBrowserBuilder,
+    ServiceRef, ServiceType, ZeroconfError,
+};
+
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures::stream::{AbortHandle, Abortable, SelectAll, StreamExt};
+use futures_core::Stream;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+// The meta-query used to find all service types, see RFC 6763 §9
+pub(crate) const SERVICE_TYPE_QUERY: &str = "_services._dns-sd._udp";
+
+/// `ServiceTypeEvent` is produced by a [`ServiceTypeBrowser`] when a service
+/// type is found or removed.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ServiceTypeEvent {
+    /// A service type has been found by the browser.
+    Added(ServiceType),
+    /// There are no longer any services of a type previously found by the
+    /// browser.
+    Removed(ServiceType),
+}
+
+impl ServiceTypeEvent {
+    /// Get the service type that was added or removed.
+    pub fn service_type(&self) -> &ServiceType {
+        match self {
+            ServiceTypeEvent::Added(t) | ServiceTypeEvent::Removed(t) => t,
+        }
+    }
+}
+
+/// `ServiceTypeBrowserBuilder` is used to find the types of all the services
+/// on the network, by browsing for the `_services._dns-sd._udp` meta-query
+/// described in [RFC 6763 §9][rfc].
+///
+/// # Examples
+/// ```
+/// # tokio_test::block_on(async {
+/// use async_zeroconf::{ServiceTypeBrowserBuilder, ServiceTypeEvent};
+///
+/// let mut builder = ServiceTypeBrowserBuilder::new();
+/// let mut types = builder
+///     .timeout(tokio::time::Duration::from_secs(2))
+///     .browse()?;
+///
+/// while let Some(Ok(event)) = types.recv().await {
+///     match event {
+///         ServiceTypeEvent::Added(t) => println!("Added = {}", t),
+///         ServiceTypeEvent::Removed(t) => println!("Removed = {}", t),
+///     }
+/// }
+/// # Ok::<(), async_zeroconf::ZeroconfError>(())
+/// # });
+/// ```
+/// [rfc]: https://www.rfc-editor.org/rfc/rfc6763#section-9
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Default)]
+pub struct ServiceTypeBrowserBuilder {
+    interface: Interface,
+    domain: Option<String>,
+    timeout: Option<Duration>,
+    backend: BackendSelection,
+}
+
+impl ServiceTypeBrowserBuilder {
+    /// Create a new `ServiceTypeBrowserBuilder`
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the timeout
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the interface to find service types on rather than all
+    pub fn interface(&mut self, interface: Interface) -> &mut Self {
+        self.interface = interface;
+        self
+    }
+
+    /// Set the domain to find service types in rather than all
+    pub fn domain(&mut self, domain: String) -> &mut Self {
+        self.domain = Some(domain);
+        self
+    }
+
+    /// Set the backend used to find service types rather than the default
+    /// backend
+    pub fn backend(&mut self, backend: Arc<dyn Backend>) -> &mut Self {
+        self.backend = BackendSelection::new(backend);
+        self
+    }
+
+    /// Start finding service types, which will continue until the specified
+    /// timeout or until the [`ServiceTypeBrowser`] is dropped.
+    pub fn browse(&self) -> Result<ServiceTypeBrowser, ZeroconfError> {
+        let (browser, task) = self.browse_task()?;
+
+        tokio::spawn(task);
+
+        Ok(browser)
+    }
+
+    /// Start finding service types, which will continue until the specified
+    /// timeout or until the [`ServiceTypeBrowser`] is dropped. The returned
+    /// [`ProcessTask`] future must be awaited to process events associated
+    /// with the browser.
+    ///
+    /// # Note
+    /// This method is intended if more control is needed over how the task
+    /// is spawned. [`ServiceTypeBrowserBuilder::browse`] will automatically
+    /// spawn the task.
+    pub fn browse_task(&self) -> Result<(ServiceTypeBrowser, impl ProcessTask), ZeroconfError> {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        let operation = self.backend.get()?.browse(
+            &self.interface,
+            SERVICE_TYPE_QUERY,
+            self.domain.as_deref(),
+            BrowseContext::new(tx, self.backend.clone()),
+        )?;
+
+        let (delegate, task) = ServiceRef::from_operation(
+            operation,
+            OpType::new(SERVICE_TYPE_QUERY, OpKind::Browse),
+            self.timeout,
+        );
+
+        log::debug!("Created ServiceTypeBrowser");
+        let browser = ServiceTypeBrowser {
+            rx,
+            delegate,
+            found: Vec::new(),
+            builder: self.clone(),
+        };
+
+        Ok((browser, task))
+    }
+
+    // A builder for a browser of the services of type `service_type`, using
+    // the same interface, domain and backend
+    fn service_browser(&self, service_type: &ServiceType) -> ServiceBrowserBuilder {
+        let mut builder = ServiceBrowserBuilder::new(&service_type.to_string());
+        builder
+            .interface(self.interface)
+            .select_backend(self.backend.clone());
+        if let Some(domain) = &self.domain {
+            builder.domain(domain.clone());
+        }
+        builder
+    }
+}
+
+/// Struct used to get the [`ServiceTypeEvent`]s of a service type browser,
+/// which should be constructed with a [`ServiceTypeBrowserBuilder`]. It can be
+/// used as a stream or with [`ServiceTypeBrowser::recv`].
+///
+/// Each service type is reported once, even if it is found on multiple
+/// interfaces or domains.
+#[derive(Debug)]
+pub struct ServiceTypeBrowser {
+    // Channel to receive the service types, reported as services
+    rx: mpsc::UnboundedReceiver<(Result<BrowseEvent, ZeroconfError>, bool)>,
+    // Reference to the browse operation
+    delegate: ServiceRef,
+    // Service types found and the number of times each has been reported
+    found: Vec<(ServiceType, usize)>,
+    // Builder used to create browsers for each service type
+    builder: ServiceTypeBrowserBuilder,
+}
+
+impl ServiceTypeBrowser {
+    /// Receive an event from the browser.
+    ///
+    /// A response of `None` indicates that the browse operation has
+    /// finished, for example due to a timeout or error.
+    pub async fn recv(&mut self) -> Option<Result<ServiceTypeEvent, ZeroconfError>> {
+        loop {
+            let event = self.rx.recv().await?.0;
+            if let Some(event) = self.update(event) {
+                return Some(event);
+            }
+        }
+    }
+
+    /// Get the [`OpType`] of the browse operation.
+    pub fn op_type(&self) -> &OpType {
+        self.delegate.op_type()
+    }
+
+    /// Return a stream of the events of a [`ServiceBrowser`][`crate::ServiceBrowser`]
+    /// for each service type found, reporting every service on the network
+    /// that is found or removed. The `ServiceTypeBrowser` is consumed to
+    /// produce the new stream.
+    ///
+    /// The browsers use the same interface, domain and backend as the
+    /// `ServiceTypeBrowser`, and finish when it finishes. The browser for a
+    /// service type is stopped when the type is removed.
+    ///
+    /// # Examples
+    /// ```
+    /// use tokio_stream::StreamExt;
+    /// # tokio_test::block_on(async {
+    /// let mut builder = async_zeroconf::ServiceTypeBrowserBuilder::new();
+    /// let types = builder
+    ///     .timeout(tokio::time::Duration::from_secs(2))
+    ///     .browse()?;
+    ///
+    /// let mut stream = types.services();
+    /// while let Some(Ok(event)) = stream.next().await {
+    ///     println!("Event = {:?}", event);
+    /// }
+    /// # Ok::<(), async_zeroconf::ZeroconfError>(())
+    /// # });
+    /// ```
+    pub fn services(self) -> impl Stream<Item = Result<BrowseEvent, ZeroconfError>> + Unpin {
+        // Each browser can be stopped through the handle kept for its type, so
+        // that it is stopped when the type is removed and a type found again
+        // isn't browsed twice
+        let browsers = SelectAll::new();
+        let handles: HashMap<ServiceType, AbortHandle> = HashMap::new();
+        Box::pin(futures::stream::unfold(
+            (self, browsers, handles),
+            |(mut types, mut browsers, mut handles)| async move {
+                loop {
+                    tokio::select! {
+                        Some(event) = browsers.next() => {
+                            return Some((event, (types, browsers, handles)))
+                        }
+                        event = types.recv() => match event? {
+                            Ok(ServiceTypeEvent::Added(t)) if !handles.contains_key(&t) => {
+                                match types.builder.service_browser(&t).browse() {
+                                    Ok(browser) => {
+                                        let (handle, registration) = AbortHandle::new_pair();
+                                        let events = browser.events();
+                                        browsers.push(Abortable::new(events, registration));
+                                        handles.insert(t, handle);
+                                    }
+                                    Err(e) => return Some((Err(e), (types, browsers, handles))),
+                                }
+                            }
+                            Ok(ServiceTypeEvent::Added(_)) => (),
+                            Ok(ServiceTypeEvent::Removed(t)) => {
+                                if let Some(handle) = handles.remove(&t) {
+                                    handle.abort();
+                                }
+                            }
+                            Err(e) => return Some((Err(e), (types, browsers, handles))),
+                        },
+                    }
+                }
+            },
+        ))
+    }
+
+    // Convert a service found by the meta-query into an event, if the service
+    // type hasn't already been reported
+    fn update(
+        &mut self,
+        event: Result<BrowseEvent, ZeroconfError>,
+    ) -> Option<Result<ServiceTypeEvent, ZeroconfError>> {
+        let (added, service) = match event {
+            Ok(BrowseEvent::Added(s)) => (true, s),
+            Ok(BrowseEvent::Removed(s)) => (false, s),
+            Err(e) => return Some(Err(e)),
+        };
+        // A single malformed type on the network shouldn't end the browser
+        let service_type = match parse_type(&service) {
+            Ok(t) => t,
+            Err(e) => {
+                log::warn!("Skipping service type - {}", e);
+                return None;
+            }
+        };
+        let position = self.found.iter().position(|f| f.0 == service_type);
+
+        match (added, position) {
+            (true, Some(i)) => {
+                self.found[i].1 += 1;
+                None
+            }
+            (true, None) => {
+                self.found.push((service_type.clone(), 1));
+                Some(Ok(ServiceTypeEvent::Added(service_type)))
+            }
+            (false, Some(i)) => {
+                self.found[i].1 -= 1;
+                if self.found[i].1 == 0 {
+                    self.found.remove(i);
+                    Some(Ok(ServiceTypeEvent::Removed(service_type)))
+                } else {
+                    None
+                }
+            }
+            (false, None) => None,
+        }
+    }
+}
+
+impl Stream for ServiceTypeBrowser {
+    type Item = Result<ServiceTypeEvent, ZeroconfError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<<Self as futures_core::Stream>::Item>> {
+        loop {
+            let event = match self.rx.poll_recv(cx) {
+                Poll::Ready(Some(e)) => e.0,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+            if let Some(event) = self.update(event) {
+                return Poll::Ready(Some(event));
+            }
+        }
+    }
+}
+
+// Results of the meta-query are reported in the same form as
+// `DNSServiceBrowse`, with the first label of the service type as the name
+// and the rest, such as `_tcp.local.`, as the type. Types in use don't all
+// follow RFC 6335, so the service name isn't checked against it.
+fn parse_type(service: &Service) -> Result<ServiceType, ZeroconfError> {
+    let protocol = service.service_type().split('.').next().unwrap_or_default();
+    ServiceType::parse_lenient(&format!("{}.{}", service.name(), protocol))
+}
@@ -0,0 +1,307 @@
+// Supervision of operations, restarting them if the daemon stops
+
+use crate::backend::{AddRecordFuture, BackendOperation, ProcessFuture, UpdateFuture};
+use crate::sync::{lock, read, write};
+use crate::{BonjourError, RecordType, ResourceRecord, TxtRecord, ZeroconfError};
+
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures_core::Stream;
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// `Supervision` is used to restart an operation if the daemon providing it
+/// stops, for example when `mDNSResponder` or `avahi-daemon` is restarted.
+/// Without supervision the operation ends, so a published service is no
+/// longer registered and a browser finds no more services.
+///
+/// Supervision is enabled for a service with
+/// [`Service::supervise`][`crate::Service::supervise`] or for a browser with
+/// [`ServiceBrowserBuilder::supervise`][`crate::ServiceBrowserBuilder::supervise`].
+/// Once the daemon is found to have stopped the operation is started again
+/// after `initial_delay`, doubling the delay after each failed attempt up to
+/// `max_delay`. Attempts continue until the operation is started or the
+/// [`ServiceRef`][`crate::ServiceRef`] is dropped. The delay is reset once an
+/// operation has run for `max_delay`.
+///
+/// A published service is registered again with the TXT record of the last
+/// [`ServiceRef::update_txt`][`crate::ServiceRef::update_txt`], but any
+/// records added with [`ServiceRef::add_record`][`crate::ServiceRef::add_record`]
+/// are not restored. A browser finds any services that are still present
+/// again.
+///
+/// # Examples
+/// ```
+/// # tokio_test::block_on(async {
+/// use async_zeroconf::{ReconnectEvent, Service, Supervision};
+///
+/// let mut service = Service::new("Server", "_http._tcp", 80);
+/// let service_ref = service.supervise(Supervision::default()).publish().await?;
+///
+/// let mut reconnects = service_ref.reconnects();
+/// tokio::spawn(async move {
+///     while let Some(event) = reconnects.recv().await {
+///         match event {
+///             ReconnectEvent::Disconnected(e) => println!("Daemon stopped = {}", e),
+///             ReconnectEvent::Reconnected => println!("Service published again"),
+///         }
+///     }
+/// });
+/// # Ok::<(), async_zeroconf::ZeroconfError>(())
+/// # });
+/// ```
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Supervision {
+    initial_delay: Duration,
+    max_delay: Duration,
+}
+
+impl Supervision {
+    /// Create a `Supervision` that waits `initial_delay` before the first
+    /// attempt to start an operation again, and at most `max_delay` between
+    /// attempts.
+    pub fn new(initial_delay: Duration, max_delay: Duration) -> Self {
+        Supervision {
+            initial_delay,
+            max_delay: max_delay.max(initial_delay),
+        }
+    }
+
+    /// The delay before the first attempt to start an operation again.
+    pub fn initial_delay(&self) -> Duration {
+        self.initial_delay
+    }
+
+    /// The longest delay between attempts to start an operation again.
+    pub fn max_delay(&self) -> Duration {
+        self.max_delay
+    }
+}
+
+impl Default for Supervision {
+    /// Wait one second before the first attempt, and at most a minute
+    /// between attempts.
+    fn default() -> Self {
+        Supervision::new(Duration::from_secs(1), Duration::from_secs(60))
+    }
+}
+
+/// `ReconnectEvent` is produced by [`ReconnectEvents`] when the daemon
+/// providing a supervised operation stops, and when the operation is started
+/// again.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ReconnectEvent {
+    /// The daemon stopped with the error given, so the operation has ended
+    /// until it is started again.
+    Disconnected(BonjourError),
+    /// The operation has been started again. A published service reports
+    /// its registration as a [`RegistrationEvent`][`crate::RegistrationEvent`]
+    /// as before.
+    Reconnected,
+}
+
+/// Struct used to get the [`ReconnectEvent`]s of a supervised operation,
+/// produced by [`ServiceRef::reconnects`][`crate::ServiceRef::reconnects`]
+/// or [`ServiceBrowser::reconnects`][`crate::ServiceBrowser::reconnects`],
+/// which can be used as a stream or with [`ReconnectEvents::recv`].
+#[derive(Debug)]
+pub struct ReconnectEvents {
+    rx: mpsc::UnboundedReceiver<ReconnectEvent>,
+}
+
+impl ReconnectEvents {
+    pub(crate) fn new(rx: mpsc::UnboundedReceiver<ReconnectEvent>) -> Self {
+        ReconnectEvents { rx }
+    }
+
+    /// Receive the next event for the operation.
+    ///
+    /// A response of `None` indicates that the operation has ended.
+    pub async fn recv(&mut self) -> Option<ReconnectEvent> {
+        self.rx.recv().await
+    }
+}
+
+impl Stream for ReconnectEvents {
+    type Item = ReconnectEvent;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<<Self as futures_core::Stream>::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+// Subscribers to the events of a supervised operation, shared between the
+// operation and the `ServiceRef`
+#[derive(Debug, Default)]
+pub(crate) struct SupervisionState {
+    subscribers: Mutex<Vec<mpsc::UnboundedSender<ReconnectEvent>>>,
+}
+
+impl SupervisionState {
+    pub(crate) fn subscribe(&self) -> mpsc::UnboundedReceiver<ReconnectEvent> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        lock(&self.subscribers).push(tx);
+        rx
+    }
+
+    fn send(&self, event: ReconnectEvent) {
+        lock(&self.subscribers).retain(|s| s.send(event.clone()).is_ok());
+    }
+}
+
+// Check if an error shows that the daemon providing an operation has stopped
+fn daemon_stopped(e: &ZeroconfError) -> Option<BonjourError> {
+    match e {
+        ZeroconfError::Bonjour(BonjourError::ServiceNotRunning) => {
+            Some(BonjourError::ServiceNotRunning)
+        }
+        _ => None,
+    }
+}
+
+// Function starting the supervised operation, passed the TXT record of the
+// last update if there has been one
+type StartFn =
+    dyn Fn(Option<TxtRecord>) -> Result<Box<dyn BackendOperation>, ZeroconfError> + Send + Sync;
+
+// Delay before the next attempt to start the operation, and when the
+// operation was last started
+#[derive(Debug)]
+struct Backoff {
+    delay: Duration,
+    started: Instant,
+}
+
+// Operation that is started again by `start` whenever the daemon stops
+pub(crate) struct SupervisedOperation {
+    operation: RwLock<Arc<dyn BackendOperation>>,
+    start: Box<StartFn>,
+    supervision: Supervision,
+    state: Arc<SupervisionState>,
+    backoff: Mutex<Backoff>,
+    // TXT record of the last update, used when the service is registered
+    // again
+    txt: Mutex<Option<TxtRecord>>,
+}
+
+impl SupervisedOperation {
+    // Start an operation with `start`, returning the supervised operation and
+    // the state used to subscribe to its events
+    pub(crate) fn start<F>(
+        start: F,
+        supervision: Supervision,
+    ) -> Result<(Box<dyn BackendOperation>, Arc<SupervisionState>), ZeroconfError>
+    where
+        F: Fn(Option<TxtRecord>) -> Result<Box<dyn BackendOperation>, ZeroconfError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let operation = start(None)?;
+        let state = Arc::new(SupervisionState::default());
+        let supervised = SupervisedOperation {
+            operation: RwLock::new(Arc::from(operation)),
+            start: Box::new(start),
+            supervision,
+            state: state.clone(),
+            backoff: Mutex::new(Backoff {
+                delay: supervision.initial_delay,
+                started: Instant::now(),
+            }),
+            txt: Mutex::new(None),
+        };
+        Ok((Box::new(supervised), state))
+    }
+
+    // Get the operation that is currently running
+    fn current(&self) -> Arc<dyn BackendOperation> {
+        read(&self.operation).clone()
+    }
+
+    // Start the operation again, retrying with an increasing delay while the
+    // daemon isn't running
+    async fn restart(&self) -> Result<(), ZeroconfError> {
+        let mut delay = {
+            let backoff = lock(&self.backoff);
+            if backoff.started.elapsed() >= self.supervision.max_delay {
+                self.supervision.initial_delay
+            } else {
+                backoff.delay
+            }
+        };
+        loop {
+            log::debug!("Starting operation again in {:?}", delay);
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(self.supervision.max_delay);
+
+            let txt = lock(&self.txt).clone();
+            match (self.start)(txt) {
+                Ok(operation) => {
+                    *lock(&self.backoff) = Backoff {
+                        delay,
+                        started: Instant::now(),
+                    };
+                    *write(&self.operation) = Arc::from(operation);
+                    return Ok(());
+                }
+                Err(e) if daemon_stopped(&e).is_some() => {
+                    log::debug!("Failed to start operation again: {}", e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl fmt::Debug for SupervisedOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SupervisedOperation")
+            .field("operation", &self.current())
+            .field("supervision", &self.supervision)
+            .finish()
+    }
+}
+
+impl BackendOperation for SupervisedOperation {
+    fn process(&self) -> ProcessFuture<'_> {
+        Box::pin(async move {
+            let result = self.current().process().await;
+            match result.as_ref().err().and_then(daemon_stopped) {
+                Some(e) => {
+                    log::warn!("Daemon stopped ({}), starting operation again", e);
+                    self.state.send(ReconnectEvent::Disconnected(e));
+                    self.restart().await?;
+                    log::debug!("Operation started again");
+                    self.state.send(ReconnectEvent::Reconnected);
+                    Ok(true)
+                }
+                None => result,
+            }
+        })
+    }
+
+    fn update_txt(&self, txt: TxtRecord) -> UpdateFuture<'_> {
+        Box::pin(async move {
+            self.current().update_txt(txt.clone()).await?;
+            *lock(&self.txt) = Some(txt);
+            Ok(())
+        })
+    }
+
+    fn add_record(&self, rrtype: RecordType, rdata: Vec<u8>, ttl: u32) -> AddRecordFuture<'_> {
+        Box::pin(async move { self.current().add_record(rrtype, rdata, ttl).await })
+    }
+
+    fn register_record(&self, record: ResourceRecord, unique: bool) -> AddRecordFuture<'_> {
+        Box::pin(async move { self.current().register_record(record, unique).await })
+    }
+
+    fn remove_record(&self, id: u64) -> Result<(), ZeroconfError> {
+        self.current().remove_record(id)
+    }
+}
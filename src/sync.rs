@@ -0,0 +1,24 @@
+use std::sync::{Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+// Locks are taken ignoring poisoning, as the data protected by the crate's
+// locks is always left consistent
+
+// Lock a mutex
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(poisoned::<T, _>)
+}
+
+// Lock a read-write lock for reading
+pub(crate) fn read<T>(rwlock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    rwlock.read().unwrap_or_else(poisoned::<T, _>)
+}
+
+// Lock a read-write lock for writing
+pub(crate) fn write<T>(rwlock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    rwlock.write().unwrap_or_else(poisoned::<T, _>)
+}
+
+fn poisoned<T, G>(e: PoisonError<G>) -> G {
+    log::warn!("Lock of {} was poisoned", std::any::type_name::<T>());
+    e.into_inner()
+}
@@ -23,6 +23,194 @@ async fn create_invalid_service() {
     }
 }
 
+#[test]
+fn service_registration_type() {
+    let mut service = Service::new("Server", "_http._tcp", 80);
+    assert_eq!(service.registration_type(), "_http._tcp");
+    service.add_subtype("_printer").add_subtype("_color");
+    assert_eq!(service.registration_type(), "_http._tcp,_printer,_color");
+}
+
+#[test]
+fn parse_service_type() {
+    use crate::{ServiceProtocol, ServiceType};
+
+    let valid = [
+        ("_http._tcp", "_http._tcp"),
+        ("_http._tcp.", "_http._tcp"),
+        ("_ipp._UDP", "_ipp._udp"),
+        ("_a-b1._tcp,_printer,_color", "_a-b1._tcp,_printer,_color"),
+        ("_printer._sub._http._tcp.", "_http._tcp,_printer"),
+    ];
+    for (s, expected) in valid {
+        let service_type: ServiceType = s.parse().unwrap();
+        assert_eq!(service_type.to_string(), expected);
+    }
+
+    let invalid = [
+        "_a._tcp.garbage",
+        "http._tcp",
+        "_http._sctp",
+        "_http",
+        "_123._tcp",
+        "_-http._tcp",
+        "_ht--tp._tcp",
+        "_web_server._tcp",
+        "_sixteencharslong._tcp",
+        "_http._tcp,printer",
+    ];
+    for s in invalid {
+        println!("Testing '{}'", s);
+        assert!(s.parse::<ServiceType>().is_err());
+    }
+
+    let mut service_type = ServiceType::new("http", ServiceProtocol::Udp).unwrap();
+    service_type.add_subtype("_printer").unwrap();
+    assert_eq!(service_type.to_string(), "_http._udp,_printer");
+    assert!(ServiceType::new("_http", ServiceProtocol::Tcp).is_err());
+}
+
+#[test]
+fn service_type_with_subtypes() {
+    let service = Service::new("Server", "_http._tcp,_printer", 80);
+    assert_eq!(service.service_type(), "_http._tcp");
+    assert_eq!(service.subtypes(), ["_printer"]);
+    assert_eq!(service.registration_type(), "_http._tcp,_printer");
+}
+
+#[test]
+fn parse_instance_name() {
+    use crate::ServiceInstanceName;
+
+    let names = [
+        ("My Printer", "My\\032Printer._ipp._tcp.local."),
+        ("Office 3.1", "Office\\0323\\.1._ipp._tcp.local."),
+        ("back\\slash", "back\\\\slash._ipp._tcp.local."),
+        ("Tab\tName", "Tab\\009Name._ipp._tcp.local."),
+        ("Café", "Café._ipp._tcp.local."),
+    ];
+    for (name, fullname) in names {
+        let instance = ServiceInstanceName::new(name, "_ipp._tcp.", "local.");
+        assert_eq!(instance.to_string(), fullname);
+
+        let parsed: ServiceInstanceName = fullname.parse().unwrap();
+        assert_eq!(parsed, instance);
+    }
+
+    let parsed: ServiceInstanceName = "Server._http._tcp.example.com".parse().unwrap();
+    assert_eq!(parsed.name(), "Server");
+    assert_eq!(parsed.domain(), "example.com");
+
+    let invalid = [
+        "Server._http._tcp.",
+        "Server._http.local.",
+        "Server.http._tcp.local.",
+        "Server.._http._tcp.local.",
+        "Bad\\25._http._tcp.local.",
+        "Bad\\256._http._tcp.local.",
+        "Bad\\",
+    ];
+    for s in invalid {
+        println!("Testing '{}'", s);
+        assert!(s.parse::<ServiceInstanceName>().is_err());
+    }
+}
+
+#[test]
+fn parse_record_data() {
+    use crate::{RecordData, RecordType};
+
+    let srv = [
+        0, 1, 0, 2, 0, 80, 4, b'h', b'o', b's', b't', 5, b'l', b'o', b'c', b'a', b'l', 0,
+    ];
+    assert_eq!(
+        RecordData::from_rdata(33, &srv),
+        RecordData::Srv {
+            priority: 1,
+            weight: 2,
+            port: 80,
+            target: "host.local.".to_string()
+        }
+    );
+
+    let hinfo = b"\x05ARM64\x05Linux";
+    assert_eq!(
+        RecordData::from_rdata(13, hinfo),
+        RecordData::Hinfo {
+            cpu: "ARM64".to_string(),
+            os: "Linux".to_string()
+        }
+    );
+
+    let mut txt = TxtRecord::new();
+    txt.add("model".to_string(), "Xserve".to_string());
+    assert_eq!(
+        RecordData::from_rdata(16, b"\x0cmodel=Xserve"),
+        RecordData::Txt(txt)
+    );
+
+    assert_eq!(
+        RecordData::from_rdata(1, &[192, 168, 0, 2]),
+        RecordData::A("192.168.0.2".parse().unwrap())
+    );
+    // Malformed and unknown records are kept as raw data
+    assert_eq!(
+        RecordData::from_rdata(1, &[192, 168]),
+        RecordData::Other(1, vec![192, 168])
+    );
+    let other = RecordData::from_rdata(99, &[1, 2, 3]);
+    assert_eq!(other, RecordData::Other(99, vec![1, 2, 3]));
+    assert_eq!(other.rrtype(), RecordType::Other(99));
+}
+
+#[test]
+fn encode_record_data() -> Result<(), ZeroconfError> {
+    use crate::RecordData;
+
+    let srv = RecordData::Srv {
+        priority: 1,
+        weight: 2,
+        port: 80,
+        target: "host.local.".to_string(),
+    };
+    assert_eq!(
+        srv.to_rdata()?,
+        [0, 1, 0, 2, 0, 80, 4, b'h', b'o', b's', b't', 5, b'l', b'o', b'c', b'a', b'l', 0]
+    );
+
+    let hinfo = RecordData::Hinfo {
+        cpu: "ARM64".to_string(),
+        os: "Linux".to_string(),
+    };
+    assert_eq!(hinfo.to_rdata()?, b"\x05ARM64\x05Linux");
+    assert_eq!(RecordData::from_rdata(13, &hinfo.to_rdata()?), hinfo);
+
+    // An escaped dot is part of the label
+    let ptr = RecordData::Ptr("a\\.b.local.".to_string());
+    assert_eq!(ptr.to_rdata()?, b"\x03a.b\x05local\x00");
+    assert_eq!(RecordData::from_rdata(12, &ptr.to_rdata()?), ptr);
+
+    // An empty TXT record holds a single empty string
+    assert_eq!(RecordData::Txt(TxtRecord::new()).to_rdata()?, [0]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn create_invalid_subtype() {
+    let strings = ["printer", "_", "_a.b", "_a,b"];
+    for s in strings {
+        let result = Service::new("", "_http._tcp", 0)
+            .add_subtype(s)
+            .publish()
+            .await;
+        println!("Testing '{}'", s);
+        assert!(matches!(result, Err(ZeroconfError::InvalidSubtype(_))));
+
+        let result = ServiceBrowserBuilder::new("_http._tcp").subtype(s).browse();
+        assert!(matches!(result, Err(ZeroconfError::InvalidSubtype(_))));
+    }
+}
+
 #[tokio::test]
 async fn publish_service() -> Result<(), ZeroconfError> {
     let service = Service::new("Server", "_http._tcp", 80);
@@ -62,7 +250,7 @@ async fn publish_service_err_req() {
     let service_ref = service.publish().await;
     assert!(matches!(
         service_ref.unwrap_err(),
-        ZeroconfError::NullString(_)
+        ZeroconfError::InvalidServiceType(_)
     ))
 }
 
@@ -103,6 +291,27 @@ async fn resolve() -> Result<(), ZeroconfError> {
     Ok(())
 }
 
+#[cfg(feature = "bonjour")]
+#[tokio::test]
+async fn shared_connection() -> Result<(), ZeroconfError> {
+    let connection = std::sync::Arc::new(crate::backend::Connection::connect()?);
+
+    let mut service = Service::new("Server", "_http._tcp", 80);
+    let _service_ref = service.set_backend(connection.clone()).publish().await?;
+
+    let mut browser = ServiceBrowserBuilder::new("_http._tcp");
+    let mut services = browser
+        .backend(connection)
+        .timeout(tokio::time::Duration::from_secs(2))
+        .browse()?;
+
+    while let Some(Ok(v)) = services.recv().await {
+        let resolved_service = ServiceResolver::r(&v).await?;
+        println!("Service = {:?}", resolved_service);
+    }
+    Ok(())
+}
+
 /// TXT record validation
 #[test]
 fn txt_validate_key_len_ok() {
@@ -166,13 +375,16 @@ fn txt_compare_string_iter_to_iter() {
     let mut txt = TxtRecord::new();
     txt.add("key".to_string(), "value".to_string());
     txt.add("key2".to_string(), "va\0lue".to_string());
+    txt.add_flag("flag".to_string());
     // String iterator
     let s_iter = txt.iter_string();
     // Equivalent iterator
-    let iter = txt.iter().map(|(k, v)| (k, std::str::from_utf8(v)));
+    let iter = txt
+        .iter()
+        .map(|(k, v)| (k, v.map(|v| std::str::from_utf8(v)).transpose()));
     assert_eq!(
-        s_iter.collect::<Vec<(&String, Result<&str, std::str::Utf8Error>)>>(),
-        iter.collect::<Vec<(&String, Result<&str, std::str::Utf8Error>)>>()
+        s_iter.collect::<Vec<(&String, Result<Option<&str>, std::str::Utf8Error>)>>(),
+        iter.collect::<Vec<(&String, Result<Option<&str>, std::str::Utf8Error>)>>()
     )
 }
 
@@ -181,14 +393,1511 @@ fn txt_compare_string_iter_lossy_to_iter() {
     let mut txt = TxtRecord::new();
     txt.add("key".to_string(), "value".to_string());
     txt.add("key2".to_string(), "va\0lue".to_string());
+    txt.add_flag("flag".to_string());
     // String iterator
     let s_iter = txt.iter_string_lossy();
     // Equivalent iterator
     let iter = txt
         .iter()
-        .map(|(k, v)| (k, std::str::from_utf8(v).unwrap_or("�")));
+        .map(|(k, v)| (k, v.map(|v| std::str::from_utf8(v).unwrap_or("�"))));
+    assert_eq!(
+        s_iter.collect::<Vec<(&String, Option<&str>)>>(),
+        iter.collect::<Vec<(&String, Option<&str>)>>()
+    )
+}
+
+/// TXT record flags
+#[test]
+fn txt_flag() -> Result<(), ZeroconfError> {
+    let mut txt = TxtRecord::new();
+    txt.add_flag("tls".to_string());
+    txt.add("path".to_string(), "".to_string());
+    assert!(txt.has_flag("tls"));
+    assert!(!txt.has_flag("path"));
+    assert!(!txt.has_flag("other"));
+    assert_eq!(txt.to_bytes()?, b"\x03tls\x05path=");
+    assert!(format!("{}", txt).contains("\"tls\""));
+
+    // Replacing a flag with a value
+    txt.add("tls".to_string(), "1".to_string());
+    assert!(!txt.has_flag("tls"));
+    Ok(())
+}
+
+/// TXT record order and case-insensitive keys
+#[test]
+fn txt_keys() {
+    let mut txt = TxtRecord::new();
+    txt.add("txtvers".to_string(), "1".to_string());
+    txt.add("Path".to_string(), "/".to_string());
+    txt.add_flag("tls".to_string());
+    assert!(txt.contains_key("PATH"));
+    assert!(txt.has_flag("TLS"));
+    assert_eq!(txt.get("path"), Some(Some(&b"/".to_vec())));
+
+    // Replacing an entry keeps its position
+    txt.add("TXTVERS".to_string(), "2".to_string());
+    let keys: Vec<&String> = txt.iter().map(|(k, _)| k).collect();
+    assert_eq!(keys, ["TXTVERS", "Path", "tls"]);
+    assert_eq!(txt.get("txtvers"), Some(Some(&b"2".to_vec())));
+
+    assert_eq!(txt.remove("path"), Some(Some(b"/".to_vec())));
+    assert_eq!(txt.remove("path"), None);
+    assert!(!txt.contains_key("Path"));
+    assert_eq!(txt.remove("tls"), Some(None));
+}
+
+#[test]
+fn txt_eq() {
+    let mut txt = TxtRecord::new();
+    txt.add("Path".to_string(), "/".to_string());
+    txt.add_flag("tls".to_string());
+
+    // Keys are compared ignoring case, but values and order must match
+    let mut other = TxtRecord::new();
+    other.add("path".to_string(), "/".to_string());
+    other.add_flag("TLS".to_string());
+    assert_eq!(txt, other);
+
+    other.add("path".to_string(), "/index.html".to_string());
+    assert_ne!(txt, other);
+
+    let mut reordered = TxtRecord::new();
+    reordered.add_flag("tls".to_string());
+    reordered.add("Path".to_string(), "/".to_string());
+    assert_ne!(txt, reordered);
+}
+
+/// TXT record wire format
+#[test]
+fn txt_to_bytes() -> Result<(), ZeroconfError> {
+    let mut txt = TxtRecord::new();
+    txt.add("b".to_string(), "2".to_string());
+    txt.add_vec("a".to_string(), vec![0, 1]);
+    txt.add("c".to_string(), "".to_string());
+    assert_eq!(txt.to_bytes()?, b"\x03b=2\x04a=\x00\x01\x02c=");
+    assert_eq!(TxtRecord::new().to_bytes()?, [0]);
+    Ok(())
+}
+
+#[test]
+fn txt_to_bytes_err() {
+    let mut txt = TxtRecord::new();
+    txt.add_vec("k".to_string(), vec![0x20; 254]);
+    assert!(matches!(
+        txt.to_bytes(),
+        Err(ZeroconfError::InvalidTxtRecord(_))
+    ));
+
+    let mut txt = TxtRecord::new();
+    txt.add("".to_string(), "v".to_string());
+    assert!(txt.to_bytes().is_err());
+}
+
+#[test]
+fn txt_from_bytes() -> Result<(), ZeroconfError> {
+    let txt = TxtRecord::from_bytes(b"\x00\x03k=1\x03K=2\x02=v\x04flag\x05v=a=b")?;
+    let mut expected = TxtRecord::new();
+    expected.add("k".to_string(), "1".to_string());
+    expected.add_flag("flag".to_string());
+    expected.add("v".to_string(), "a=b".to_string());
+    assert_eq!(txt, expected);
+
+    assert!(TxtRecord::from_bytes(b"")?.is_empty());
+    assert!(TxtRecord::from_bytes(b"\x00")?.is_empty());
+    assert!(matches!(
+        TxtRecord::from_bytes(b"\x03k=1\x05k=1"),
+        Err(ZeroconfError::InvalidTxtRecord(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn txt_from_bytes_lossy() {
+    // A received record keeps the entries before a truncated string
+    let txt = TxtRecord::from_bytes_lossy(b"\x03k=1\x03tls\x09path=/in");
+    let mut expected = TxtRecord::new();
+    expected.add("k".to_string(), "1".to_string());
+    expected.add_flag("tls".to_string());
+    assert_eq!(txt, expected);
+
+    assert!(TxtRecord::from_bytes_lossy(b"\x05k").is_empty());
+}
+
+#[test]
+fn txt_bytes_round_trip() -> Result<(), ZeroconfError> {
+    let mut txt = TxtRecord::new();
+    txt.add("key".to_string(), "value".to_string());
+    txt.add("key2".to_string(), "va\0lue".to_string());
+    txt.add_vec("bin".to_string(), (0..200).collect());
+    txt.add("empty".to_string(), "".to_string());
+    txt.add_flag("flag".to_string());
+    assert_eq!(TxtRecord::from_bytes(&txt.to_bytes()?)?, txt);
+    Ok(())
+}
+
+/// Native backend
+#[cfg(feature = "native")]
+fn native_backend() -> std::sync::Arc<dyn crate::backend::Backend> {
+    std::sync::Arc::new(crate::backend::NativeBackend::new())
+}
+
+#[cfg(feature = "native")]
+#[tokio::test]
+async fn native_publish_browse_resolve() -> Result<(), ZeroconfError> {
+    let mut service = Service::new("Native Server", "_asynczc-nat._tcp", 8080);
+    let _service_ref = service
+        .set_backend(native_backend())
+        .add_txt("k".to_string(), "v".to_string())
+        .add_txt_flag("tls".to_string())
+        .publish()
+        .await?;
+
+    let mut browser = ServiceBrowserBuilder::new("_asynczc-nat._tcp");
+    let mut services = browser
+        .backend(native_backend())
+        .timeout(tokio::time::Duration::from_secs(5))
+        .browse()?;
+
+    let found = services.recv().await.expect("service not found")?;
+    assert_eq!(found.name(), "Native Server");
+
+    let resolved = ServiceResolver::new_with_timeout(tokio::time::Duration::from_secs(5))
+        .resolve(&found)
+        .await?;
+    assert_eq!(resolved.port(), 8080);
+    assert_eq!(resolved.txt(), service.txt());
+
+    let addresses = crate::AddrResolver::new_with_timeout(tokio::time::Duration::from_secs(5))
+        .addresses(&resolved)
+        .await?;
+    assert!(!addresses.is_empty());
+    Ok(())
+}
+
+#[cfg(feature = "native")]
+#[tokio::test]
+async fn native_registered_name() -> Result<(), ZeroconfError> {
+    let mut service = Service::new("Rename", "_asynczc-ren._tcp", 80);
+    let service_ref = service.set_backend(native_backend()).publish().await?;
+    assert_eq!(service_ref.registered_name().as_deref(), Some("Rename"));
+
+    let mut duplicate = Service::new("Rename", "_asynczc-ren._tcp", 81);
+    let duplicate_ref = duplicate.set_backend(native_backend()).publish().await?;
+    assert_eq!(
+        duplicate_ref.registered_name().as_deref(),
+        Some("Rename (2)")
+    );
+    assert_eq!(duplicate_ref.registered_domain().as_deref(), Some("local."));
+    Ok(())
+}
+
+#[cfg(feature = "native")]
+#[tokio::test]
+async fn native_update_txt() -> Result<(), ZeroconfError> {
+    let mut service = Service::new("Update Server", "_asynczc-upd._tcp", 80);
+    let service_ref = service
+        .set_backend(native_backend())
+        .add_txt("status".to_string(), "starting".to_string())
+        .publish()
+        .await?;
+
+    let mut txt = TxtRecord::new();
+    txt.add("status".to_string(), "ready".to_string());
+    txt.add("path".to_string(), "".to_string());
+    txt.add_flag("tls".to_string());
+    service_ref.update_txt(txt.clone()).await?;
+
+    let mut browser = ServiceBrowserBuilder::new("_asynczc-upd._tcp");
+    let mut services = browser
+        .backend(native_backend())
+        .timeout(tokio::time::Duration::from_secs(5))
+        .browse()?;
+
+    let found = services.recv().await.expect("service not found")?;
+    let resolved = ServiceResolver::new_with_timeout(tokio::time::Duration::from_secs(5))
+        .resolve(&found)
+        .await?;
+    assert_eq!(resolved.txt(), &txt);
+    Ok(())
+}
+
+#[cfg(feature = "native")]
+#[tokio::test]
+async fn native_subtype() -> Result<(), ZeroconfError> {
+    let mut service = Service::new("Subtype Server", "_asynczc-sub._tcp", 80);
+    let _service_ref = service
+        .set_backend(native_backend())
+        .add_subtype("_printer")
+        .publish()
+        .await?;
+
+    let mut browser = ServiceBrowserBuilder::new("_asynczc-sub._tcp");
+    let mut services = browser
+        .backend(native_backend())
+        .subtype("_printer")
+        .timeout(tokio::time::Duration::from_secs(5))
+        .browse()?;
+
+    let found = services.recv().await.expect("service not found")?;
+    assert_eq!(found.name(), "Subtype Server");
+    assert_eq!(found.service_type(), "_asynczc-sub._tcp.");
+    Ok(())
+}
+
+#[cfg(feature = "native")]
+#[tokio::test]
+async fn native_name_conflict() -> Result<(), ZeroconfError> {
+    let mut service = Service::new("Conflict", "_asynczc-con._tcp", 80);
+    let _service_ref = service.set_backend(native_backend()).publish().await?;
+
+    let mut duplicate = Service::new("Conflict", "_asynczc-con._tcp", 81);
+    let result = duplicate
+        .set_backend(native_backend())
+        .prevent_rename()
+        .publish()
+        .await;
+    assert!(matches!(
+        result.unwrap_err(),
+        ZeroconfError::Bonjour(crate::BonjourError::NameConflict)
+    ));
+    Ok(())
+}
+
+#[cfg(feature = "native")]
+#[tokio::test]
+async fn native_simultaneous_probe() -> Result<(), ZeroconfError> {
+    // Both services probe for the name at the same time, and the one with the
+    // greater SRV record wins the tie-break, so keeps the name
+    let mut first = Service::new("Tie", "_asynczc-tie._tcp", 80);
+    let mut second = Service::new("Tie", "_asynczc-tie._tcp", 81);
+    first.set_backend(native_backend());
+    second.set_backend(native_backend());
+    let (first_ref, second_ref) = tokio::join!(first.publish(), second.publish());
+
+    assert_eq!(first_ref?.registered_name().as_deref(), Some("Tie (2)"));
+    assert_eq!(second_ref?.registered_name().as_deref(), Some("Tie"));
+    Ok(())
+}
+
+#[cfg(feature = "native")]
+#[tokio::test]
+async fn native_name_too_long() {
+    let mut service = Service::new(&"é".repeat(32), "_asynczc-long._tcp", 80);
+    let result = service.set_backend(native_backend()).publish().await;
+    assert!(matches!(
+        result.unwrap_err(),
+        ZeroconfError::Bonjour(crate::BonjourError::BadParam)
+    ));
+}
+
+#[cfg(feature = "native")]
+#[tokio::test]
+async fn native_enumerate_domains() -> Result<(), ZeroconfError> {
+    let mut builder = crate::DomainBrowserBuilder::new(crate::DomainKind::Registration);
+    let mut domains = builder.backend(native_backend()).browse()?;
+    let event = domains.recv().await.expect("domain not found")?;
     assert_eq!(
-        s_iter.collect::<Vec<(&String, &str)>>(),
-        iter.collect::<Vec<(&String, &str)>>()
+        event,
+        crate::DomainEvent::Added(crate::Domain::new("local.", true))
+    );
+    Ok(())
+}
+
+#[cfg(feature = "native")]
+#[tokio::test]
+async fn native_query_record() -> Result<(), ZeroconfError> {
+    use crate::{RecordData, RecordEvent, RecordQuery, RecordType};
+
+    let _service_ref = Service::new("Record Server", "_asynczc-rec._tcp", 8080)
+        .set_backend(native_backend())
+        .add_txt("k".to_string(), "v".to_string())
+        .publish()
+        .await?;
+
+    let mut records = RecordQuery::new(
+        "Record Server._asynczc-rec._tcp.local.",
+        RecordType::Txt,
+        RecordQuery::CLASS_IN,
+    )
+    .set_backend(native_backend())
+    .set_timeout(tokio::time::Duration::from_secs(5))
+    .query()?;
+
+    let event = records.recv().await.expect("record not found")?;
+    let mut txt = TxtRecord::new();
+    txt.add("k".to_string(), "v".to_string());
+    match event {
+        RecordEvent::Added(r) => assert_eq!(r.data(), &RecordData::Txt(txt)),
+        RecordEvent::Removed(r) => panic!("unexpected removal of {}", r),
+    }
+    Ok(())
+}
+
+#[cfg(feature = "native")]
+#[tokio::test]
+async fn native_query_record_escaped() -> Result<(), ZeroconfError> {
+    use crate::{RecordData, RecordEvent, RecordQuery, RecordType, ServiceInstanceName};
+
+    let _service_ref = Service::new("Floor 2.1 Printer", "_asynczc-esc._tcp", 631)
+        .set_backend(native_backend())
+        .add_txt("k".to_string(), "v".to_string())
+        .publish()
+        .await?;
+
+    // The full name is escaped, as `Floor\0322\.1\032Printer._asynczc-esc._tcp.local.`
+    let fullname = ServiceInstanceName::new("Floor 2.1 Printer", "_asynczc-esc._tcp", "local");
+    let mut records = RecordQuery::new(
+        &fullname.to_string(),
+        RecordType::Txt,
+        RecordQuery::CLASS_IN,
+    )
+    .set_backend(native_backend())
+    .set_timeout(tokio::time::Duration::from_secs(5))
+    .query()?;
+
+    let event = records.recv().await.expect("record not found")?;
+    let mut txt = TxtRecord::new();
+    txt.add("k".to_string(), "v".to_string());
+    match event {
+        RecordEvent::Added(r) => assert_eq!(r.data(), &RecordData::Txt(txt)),
+        RecordEvent::Removed(r) => panic!("unexpected removal of {}", r),
+    }
+    Ok(())
+}
+
+#[cfg(feature = "native")]
+#[tokio::test]
+async fn native_add_record() -> Result<(), ZeroconfError> {
+    use crate::{RecordData, RecordEvent, RecordQuery, RecordType};
+
+    let service_ref = Service::new("Extra Server", "_asynczc-ext._tcp", 8080)
+        .set_backend(native_backend())
+        .publish()
+        .await?;
+    let _record_ref = service_ref
+        .add_record(RecordType::Other(10), &[1, 2, 3], 0)
+        .await?;
+
+    let mut records = RecordQuery::new(
+        "Extra Server._asynczc-ext._tcp.local.",
+        RecordType::Other(10),
+        RecordQuery::CLASS_IN,
     )
+    .set_backend(native_backend())
+    .set_timeout(tokio::time::Duration::from_secs(5))
+    .query()?;
+
+    let event = records.recv().await.expect("record not found")?;
+    match event {
+        RecordEvent::Added(r) => assert_eq!(r.data(), &RecordData::Other(10, vec![1, 2, 3])),
+        RecordEvent::Removed(r) => panic!("unexpected removal of {}", r),
+    }
+    Ok(())
+}
+
+#[cfg(feature = "native")]
+#[tokio::test]
+async fn native_record_registrar() -> Result<(), ZeroconfError> {
+    use crate::{RecordData, RecordEvent, RecordQuery, RecordType};
+
+    let registrar = crate::RecordRegistrarBuilder::new()
+        .backend(native_backend())
+        .connect()?;
+    let _record_ref = registrar
+        .register_address("asynczc-proxy.local.", "192.168.0.20".parse().unwrap())
+        .await?;
+
+    let mut records =
+        RecordQuery::new("asynczc-proxy.local.", RecordType::A, RecordQuery::CLASS_IN)
+            .set_backend(native_backend())
+            .set_timeout(tokio::time::Duration::from_secs(5))
+            .query()?;
+
+    let event = records.recv().await.expect("record not found")?;
+    match event {
+        RecordEvent::Added(r) => {
+            assert_eq!(r.data(), &RecordData::A("192.168.0.20".parse().unwrap()))
+        }
+        RecordEvent::Removed(r) => panic!("unexpected removal of {}", r),
+    }
+    Ok(())
+}
+
+#[cfg(feature = "native")]
+#[tokio::test]
+async fn native_record_registrar_escaped() -> Result<(), ZeroconfError> {
+    use crate::{RecordData, RecordEvent, RecordQuery, RecordType, ResourceRecord};
+
+    // The dot in the first label is escaped, so the name has three labels
+    let name = "asynczc\\032proxy\\.2.local.";
+    let registrar = crate::RecordRegistrarBuilder::new()
+        .backend(native_backend())
+        .connect()?;
+    let record = ResourceRecord::new(
+        name,
+        RecordQuery::CLASS_IN,
+        None,
+        RecordData::A("192.168.0.21".parse().unwrap()),
+    );
+    let _record_ref = registrar.register(&record, true).await?;
+
+    let mut records = RecordQuery::new(name, RecordType::A, RecordQuery::CLASS_IN)
+        .set_backend(native_backend())
+        .set_timeout(tokio::time::Duration::from_secs(5))
+        .query()?;
+
+    let event = records.recv().await.expect("record not found")?;
+    match event {
+        RecordEvent::Added(r) => {
+            assert_eq!(r.data(), &RecordData::A("192.168.0.21".parse().unwrap()))
+        }
+        RecordEvent::Removed(r) => panic!("unexpected removal of {}", r),
+    }
+
+    let record = ResourceRecord::new(
+        "bad\\25.local.",
+        RecordQuery::CLASS_IN,
+        None,
+        record.data().clone(),
+    );
+    assert!(matches!(
+        registrar.register(&record, true).await.unwrap_err(),
+        ZeroconfError::Bonjour(crate::BonjourError::BadParam)
+    ));
+    Ok(())
+}
+
+#[cfg(feature = "native")]
+#[tokio::test]
+async fn native_service_types() -> Result<(), ZeroconfError> {
+    let _service_ref = Service::new("Type Server", "_asynczc-typ._tcp", 80)
+        .set_backend(native_backend())
+        .publish()
+        .await?;
+
+    let mut builder = crate::ServiceTypeBrowserBuilder::new();
+    let mut types = builder
+        .backend(native_backend())
+        .timeout(tokio::time::Duration::from_secs(5))
+        .browse()?;
+
+    let expected: crate::ServiceType = "_asynczc-typ._tcp".parse()?;
+    while let Some(event) = types.recv().await {
+        if event? == crate::ServiceTypeEvent::Added(expected.clone()) {
+            return Ok(());
+        }
+    }
+    panic!("service type not found");
+}
+
+/// Avahi backend, using the bus at `ASYNC_ZEROCONF_AVAHI_BUS` if set, such as
+/// a private `dbus-daemon` with its own `avahi-daemon`, or the system bus.
+/// Tests are skipped if `avahi-daemon` can't be reached.
+#[cfg(feature = "avahi")]
+async fn avahi_backend() -> Option<std::sync::Arc<dyn crate::backend::Backend>> {
+    let backend = match std::env::var("ASYNC_ZEROCONF_AVAHI_BUS") {
+        Ok(address) => crate::backend::AvahiBackend::with_address(&address),
+        Err(_) => crate::backend::AvahiBackend::new(),
+    };
+    if backend.is_available().await {
+        Some(std::sync::Arc::new(backend))
+    } else {
+        eprintln!("avahi-daemon not available, skipping test");
+        None
+    }
+}
+
+#[cfg(feature = "avahi")]
+#[tokio::test]
+async fn avahi_publish_browse_resolve() -> Result<(), ZeroconfError> {
+    let backend = match avahi_backend().await {
+        Some(b) => b,
+        None => return Ok(()),
+    };
+
+    let mut service = Service::new("Avahi Server", "_asynczc-avh._tcp", 8080);
+    let _service_ref = service
+        .set_backend(backend.clone())
+        .add_txt("k".to_string(), "v".to_string())
+        .add_txt_flag("tls".to_string())
+        .publish()
+        .await?;
+
+    let mut browser = ServiceBrowserBuilder::new("_asynczc-avh._tcp");
+    let mut services = browser
+        .backend(backend)
+        .timeout(tokio::time::Duration::from_secs(5))
+        .browse()?;
+
+    let found = services.recv().await.expect("service not found")?;
+    assert_eq!(found.name(), "Avahi Server");
+
+    let resolved = ServiceResolver::new_with_timeout(tokio::time::Duration::from_secs(5))
+        .resolve(&found)
+        .await?;
+    assert_eq!(resolved.port(), 8080);
+    assert_eq!(resolved.txt(), service.txt());
+    Ok(())
+}
+
+#[cfg(feature = "avahi")]
+#[tokio::test]
+async fn avahi_name_conflict() -> Result<(), ZeroconfError> {
+    let backend = match avahi_backend().await {
+        Some(b) => b,
+        None => return Ok(()),
+    };
+
+    let mut service = Service::new("Avahi Conflict", "_asynczc-avc._tcp", 80);
+    let service_ref = service.set_backend(backend.clone()).publish().await?;
+    assert_eq!(
+        service_ref.registered_name().as_deref(),
+        Some("Avahi Conflict")
+    );
+
+    let mut duplicate = Service::new("Avahi Conflict", "_asynczc-avc._tcp", 81);
+    let result = duplicate
+        .set_backend(backend)
+        .prevent_rename()
+        .publish()
+        .await;
+    assert!(matches!(
+        result.unwrap_err(),
+        ZeroconfError::Bonjour(crate::BonjourError::NameConflict)
+    ));
+    Ok(())
+}
+
+#[cfg(feature = "avahi")]
+#[tokio::test]
+async fn avahi_add_record() -> Result<(), ZeroconfError> {
+    use crate::{RecordData, RecordEvent, RecordQuery, RecordType};
+
+    let backend = match avahi_backend().await {
+        Some(b) => b,
+        None => return Ok(()),
+    };
+
+    let service_ref = Service::new("Avahi Extra", "_asynczc-avx._tcp", 8080)
+        .set_backend(backend.clone())
+        .publish()
+        .await?;
+    let mut events = service_ref.events();
+    let _record_ref = service_ref
+        .add_record(RecordType::Other(10), &[1, 2, 3], 0)
+        .await?;
+
+    let mut records = RecordQuery::new(
+        "Avahi\\032Extra._asynczc-avx._tcp.local.",
+        RecordType::Other(10),
+        RecordQuery::CLASS_IN,
+    )
+    .set_backend(backend)
+    .set_timeout(tokio::time::Duration::from_secs(5))
+    .query()?;
+
+    let event = records.recv().await.expect("record not found")?;
+    match event {
+        RecordEvent::Added(r) => assert_eq!(r.data(), &RecordData::Other(10, vec![1, 2, 3])),
+        RecordEvent::Removed(r) => panic!("unexpected removal of {}", r),
+    }
+
+    // Adding a record doesn't withdraw and register the service again
+    let timeout = tokio::time::Duration::from_millis(500);
+    assert!(tokio::time::timeout(timeout, events.recv()).await.is_err());
+    Ok(())
+}
+
+/// Mock backend
+fn mock_backend(
+    network: &crate::backend::MockNetwork,
+) -> std::sync::Arc<dyn crate::backend::Backend> {
+    std::sync::Arc::new(crate::backend::MockBackend::new(network))
+}
+
+#[tokio::test]
+async fn mock_publish_browse_resolve() -> Result<(), ZeroconfError> {
+    let network = crate::backend::MockNetwork::new();
+    let mut service = Service::new("Mock Server", "_http._tcp", 8080);
+    let _service_ref = service
+        .set_backend(mock_backend(&network))
+        .add_txt("k".to_string(), "v".to_string())
+        .add_txt_flag("tls".to_string())
+        .publish()
+        .await?;
+
+    let mut browser = ServiceBrowserBuilder::new("_http._tcp");
+    let mut services = browser
+        .backend(mock_backend(&network))
+        .timeout(tokio::time::Duration::from_secs(1))
+        .browse()?;
+
+    let found = services.recv().await.expect("service not found")?;
+    assert_eq!(found.name(), "Mock Server");
+
+    let resolved = ServiceResolver::r(&found).await?;
+    assert_eq!(resolved.port(), 8080);
+    assert_eq!(resolved.host().as_deref(), Some("mock"));
+    assert_eq!(resolved.txt(), service.txt());
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_resolve_escaped_name() -> Result<(), ZeroconfError> {
+    let network = crate::backend::MockNetwork::new();
+    let mut service = Service::new("Floor 2.1 Printer", "_ipp._tcp", 631);
+    let _service_ref = service
+        .set_backend(mock_backend(&network))
+        .set_host("printer.example.com.".to_string())
+        .publish()
+        .await?;
+
+    let mut browser = ServiceBrowserBuilder::new("_ipp._tcp");
+    let mut services = browser.backend(mock_backend(&network)).browse()?;
+
+    let found = services.recv().await.expect("service not found")?;
+    let resolved = ServiceResolver::r(&found).await?;
+    assert_eq!(resolved.name(), "Floor 2.1 Printer");
+    assert_eq!(resolved.domain().as_deref(), Some("local."));
+    // The host is not in the domain of the service, so is fully qualified
+    assert_eq!(resolved.host().as_deref(), Some("printer.example.com."));
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_browse_before_publish() -> Result<(), ZeroconfError> {
+    let network = crate::backend::MockNetwork::new();
+    let mut browser = ServiceBrowserBuilder::new("_http._tcp");
+    let mut services = browser
+        .backend(mock_backend(&network))
+        .timeout(tokio::time::Duration::from_secs(1))
+        .browse()?;
+
+    let _service_ref = Service::new("Later", "_http._tcp", 80)
+        .set_backend(mock_backend(&network))
+        .publish()
+        .await?;
+
+    let found = services.recv().await.expect("service not found")?;
+    assert_eq!(found.name(), "Later");
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_name_conflict() -> Result<(), ZeroconfError> {
+    let network = crate::backend::MockNetwork::new();
+    network.add_conflict("Taken", "_http._tcp");
+
+    let _service_ref = Service::new("Taken", "_http._tcp", 80)
+        .set_backend(mock_backend(&network))
+        .publish()
+        .await?;
+    assert_eq!(network.services()[0].name(), "Taken (2)");
+
+    let result = Service::new("Taken", "_http._tcp", 80)
+        .set_backend(mock_backend(&network))
+        .prevent_rename()
+        .publish()
+        .await;
+    assert!(matches!(
+        result.unwrap_err(),
+        ZeroconfError::Bonjour(crate::BonjourError::NameConflict)
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_registered_name() -> Result<(), ZeroconfError> {
+    let network = crate::backend::MockNetwork::new();
+    network.add_conflict("Taken", "_http._tcp");
+
+    let service_ref = Service::new("Taken", "_http._tcp", 80)
+        .set_backend(mock_backend(&network))
+        .publish()
+        .await?;
+    assert_eq!(service_ref.registered_name().as_deref(), Some("Taken (2)"));
+    assert_eq!(service_ref.registered_domain().as_deref(), Some("local."));
+    let registration = service_ref.registration().unwrap();
+    assert_eq!(registration.service_type(), "_http._tcp.");
+
+    // No further changes, so the stream ends once the service is removed
+    let mut renames = service_ref.renames();
+    drop(service_ref);
+    assert!(renames.recv().await.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn registration_events() {
+    use crate::service_ref::RegistrationState;
+    use crate::{BonjourError, RegisteredService, RegistrationEvent};
+
+    let state = RegistrationState::default();
+    let mut events = state.subscribe();
+    let first = RegisteredService::new("Server", "_http._tcp.", "local.");
+    let renamed = RegisteredService::new("Server (2)", "_http._tcp.", "local.");
+
+    state.update(Ok(first.clone()));
+    state.update(Ok(first.clone()));
+    state.update(Ok(renamed.clone()));
+    state.update(Err(BonjourError::NameConflict));
+    state.update(Err(BonjourError::Unknown));
+    drop(state);
+
+    assert_eq!(
+        events.recv().await,
+        Some(RegistrationEvent::Registered(first.clone()))
+    );
+    assert_eq!(
+        events.recv().await,
+        Some(RegistrationEvent::Registered(first))
+    );
+    assert_eq!(
+        events.recv().await,
+        Some(RegistrationEvent::Renamed(renamed))
+    );
+    assert_eq!(events.recv().await, Some(RegistrationEvent::Conflict));
+    assert_eq!(
+        events.recv().await,
+        Some(RegistrationEvent::Error(BonjourError::Unknown))
+    );
+    assert_eq!(events.recv().await, None);
+}
+
+#[tokio::test]
+async fn mock_registration_events() -> Result<(), ZeroconfError> {
+    let network = crate::backend::MockNetwork::new();
+    let service_ref = Service::new("Server", "_http._tcp", 80)
+        .set_backend(mock_backend(&network))
+        .publish()
+        .await?;
+
+    // Already registered, so the stream ends once the service is removed
+    let mut events = service_ref.events();
+    drop(service_ref);
+    assert!(events.recv().await.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_unpublish() -> Result<(), ZeroconfError> {
+    let network = crate::backend::MockNetwork::new();
+    let service_ref = Service::new("Server", "_http._tcp", 80)
+        .set_backend(mock_backend(&network))
+        .publish()
+        .await?;
+    assert_eq!(network.services().len(), 1);
+
+    drop(service_ref);
+    tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+    assert!(network.services().is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_browse_removed() -> Result<(), ZeroconfError> {
+    let network = crate::backend::MockNetwork::new();
+    let service_ref = Service::new("Server", "_http._tcp", 80)
+        .set_backend(mock_backend(&network))
+        .publish()
+        .await?;
+
+    let mut browser = ServiceBrowserBuilder::new("_http._tcp");
+    let mut services = browser.backend(mock_backend(&network)).browse()?;
+
+    let added = services.recv_event().await.unwrap()?;
+    assert!(matches!(added, crate::BrowseEvent::Added(_)));
+    assert_eq!(added.service().name(), "Server");
+
+    drop(service_ref);
+    let removed = services.recv_event().await.unwrap()?;
+    assert!(matches!(removed, crate::BrowseEvent::Removed(_)));
+    assert_eq!(removed.service().name(), "Server");
+    assert!(removed.service().browse());
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_directory() -> Result<(), ZeroconfError> {
+    let network = crate::backend::MockNetwork::new();
+    let mut browser = ServiceBrowserBuilder::new("_http._tcp");
+    let services = browser.backend(mock_backend(&network)).browse()?;
+    let directory = crate::ServiceDirectory::new(services);
+    let mut events = directory.events();
+
+    // The same service on two interfaces has a single entry
+    let mut refs = Vec::new();
+    for index in 1..=2 {
+        let service_ref = Service::new("Server", "_http._tcp", 80)
+            .set_interface(Interface::Interface(index))
+            .set_backend(mock_backend(&network))
+            .publish()
+            .await?;
+        refs.push(service_ref);
+    }
+
+    let mut entry = match events.recv().await {
+        Some(crate::DirectoryEvent::Added(e)) => e,
+        e => panic!("Unexpected event {:?}", e),
+    };
+    while entry.interfaces().len() < 2 || entry.resolved().is_none() {
+        entry = match events.recv().await {
+            Some(crate::DirectoryEvent::Updated(e)) => e,
+            e => panic!("Unexpected event {:?}", e),
+        };
+    }
+    assert_eq!(entry.key().name(), "Server");
+    assert_eq!(entry.resolved().unwrap().port(), 80);
+    assert_eq!(directory.snapshot().len(), 1);
+    assert_eq!(directory.get(entry.key()), Some(entry.clone()));
+
+    // Only removed once gone from every interface
+    refs.pop();
+    match events.recv().await {
+        Some(crate::DirectoryEvent::Updated(e)) => assert_eq!(e.interfaces().len(), 1),
+        e => panic!("Unexpected event {:?}", e),
+    }
+    refs.pop();
+    loop {
+        match events.recv().await {
+            Some(crate::DirectoryEvent::Updated(_)) => (),
+            Some(crate::DirectoryEvent::Removed(e)) => {
+                assert_eq!(e.key(), entry.key());
+                break;
+            }
+            e => panic!("Unexpected event {:?}", e),
+        }
+    }
+    assert!(directory.snapshot().is_empty());
+    assert!(directory.watch().borrow().is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_update_txt() -> Result<(), ZeroconfError> {
+    let network = crate::backend::MockNetwork::new();
+    let service_ref = Service::new("Server", "_http._tcp", 80)
+        .set_backend(mock_backend(&network))
+        .add_txt("status".to_string(), "starting".to_string())
+        .publish()
+        .await?;
+
+    let mut txt = TxtRecord::new();
+    txt.add("status".to_string(), "ready".to_string());
+    service_ref.update_txt(txt.clone()).await?;
+    assert_eq!(network.services()[0].txt(), &txt);
+
+    let mut invalid = TxtRecord::new();
+    invalid.add("k=".to_string(), "v".to_string());
+    assert!(matches!(
+        service_ref.update_txt(invalid).await,
+        Err(ZeroconfError::InvalidTxtRecord(_))
+    ));
+    assert_eq!(network.services()[0].txt(), &txt);
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_subtype() -> Result<(), ZeroconfError> {
+    let network = crate::backend::MockNetwork::new();
+    let _plain_ref = Service::new("Plain", "_http._tcp", 80)
+        .set_backend(mock_backend(&network))
+        .publish()
+        .await?;
+    let _printer_ref = Service::new("Printer", "_http._tcp", 81)
+        .set_backend(mock_backend(&network))
+        .add_subtype("_printer")
+        .publish()
+        .await?;
+
+    let mut browser = ServiceBrowserBuilder::new("_http._tcp");
+    let mut services = browser
+        .backend(mock_backend(&network))
+        .subtype("_printer")
+        .timeout(tokio::time::Duration::from_millis(100))
+        .browse()?;
+
+    let mut found = Vec::new();
+    while let Some(service) = services.recv().await {
+        found.push(service?.name().to_string());
+    }
+    assert_eq!(found, vec!["Printer"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_browse_nonconforming_type() -> Result<(), ZeroconfError> {
+    let network = crate::backend::MockNetwork::new();
+    // Types that don't follow RFC 6335 can't be published but can be browsed
+    for s in ["_nvstream_dbd._tcp", "_sixteencharslong._tcp"] {
+        let result = Service::new("Server", s, 80)
+            .set_backend(mock_backend(&network))
+            .publish()
+            .await;
+        assert!(matches!(result, Err(ZeroconfError::InvalidServiceType(_))));
+
+        let mut browser = ServiceBrowserBuilder::new(s);
+        let mut services = browser
+            .backend(mock_backend(&network))
+            .timeout(tokio::time::Duration::from_millis(100))
+            .browse()?;
+        assert!(services.recv().await.is_none());
+    }
+
+    let result = ServiceBrowserBuilder::new("_a._tcp.garbage")
+        .backend(mock_backend(&network))
+        .browse();
+    assert!(matches!(result, Err(ZeroconfError::InvalidServiceType(_))));
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_enumerate_domains() -> Result<(), ZeroconfError> {
+    let network = crate::backend::MockNetwork::new();
+    network.add_domain("example.com", crate::DomainKind::Browse);
+    network.add_domain("register.example.com", crate::DomainKind::Registration);
+
+    let mut builder = crate::DomainBrowserBuilder::new(crate::DomainKind::Browse);
+    let mut domains = builder
+        .backend(mock_backend(&network))
+        .timeout(tokio::time::Duration::from_millis(100))
+        .browse()?;
+
+    let mut found = Vec::new();
+    while let Some(event) = domains.recv().await {
+        match event? {
+            crate::DomainEvent::Added(d) => found.push(d),
+            crate::DomainEvent::Removed(d) => panic!("unexpected removal of {}", d),
+        }
+    }
+    assert_eq!(
+        found,
+        vec![
+            crate::Domain::new("local.", true),
+            crate::Domain::new("example.com.", false)
+        ]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_service_types() -> Result<(), ZeroconfError> {
+    let network = crate::backend::MockNetwork::new();
+    let _first_ref = Service::new("First", "_http._tcp", 80)
+        .set_backend(mock_backend(&network))
+        .publish()
+        .await?;
+    let second_ref = Service::new("Second", "_http._tcp", 81)
+        .set_backend(mock_backend(&network))
+        .publish()
+        .await?;
+
+    let mut builder = crate::ServiceTypeBrowserBuilder::new();
+    let mut types = builder.backend(mock_backend(&network)).browse()?;
+    let http: crate::ServiceType = "_http._tcp".parse()?;
+    assert_eq!(
+        types.recv().await.unwrap()?,
+        crate::ServiceTypeEvent::Added(http.clone())
+    );
+
+    // Types are only removed once there are no services of that type
+    drop(second_ref);
+    let ipp_ref = Service::new("Printer", "_ipp._tcp", 631)
+        .set_backend(mock_backend(&network))
+        .publish()
+        .await?;
+    let ipp: crate::ServiceType = "_ipp._tcp".parse()?;
+    assert_eq!(
+        types.recv().await.unwrap()?,
+        crate::ServiceTypeEvent::Added(ipp.clone())
+    );
+    drop(ipp_ref);
+    assert_eq!(
+        types.recv().await.unwrap()?,
+        crate::ServiceTypeEvent::Removed(ipp)
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_service_types_nonconforming() -> Result<(), ZeroconfError> {
+    use crate::backend::PublishContext;
+
+    // Register directly with the backend, as these types can't be published
+    let network = crate::backend::MockNetwork::new();
+    let backend = mock_backend(&network);
+    let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+    for t in ["_bad\\type._tcp", "_nvstream_dbd._tcp"] {
+        let context = PublishContext::new(tx.clone(), Default::default());
+        let operation = backend.register(&Service::new("Server", t, 80), context)?;
+        tokio::spawn(async move { while let Ok(true) = operation.process().await {} });
+    }
+
+    // Types that don't follow RFC 6335 are reported, and any that can't be
+    // parsed at all are skipped rather than ending the browser
+    let mut builder = crate::ServiceTypeBrowserBuilder::new();
+    let mut types = builder
+        .backend(mock_backend(&network))
+        .timeout(tokio::time::Duration::from_millis(100))
+        .browse()?;
+    let mut found = Vec::new();
+    while let Some(event) = types.recv().await {
+        found.push(event?.service_type().to_string());
+    }
+    assert_eq!(found, vec!["_nvstream_dbd._tcp"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_service_types_services() -> Result<(), ZeroconfError> {
+    use tokio_stream::StreamExt;
+
+    let network = crate::backend::MockNetwork::new();
+    let _http_ref = Service::new("Server", "_http._tcp", 80)
+        .set_backend(mock_backend(&network))
+        .publish()
+        .await?;
+    let _ipp_ref = Service::new("Printer", "_ipp._tcp", 631)
+        .set_backend(mock_backend(&network))
+        .publish()
+        .await?;
+
+    let mut builder = crate::ServiceTypeBrowserBuilder::new();
+    let types = builder
+        .backend(mock_backend(&network))
+        .timeout(tokio::time::Duration::from_millis(100))
+        .browse()?;
+
+    let mut found = Vec::new();
+    let mut services = types.services();
+    while let Some(event) = services.next().await {
+        match event? {
+            crate::BrowseEvent::Added(s) => found.push(s.name().to_string()),
+            crate::BrowseEvent::Removed(s) => panic!("unexpected removal of {}", s),
+        }
+    }
+    found.sort();
+    assert_eq!(found, vec!["Printer", "Server"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_service_types_services_readded() -> Result<(), ZeroconfError> {
+    use tokio_stream::StreamExt;
+
+    let network = crate::backend::MockNetwork::new();
+    let first_ref = Service::new("First", "_ipp._tcp", 631)
+        .set_backend(mock_backend(&network))
+        .publish()
+        .await?;
+
+    let mut builder = crate::ServiceTypeBrowserBuilder::new();
+    let types = builder
+        .backend(mock_backend(&network))
+        .timeout(tokio::time::Duration::from_secs(1))
+        .browse()?;
+    let mut services = types.services();
+
+    let mut added = Vec::new();
+    match services.next().await.expect("service not found")? {
+        crate::BrowseEvent::Added(s) => added.push(s.name().to_string()),
+        crate::BrowseEvent::Removed(s) => panic!("unexpected removal of {}", s),
+    }
+
+    // Remove the type, letting the stream handle its removal, then add it
+    // again with a different service
+    drop(first_ref);
+    let wait = tokio::time::Duration::from_millis(100);
+    while let Ok(Some(event)) = tokio::time::timeout(wait, services.next()).await {
+        if let crate::BrowseEvent::Added(s) = event? {
+            added.push(s.name().to_string());
+        }
+    }
+    let _second_ref = Service::new("Second", "_ipp._tcp", 631)
+        .set_backend(mock_backend(&network))
+        .publish()
+        .await?;
+
+    // Only the browser started when the type was found again reports it
+    while let Some(event) = services.next().await {
+        if let crate::BrowseEvent::Added(s) = event? {
+            added.push(s.name().to_string());
+        }
+    }
+    assert_eq!(added, vec!["First", "Second"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_query_record() -> Result<(), ZeroconfError> {
+    use crate::{RecordData, RecordEvent, RecordQuery, RecordType, ResourceRecord};
+
+    let network = crate::backend::MockNetwork::new();
+    let hinfo = ResourceRecord::new(
+        "mock.local.",
+        RecordQuery::CLASS_IN,
+        Some(120),
+        RecordData::Hinfo {
+            cpu: "ARM64".to_string(),
+            os: "Linux".to_string(),
+        },
+    );
+    network.add_record(hinfo.clone());
+    network.add_record(ResourceRecord::new(
+        "mock.local.",
+        RecordQuery::CLASS_IN,
+        Some(120),
+        RecordData::A("192.168.0.2".parse().unwrap()),
+    ));
+
+    let mut records = RecordQuery::new("mock.local", RecordType::Hinfo, RecordQuery::CLASS_IN)
+        .set_backend(mock_backend(&network))
+        .set_timeout(tokio::time::Duration::from_millis(100))
+        .query()?;
+
+    let mut found = Vec::new();
+    while let Some(event) = records.recv().await {
+        found.push(event?);
+    }
+    assert_eq!(found, vec![RecordEvent::Added(hinfo)]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_add_record() -> Result<(), ZeroconfError> {
+    use crate::{RecordData, RecordQuery, RecordType};
+
+    let network = crate::backend::MockNetwork::new();
+    let service_ref = Service::new("Server", "_http._tcp", 80)
+        .set_backend(mock_backend(&network))
+        .publish()
+        .await?;
+    let record_ref = service_ref
+        .add_record(RecordType::Other(10), &[1, 2, 3], 120)
+        .await?;
+    assert_eq!(record_ref.rrtype(), RecordType::Other(10));
+
+    let query = || {
+        RecordQuery::new(
+            "Server._http._tcp.local.",
+            RecordType::Other(10),
+            RecordQuery::CLASS_IN,
+        )
+        .set_backend(mock_backend(&network))
+        .set_timeout(tokio::time::Duration::from_millis(100))
+        .query()
+    };
+
+    let mut records = query()?;
+    let mut found = Vec::new();
+    while let Some(event) = records.recv().await {
+        found.push(event?.record().data().clone());
+    }
+    assert_eq!(found, vec![RecordData::Other(10, vec![1, 2, 3])]);
+
+    // Dropping the reference removes the record
+    drop(record_ref);
+    tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+    let mut records = query()?;
+    assert!(records.recv().await.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_record_registrar() -> Result<(), ZeroconfError> {
+    use crate::{RecordData, RecordQuery, RecordType, ResourceRecord};
+
+    let network = crate::backend::MockNetwork::new();
+    let registrar = crate::RecordRegistrarBuilder::new()
+        .backend(mock_backend(&network))
+        .connect()?;
+    let record_ref = registrar
+        .register_address("printer.local.", "192.168.0.20".parse().unwrap())
+        .await?;
+    assert_eq!(record_ref.rrtype(), RecordType::A);
+
+    // A unique record can't be registered with different data
+    let other = ResourceRecord::new(
+        "printer.local.",
+        RecordQuery::CLASS_IN,
+        None,
+        RecordData::A("192.168.0.21".parse().unwrap()),
+    );
+    assert!(matches!(
+        registrar.register(&other, true).await,
+        Err(ZeroconfError::Bonjour(crate::BonjourError::NameConflict))
+    ));
+    let _shared_ref = registrar.register(&other, false).await?;
+
+    let query = || {
+        RecordQuery::new("printer.local", RecordType::A, RecordQuery::CLASS_IN)
+            .set_backend(mock_backend(&network))
+            .set_timeout(tokio::time::Duration::from_millis(100))
+            .query()
+    };
+
+    let mut records = query()?;
+    let mut found = Vec::new();
+    while let Some(event) = records.recv().await {
+        found.push(event?.record().data().clone());
+    }
+    assert_eq!(
+        found,
+        vec![
+            RecordData::A("192.168.0.20".parse().unwrap()),
+            RecordData::A("192.168.0.21".parse().unwrap())
+        ]
+    );
+
+    // Dropping the registrar removes the remaining records
+    drop(record_ref);
+    drop(registrar);
+    tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+    let mut records = query()?;
+    assert!(records.recv().await.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_injected_error() -> Result<(), ZeroconfError> {
+    let network = crate::backend::MockNetwork::new();
+    network.inject_error(crate::OpKind::Browse, crate::BonjourError::Refused);
+
+    let mut browser = ServiceBrowserBuilder::new("_http._tcp");
+    let mut services = browser.backend(mock_backend(&network)).browse()?;
+    assert!(matches!(
+        services.recv().await,
+        Some(Err(ZeroconfError::Bonjour(crate::BonjourError::Refused)))
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_latency() -> Result<(), ZeroconfError> {
+    let network = crate::backend::MockNetwork::new();
+    network.set_latency(tokio::time::Duration::from_millis(100));
+
+    let start = tokio::time::Instant::now();
+    let _service_ref = Service::new("Server", "_http._tcp", 80)
+        .set_backend(mock_backend(&network))
+        .publish()
+        .await?;
+    assert!(start.elapsed() >= tokio::time::Duration::from_millis(100));
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_supervised_publish() -> Result<(), ZeroconfError> {
+    let network = crate::backend::MockNetwork::new();
+    let supervision = crate::Supervision::new(
+        tokio::time::Duration::from_millis(10),
+        tokio::time::Duration::from_millis(50),
+    );
+    let service_ref = Service::new("Supervised", "_http._tcp", 80)
+        .set_backend(mock_backend(&network))
+        .supervise(supervision)
+        .publish()
+        .await?;
+    let _unsupervised_ref = Service::new("Unsupervised", "_http._tcp", 80)
+        .set_backend(mock_backend(&network))
+        .publish()
+        .await?;
+
+    let mut txt = TxtRecord::new();
+    txt.add("status".to_string(), "ready".to_string());
+    service_ref.update_txt(txt.clone()).await?;
+
+    let mut reconnects = service_ref.reconnects();
+    let mut events = service_ref.events();
+    network.stop_daemon();
+    assert_eq!(
+        reconnects.recv().await,
+        Some(crate::ReconnectEvent::Disconnected(
+            crate::BonjourError::ServiceNotRunning
+        ))
+    );
+    assert!(network.services().is_empty());
+
+    // Attempts to publish again fail until the daemon starts
+    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+    network.start_daemon();
+    assert_eq!(
+        reconnects.recv().await,
+        Some(crate::ReconnectEvent::Reconnected)
+    );
+    assert!(matches!(
+        events.recv().await,
+        Some(crate::RegistrationEvent::Registered(_))
+    ));
+
+    let services = network.services();
+    assert_eq!(services.len(), 1);
+    assert_eq!(services[0].name(), "Supervised");
+    assert_eq!(services[0].txt(), &txt);
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_supervised_browse() -> Result<(), ZeroconfError> {
+    let network = crate::backend::MockNetwork::new();
+    let mut browser = ServiceBrowserBuilder::new("_http._tcp");
+    let mut services = browser
+        .backend(mock_backend(&network))
+        .supervise(crate::Supervision::new(
+            tokio::time::Duration::from_millis(10),
+            tokio::time::Duration::from_millis(50),
+        ))
+        .browse()?;
+    let mut reconnects = services.reconnects();
+
+    network.stop_daemon();
+    assert!(matches!(
+        reconnects.recv().await,
+        Some(crate::ReconnectEvent::Disconnected(_))
+    ));
+    network.start_daemon();
+    assert_eq!(
+        reconnects.recv().await,
+        Some(crate::ReconnectEvent::Reconnected)
+    );
+
+    let _service_ref = Service::new("Server", "_http._tcp", 80)
+        .set_backend(mock_backend(&network))
+        .publish()
+        .await?;
+    let found = services.recv().await.expect("service not found")?;
+    assert_eq!(found.name(), "Server");
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_reconfirm() -> Result<(), ZeroconfError> {
+    let network = crate::backend::MockNetwork::new();
+    let _service_ref = Service::new("My Server", "_http._tcp", 8080)
+        .set_backend(mock_backend(&network))
+        .publish()
+        .await?;
+
+    let mut browser = ServiceBrowserBuilder::new("_http._tcp");
+    let mut services = browser.backend(mock_backend(&network)).browse()?;
+    let found = services.recv().await.expect("service not found")?;
+    assert!(matches!(
+        found.reconfirm(),
+        Err(ZeroconfError::NotResolved(_))
+    ));
+
+    ServiceResolver::r(&found).await?.reconfirm()?;
+    let reconfirmed = network.reconfirmed();
+    assert_eq!(reconfirmed.len(), 2);
+    assert_eq!(reconfirmed[0].name(), "_http._tcp.local.");
+    assert_eq!(
+        reconfirmed[0].data(),
+        &crate::RecordData::Ptr("My\\032Server._http._tcp.local.".to_string())
+    );
+    assert_eq!(reconfirmed[1].name(), "My\\032Server._http._tcp.local.");
+    assert_eq!(
+        reconfirmed[1].data(),
+        &crate::RecordData::Srv {
+            priority: 0,
+            weight: 0,
+            port: 8080,
+            target: "mock.local.".to_string(),
+        }
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_addresses() -> Result<(), ZeroconfError> {
+    let network = crate::backend::MockNetwork::new();
+    let v4: std::net::IpAddr = "192.168.0.2".parse().unwrap();
+    let v6: std::net::IpAddr = "fe80::2".parse().unwrap();
+    network.set_addresses("mock.local", &[v4, v6]);
+
+    let _service_ref = Service::new("Server", "_http._tcp", 80)
+        .set_backend(mock_backend(&network))
+        .publish()
+        .await?;
+
+    let mut browser = ServiceBrowserBuilder::new("_http._tcp");
+    let mut services = browser.backend(mock_backend(&network)).browse()?;
+    let resolved = services.recv_resolve().await.expect("service not found")?;
+
+    assert_eq!(resolved.addresses().await?, vec![v4, v6]);
+    let addresses = crate::AddrResolver::new()
+        .set_protocol(crate::Protocol::IPv6)
+        .addresses(&resolved)
+        .await?;
+    assert_eq!(addresses, vec![v6]);
+
+    let mut events = crate::AddrResolver::new().events(&resolved)?;
+    assert_eq!(events.recv().await.unwrap()?, crate::AddrEvent::Added(v4));
+    assert_eq!(events.recv().await.unwrap()?, crate::AddrEvent::Added(v6));
+    network.set_addresses("mock.local", &[v6]);
+    assert_eq!(events.recv().await.unwrap()?, crate::AddrEvent::Removed(v4));
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_addresses_multi_label_host() -> Result<(), ZeroconfError> {
+    let network = crate::backend::MockNetwork::new();
+    let v4: std::net::IpAddr = "192.168.0.3".parse().unwrap();
+    network.set_addresses("printer.office.local", &[v4]);
+
+    let _service_ref = Service::new("Printer", "_ipp._tcp", 631)
+        .set_backend(mock_backend(&network))
+        .set_host("printer.office.local.".to_string())
+        .publish()
+        .await?;
+
+    let mut browser = ServiceBrowserBuilder::new("_ipp._tcp");
+    let mut services = browser.backend(mock_backend(&network)).browse()?;
+    let resolved = services.recv_resolve().await.expect("service not found")?;
+
+    // The host is relative to the domain, so the domain is added back
+    assert_eq!(resolved.host().as_deref(), Some("printer.office"));
+    let addresses = crate::AddrResolver::new_with_timeout(tokio::time::Duration::from_secs(1))
+        .addresses(&resolved)
+        .await?;
+    assert_eq!(addresses, vec![v4]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn addresses_not_resolved() {
+    let service = Service::new("Server", "_http._tcp", 80);
+    assert!(matches!(
+        service.addresses().await.unwrap_err(),
+        ZeroconfError::NotResolved(_)
+    ));
+}
+
+#[tokio::test]
+async fn mock_resolve_socket_addrs() -> Result<(), ZeroconfError> {
+    let network = crate::backend::MockNetwork::new();
+    network.set_addresses(
+        "mock.local",
+        &["192.168.0.2".parse().unwrap(), "fe80::2".parse().unwrap()],
+    );
+
+    let _service_ref = Service::new("Server", "_http._tcp", 8080)
+        .set_backend(mock_backend(&network))
+        .set_interface(Interface::Interface(3))
+        .publish()
+        .await?;
+
+    let mut browser = ServiceBrowserBuilder::new("_http._tcp");
+    let mut services = browser.backend(mock_backend(&network)).browse()?;
+    let found = services.recv().await.expect("service not found")?;
+
+    let addrs = ServiceResolver::new().resolve_socket_addrs(&found).await?;
+    assert_eq!(
+        addrs,
+        vec![
+            "192.168.0.2:8080".parse::<std::net::SocketAddr>().unwrap(),
+            "[fe80::2%3]:8080".parse::<std::net::SocketAddr>().unwrap(),
+        ]
+    );
+    Ok(())
 }
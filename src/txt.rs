@@ -1,4 +1,3 @@
-use std::collections::HashMap;
 use std::fmt;
 use std::str::Utf8Error;
 
@@ -6,6 +5,16 @@ use crate::ZeroconfError;
 
 /// Struct containing the entries for TXT records associated with a service
 ///
+/// As described in [RFC 6763 §6.4][rfc], an entry may have a value, which
+/// may be empty, or may be a flag with no value, such as `tls`, which is
+/// only identified as being present.
+///
+/// Entries are kept in the order they were added, which is the order they
+/// are published in, and keys are compared case-insensitively, so adding an
+/// entry replaces any existing entry with the same key. Two collections are
+/// equal if they have the same entries in the same order, with the keys
+/// compared case-insensitively.
+///
 /// # Examples
 /// ```
 /// # tokio_test::block_on(async {
@@ -17,30 +26,97 @@ use crate::ZeroconfError;
 /// # Ok::<(), async_zeroconf::ZeroconfError>(())
 /// # });
 /// ```
-#[derive(Debug, Clone, Eq, PartialEq)]
+/// [rfc]: https://www.rfc-editor.org/rfc/rfc6763#section-6.4
+#[derive(Debug, Clone)]
 pub struct TxtRecord {
-    records: HashMap<String, Vec<u8>>,
+    records: Vec<(String, Option<Vec<u8>>)>,
 }
 
 impl TxtRecord {
     /// Create a new TXT record collection
     pub fn new() -> Self {
         TxtRecord {
-            records: HashMap::new(),
+            records: Vec::new(),
         }
     }
 
     /// Add an entry from a string
     pub fn add(&mut self, k: String, v: String) {
-        self.records.insert(k, v.as_bytes().to_vec());
+        self.insert(k, Some(v.into_bytes()));
     }
 
     /// Add an entry from a slice of u8's
     pub fn add_vec(&mut self, k: String, v: Vec<u8>) {
-        self.records.insert(k, v);
+        self.insert(k, Some(v));
+    }
+
+    /// Add a flag, which is an entry without a value that is published as
+    /// `k` rather than `k=`.
+    ///
+    /// # Examples
+    /// ```
+    /// let mut txt = async_zeroconf::TxtRecord::new();
+    /// txt.add_flag("tls".to_string());
+    /// txt.add("path".to_string(), "".to_string());
+    /// assert!(txt.has_flag("tls"));
+    /// // An entry with an empty value is not a flag
+    /// assert!(!txt.has_flag("path"));
+    /// ```
+    pub fn add_flag(&mut self, k: String) {
+        self.insert(k, None);
+    }
+
+    /// Check if the flag `k` is present, as an entry without a value
+    pub fn has_flag(&self, k: &str) -> bool {
+        matches!(self.get(k), Some(None))
+    }
+
+    /// Get the value of the entry with the key `k`, ignoring case, where the
+    /// value of a flag is `None`.
+    ///
+    /// # Examples
+    /// ```
+    /// let mut txt = async_zeroconf::TxtRecord::new();
+    /// txt.add("Path".to_string(), "/index.html".to_string());
+    /// txt.add_flag("tls".to_string());
+    /// assert_eq!(txt.get("path"), Some(Some(&b"/index.html".to_vec())));
+    /// assert_eq!(txt.get("TLS"), Some(None));
+    /// assert_eq!(txt.get("other"), None);
+    /// ```
+    pub fn get(&self, k: &str) -> Option<Option<&Vec<u8>>> {
+        self.position(k).map(|i| self.records[i].1.as_ref())
+    }
+
+    /// Remove the entry with the key `k`, ignoring case, returning its value
+    /// if it was present.
+    pub fn remove(&mut self, k: &str) -> Option<Option<Vec<u8>>> {
+        self.position(k).map(|i| self.records.remove(i).1)
     }
 
-    /// Get Iterator
+    /// Check if there is an entry with the key `k`, ignoring case, either
+    /// with a value or as a flag.
+    pub fn contains_key(&self, k: &str) -> bool {
+        self.position(k).is_some()
+    }
+
+    // Replace the entry with the key `k` if there is one, keeping its
+    // position, otherwise add it after the existing entries
+    fn insert(&mut self, k: String, v: Option<Vec<u8>>) {
+        match self.position(&k) {
+            Some(i) => self.records[i] = (k, v),
+            None => self.records.push((k, v)),
+        }
+    }
+
+    // Get the index of the entry with the key `k`, ignoring case
+    fn position(&self, k: &str) -> Option<usize> {
+        self.records
+            .iter()
+            .position(|(key, _)| key.eq_ignore_ascii_case(k))
+    }
+
+    /// Get Iterator over the entries in the order they were added, where the
+    /// value of a flag is `None`
     ///
     /// # Examples
     /// ```
@@ -52,12 +128,13 @@ impl TxtRecord {
     ///     println!("{}, {:?}", k, v);
     /// }
     /// ```
-    pub fn iter(&self) -> impl Iterator<Item = (&String, &Vec<u8>)> {
-        self.records.iter()
+    pub fn iter(&self) -> impl Iterator<Item = (&String, Option<&Vec<u8>>)> {
+        self.records.iter().map(|(k, v)| (k, v.as_ref()))
     }
 
     /// Get Iterator including conversion to string. As the conversion to a
-    /// UTF-8 string could fail the value is returned as a `Result`.
+    /// UTF-8 string could fail the value is returned as a `Result`, where the
+    /// value of a flag is `None`.
     ///
     /// # Examples
     /// ```
@@ -67,19 +144,20 @@ impl TxtRecord {
     /// let iter = txt.iter_string();
     /// for (k, v) in iter {
     ///     match v {
-    ///         Ok(v) => println!("{}, {}", k, v),
+    ///         Ok(Some(v)) => println!("{}, {}", k, v),
+    ///         Ok(None) => println!("{}", k),
     ///         Err(_) => println!("{} not valid UTF-8", k)
     ///     }
     /// }
     /// ```
-    pub fn iter_string(&self) -> impl Iterator<Item = (&String, Result<&str, Utf8Error>)> {
-        self.records
-            .iter()
-            .map(|(k, v)| (k, std::str::from_utf8(v)))
+    pub fn iter_string(&self) -> impl Iterator<Item = (&String, Result<Option<&str>, Utf8Error>)> {
+        self.iter()
+            .map(|(k, v)| (k, v.map(|v| std::str::from_utf8(v)).transpose()))
     }
 
-    /// Get Iterator including conversion to string. If the conversion to UTF-8
-    /// fails, '�' will be returned instead.
+    /// Get Iterator including conversion to string, where the value of a flag
+    /// is `None`. If the conversion to UTF-8 fails, '�' will be returned
+    /// instead.
     ///
     /// # Examples
     /// ```
@@ -88,13 +166,12 @@ impl TxtRecord {
     /// // String iterator
     /// let iter = txt.iter_string_lossy();
     /// for (k, v) in iter {
-    ///     println!("{}, {}", k, v);
+    ///     println!("{}, {:?}", k, v);
     /// }
     /// ```
-    pub fn iter_string_lossy(&self) -> impl Iterator<Item = (&String, &str)> {
-        self.records
-            .iter()
-            .map(|(k, v)| (k, std::str::from_utf8(v).unwrap_or("�")))
+    pub fn iter_string_lossy(&self) -> impl Iterator<Item = (&String, Option<&str>)> {
+        self.iter()
+            .map(|(k, v)| (k, v.map(|v| std::str::from_utf8(v).unwrap_or("�"))))
     }
 
     /// Validate if this TXT record collection contains all valid values.
@@ -115,23 +192,171 @@ impl TxtRecord {
     pub fn validate(&self) -> Result<(), ZeroconfError> {
         for (k, v) in self.iter() {
             let all_printable_ascii = k.chars().all(|c| (0x20..=0x7E).contains(&(c as u32)));
-            if k.len() > 9 || v.len() > 255 || k.contains('=') || !all_printable_ascii {
-                return Err(ZeroconfError::InvalidTxtRecord(format!(
-                    "{}={}",
-                    k,
-                    String::from_utf8_lossy(v)
-                )));
+            let value_len = v.map_or(0, |v| v.len());
+            if k.len() > 9 || value_len > 255 || k.contains('=') || !all_printable_ascii {
+                return Err(invalid(k, v));
             }
         }
         Ok(())
     }
 
+    /// Encode the TXT record collection in the wire format described in
+    /// [RFC 6763 §6][rfc], as used for the data of a DNS `TXT` record.
+    ///
+    /// Each entry is encoded as a string of the form `key=value`, or `key`
+    /// for a flag, prefixed by its length, in the order they were added. An
+    /// empty collection is encoded as a single empty string. An error is
+    /// returned if an entry is longer than 255 bytes, or if a key is empty or
+    /// contains `=`.
+    ///
+    /// # Examples
+    /// ```
+    /// let mut txt = async_zeroconf::TxtRecord::new();
+    /// txt.add("key".to_string(), "value".to_string());
+    /// txt.add_flag("tls".to_string());
+    /// assert_eq!(txt.to_bytes()?, b"\x09key=value\x03tls");
+    /// assert_eq!(async_zeroconf::TxtRecord::new().to_bytes()?, b"\x00");
+    /// # Ok::<(), async_zeroconf::ZeroconfError>(())
+    /// ```
+    /// [rfc]: https://www.rfc-editor.org/rfc/rfc6763#section-6
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ZeroconfError> {
+        let mut bytes = Vec::new();
+        for (k, v) in self.iter() {
+            let entry = entry(k, v);
+            if k.is_empty() || k.contains('=') || entry.len() > 255 {
+                return Err(invalid(k, v));
+            }
+            bytes.push(entry.len() as u8);
+            bytes.extend_from_slice(&entry);
+        }
+        // An empty TXT record must contain a single empty string
+        if bytes.is_empty() {
+            bytes.push(0);
+        }
+        Ok(bytes)
+    }
+
+    /// Decode a TXT record collection from the wire format described in
+    /// [RFC 6763 §6][rfc], such as the data of a DNS `TXT` record.
+    ///
+    /// Empty strings and strings with an empty key are ignored, and only the
+    /// first value is used if a key appears more than once, ignoring case. A
+    /// string without an `=` is a flag. An error is returned if the length of
+    /// a string extends beyond the end of `bytes`.
+    ///
+    /// # Examples
+    /// ```
+    /// let txt = async_zeroconf::TxtRecord::from_bytes(b"\x09key=value\x03tls")?;
+    /// assert_eq!(txt.iter_string().count(), 2);
+    /// assert!(txt.has_flag("tls"));
+    /// assert_eq!(txt, async_zeroconf::TxtRecord::from_bytes(&txt.to_bytes()?)?);
+    ///
+    /// assert!(async_zeroconf::TxtRecord::from_bytes(b"\x09key").is_err());
+    /// # Ok::<(), async_zeroconf::ZeroconfError>(())
+    /// ```
+    /// [rfc]: https://www.rfc-editor.org/rfc/rfc6763#section-6
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ZeroconfError> {
+        let (entries, truncated) = split_strings(bytes);
+        match truncated {
+            Some(rest) => Err(ZeroconfError::InvalidTxtRecord(
+                String::from_utf8_lossy(rest).into_owned(),
+            )),
+            None => Ok(TxtRecord::from_entries(entries)),
+        }
+    }
+
+    // Decode a TXT record received from the network, keeping the entries
+    // before a string that extends beyond the end of `bytes` rather than
+    // failing, as a peer's record may be truncated
+    pub(crate) fn from_bytes_lossy(bytes: &[u8]) -> Self {
+        let (entries, truncated) = split_strings(bytes);
+        if let Some(rest) = truncated {
+            log::warn!(
+                "Ignoring truncated TXT record string \"{}\"",
+                String::from_utf8_lossy(rest)
+            );
+        }
+        TxtRecord::from_entries(entries)
+    }
+
+    /// Create a TXT record collection from the strings in a TXT record, each
+    /// of the form `key=value`
+    pub(crate) fn from_entries<'a, I>(entries: I) -> Self
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+    {
+        let mut txt = TxtRecord::new();
+        for entry in entries.into_iter().filter(|e| !e.is_empty()) {
+            let (key, value) = match entry.iter().position(|&c| c == b'=') {
+                Some(i) => (&entry[..i], Some(entry[i + 1..].to_vec())),
+                None => (entry, None),
+            };
+            // Strings without a key are ignored, as are any repeated keys
+            let key = String::from_utf8_lossy(key).into_owned();
+            if !key.is_empty() && !txt.contains_key(&key) {
+                txt.records.push((key, value));
+            }
+        }
+        txt
+    }
+
+    /// Get the strings for a TXT record, each of the form `key=value`, or
+    /// `key` for a flag
+    #[cfg(feature = "avahi")]
+    pub(crate) fn entries(&self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        self.iter().map(|(k, v)| entry(k, v))
+    }
+
     /// Empty if no records are associated
     pub fn is_empty(&self) -> bool {
         self.records.is_empty()
     }
 }
 
+// Split the wire format of a TXT record into its length-prefixed strings,
+// also returning the remaining bytes if the last string is truncated
+fn split_strings(bytes: &[u8]) -> (Vec<&[u8]>, Option<&[u8]>) {
+    let mut strings = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let end = pos + 1 + bytes[pos] as usize;
+        match bytes.get(pos + 1..end) {
+            Some(string) => strings.push(string),
+            None => return (strings, Some(&bytes[pos + 1..])),
+        }
+        pos = end;
+    }
+    (strings, None)
+}
+
+// Format an entry as a string of the form `key=value`, or `key` for a flag
+fn entry(k: &str, v: Option<&Vec<u8>>) -> Vec<u8> {
+    let mut entry = k.as_bytes().to_vec();
+    if let Some(v) = v {
+        entry.push(b'=');
+        entry.extend_from_slice(v);
+    }
+    entry
+}
+
+// Error for an entry that can't be published
+fn invalid(k: &str, v: Option<&Vec<u8>>) -> ZeroconfError {
+    ZeroconfError::InvalidTxtRecord(String::from_utf8_lossy(&entry(k, v)).into_owned())
+}
+
+impl PartialEq for TxtRecord {
+    fn eq(&self, other: &Self) -> bool {
+        self.records.len() == other.records.len()
+            && self
+                .records
+                .iter()
+                .zip(&other.records)
+                .all(|((k1, v1), (k2, v2))| k1.eq_ignore_ascii_case(k2) && v1 == v2)
+    }
+}
+
+impl Eq for TxtRecord {}
+
 impl Default for TxtRecord {
     fn default() -> Self {
         Self::new()
@@ -146,7 +371,10 @@ impl fmt::Display for TxtRecord {
             if !first {
                 string.push_str(", ");
             }
-            string.push_str(format!("\"{}\": \"{}\"", k, v).as_str());
+            match v {
+                Some(v) => string.push_str(format!("\"{}\": \"{}\"", k, v).as_str()),
+                None => string.push_str(format!("\"{}\"", k).as_str()),
+            }
             first = false;
         }
         string.push('}');